@@ -0,0 +1,81 @@
+use space_ship_builder_v8::math::rotation::Rot;
+use space_ship_builder_v8::rules::manifest::RuleManifest;
+use space_ship_builder_v8::rules::{Rules, RULE_MANIFEST_PATH};
+use space_ship_builder_v8::world::data::node::NodeID;
+use space_ship_builder_v8::world::data::voxel_loader::VoxelLoader;
+use std::time::{Duration, Instant};
+
+const VOX_FILE_PATH: &str = "./assets/space_ship.vox";
+const RUNS: u32 = 3;
+
+fn bench(name: &str, mut f: impl FnMut() -> Duration) {
+    let mut total = Duration::ZERO;
+    for _ in 0..RUNS {
+        total += f();
+    }
+
+    println!("{name}: {:.3} sec", (total / RUNS).as_secs_f32());
+}
+
+/// The comparison against every node under every rotation add_node did before.
+fn find_node_linear(rules: &Rules, node_id: NodeID) -> Option<NodeID> {
+    let node = &rules.nodes[node_id.index];
+    let rots = Rot::IDENTITY.get_all_permutations();
+
+    let mut id = None;
+    for (i, test_node) in rules.nodes.iter().enumerate() {
+        for test_rot in rots.iter() {
+            if node.is_duplicate_node_id(node_id.rot, test_node, *test_rot) {
+                id = Some(NodeID::new(i, *test_rot));
+            }
+        }
+    }
+
+    id
+}
+
+fn main() {
+    let voxel_loader = VoxelLoader::new(VOX_FILE_PATH).unwrap();
+    let manifest = RuleManifest::load(RULE_MANIFEST_PATH).unwrap();
+
+    let mut rules = None;
+    bench("rules_new", || {
+        let start = Instant::now();
+        rules = Some(Rules::new(&voxel_loader, &manifest).unwrap());
+        start.elapsed()
+    });
+    let mut rules = rules.unwrap();
+    println!("{} nodes", rules.nodes.len());
+
+    // Every node under every rotation is a duplicate of a node in the rules.
+    let node_ids: Vec<_> = (0..rules.nodes.len())
+        .flat_map(|i| {
+            Rot::IDENTITY
+                .get_all_permutations()
+                .into_iter()
+                .map(move |rot| NodeID::new(i, rot))
+        })
+        .collect();
+
+    let mut hashed_ids = vec![];
+    bench("add_node", || {
+        let start = Instant::now();
+        hashed_ids = node_ids
+            .iter()
+            .map(|node_id| rules.add_node(rules.nodes[node_id.index], node_id.rot))
+            .collect();
+        start.elapsed()
+    });
+
+    let mut linear_ids = vec![];
+    bench("add_node_linear", || {
+        let start = Instant::now();
+        linear_ids = node_ids
+            .iter()
+            .map(|node_id| find_node_linear(&rules, *node_id).unwrap())
+            .collect();
+        start.elapsed()
+    });
+
+    assert_eq!(hashed_ids, linear_ids);
+}
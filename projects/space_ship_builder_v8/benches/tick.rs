@@ -0,0 +1,72 @@
+use glam::{vec3, Mat4};
+use space_ship_builder_v8::rules::manifest::RuleManifest;
+use space_ship_builder_v8::rules::{Rules, RULE_MANIFEST_PATH};
+use space_ship_builder_v8::world::asteroid::AsteroidGenerator;
+use space_ship_builder_v8::world::block_object::BlockObject;
+use space_ship_builder_v8::world::data::voxel_loader::VoxelLoader;
+use std::time::{Duration, Instant};
+
+const VOX_FILE_PATH: &str = "./assets/space_ship.vox";
+const ASTEROID_SIZE: i32 = 11;
+const SEED: u64 = 1;
+const RUNS: u32 = 3;
+
+fn new_asteroid(generator: &AsteroidGenerator) -> BlockObject {
+    let mut rng = fastrand::Rng::with_seed(SEED);
+    generator.generate(
+        Mat4::from_translation(vec3(50.0, 0.0, 0.0)),
+        ASTEROID_SIZE,
+        &mut rng,
+    )
+}
+
+fn bench(name: &str, mut f: impl FnMut() -> Duration) {
+    let mut total = Duration::ZERO;
+    for _ in 0..RUNS {
+        total += f();
+    }
+
+    println!("{name}: {:.3} sec", (total / RUNS).as_secs_f32());
+}
+
+fn main() {
+    let voxel_loader = VoxelLoader::new(VOX_FILE_PATH).unwrap();
+    let rules = Rules::new(
+        &voxel_loader,
+        &RuleManifest::load(RULE_MANIFEST_PATH).unwrap(),
+    )
+    .unwrap();
+    let generator = AsteroidGenerator::new(&rules).unwrap();
+
+    let mut single_bits = vec![];
+    bench("tick", || {
+        let mut asteroid = new_asteroid(&generator);
+        let start = Instant::now();
+        asteroid.tick(usize::MAX, &rules);
+        let time = start.elapsed();
+
+        single_bits = asteroid
+            .chunks
+            .into_iter()
+            .map(|c| c.node_id_bits)
+            .collect();
+        time
+    });
+
+    let mut parallel_bits = vec![];
+    bench("tick_parallel", || {
+        let mut asteroid = new_asteroid(&generator);
+        let start = Instant::now();
+        asteroid.tick_parallel(usize::MAX, &rules);
+        let time = start.elapsed();
+
+        parallel_bits = asteroid
+            .chunks
+            .into_iter()
+            .map(|c| c.node_id_bits)
+            .collect();
+        time
+    });
+
+    assert_eq!(single_bits, parallel_bits);
+}
@@ -0,0 +1,31 @@
+use anyhow::Result;
+use space_ship_builder_v8::rules::manifest::RuleManifest;
+use space_ship_builder_v8::rules::registry::SolverRegistry;
+use space_ship_builder_v8::rules::validation::RuleReport;
+use space_ship_builder_v8::rules::RULE_MANIFEST_PATH;
+use space_ship_builder_v8::world::data::voxel_loader::VoxelLoader;
+use std::env;
+use std::process::ExitCode;
+
+const VOX_FILE_PATH: &str = "./assets/space_ship.vox";
+
+/// Prints every problem of the rules in a .vox file.
+/// Usage: lint_rules [vox file] [rule manifest]
+/// Custom solvers are not registered here, so they are reported as solver errors.
+fn main() -> Result<ExitCode> {
+    let mut args = env::args().skip(1);
+    let vox_path = args.next().unwrap_or(VOX_FILE_PATH.to_owned());
+    let manifest_path = args.next().unwrap_or(RULE_MANIFEST_PATH.to_owned());
+
+    let voxel_loader = VoxelLoader::new(&vox_path)?;
+    let manifest = RuleManifest::load(&manifest_path)?;
+    let report = RuleReport::new(&voxel_loader, &manifest, &SolverRegistry::new());
+
+    println!("{report}");
+
+    if report.is_empty() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
@@ -171,9 +171,15 @@ impl DebugController {
             render_nodes[node_index_plus_padding] = RenderNode(true);
         }
 
-        for (req_pos, req_blocks) in reqs {
+        for (req_pos, req_blocks, _) in reqs {
             let pos = middle_pos + *req_pos * 2;
 
+            // Reqs of other block names have no nodes to show.
+            if req_blocks.is_empty() {
+                self.add_cube(pos.as_vec3(), (pos + 2).as_vec3(), vec4(0.0, 0.0, 1.0, 1.0));
+                continue;
+            }
+
             let req_index = self.hull_multi_renderer.req_index % req_blocks.len();
             if req_blocks[req_index] == Block::from_single_node_id(NodeID::empty()) {
                 self.add_cube(pos.as_vec3(), (pos + 2).as_vec3(), vec4(0.0, 1.0, 0.0, 1.0));
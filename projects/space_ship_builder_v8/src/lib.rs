@@ -0,0 +1,11 @@
+use std::time::Duration;
+
+#[cfg(all(debug_assertions, feature = "render"))]
+pub mod debug;
+pub mod math;
+#[cfg(feature = "render")]
+pub mod render;
+pub mod rules;
+pub mod world;
+
+pub const INPUT_INTERVALL: Duration = Duration::from_secs(1);
@@ -1,6 +1,6 @@
+use std::path::Path;
 use std::time::Duration;
 
-use crate::rules::Rules;
 use octa_force::egui_winit::winit::event::WindowEvent;
 use octa_force::vulkan::ash::vk::{self, Format};
 use octa_force::{
@@ -12,28 +12,26 @@ use octa_force::{
 use octa_force::{log, App, BaseApp};
 
 #[cfg(debug_assertions)]
-use crate::debug::{DebugController, DebugMode::Off};
-use crate::render::parallax::renderer::ParallaxRenderer;
-use crate::render::Renderer;
-use crate::world::data::voxel_loader::VoxelLoader;
-use crate::world::manager::WorldManager;
-
-#[cfg(debug_assertions)]
-pub mod debug;
-pub mod math;
-pub mod render;
-pub mod rules;
-pub mod world;
+use space_ship_builder_v8::debug::{DebugController, DebugMode::Off};
+use space_ship_builder_v8::render::parallax::renderer::ParallaxRenderer;
+use space_ship_builder_v8::render::Renderer;
+use space_ship_builder_v8::rules::manifest::RuleManifest;
+use space_ship_builder_v8::rules::{Rules, RULE_CACHE_DIR, RULE_MANIFEST_PATH};
+use space_ship_builder_v8::world::data::file_watcher::FileWatcher;
+use space_ship_builder_v8::world::data::voxel_loader::VoxelLoader;
+use space_ship_builder_v8::world::manager::WorldManager;
+use space_ship_builder_v8::INPUT_INTERVALL;
 
 const WIDTH: u32 = 1280; // 2200;
 const HEIGHT: u32 = 720; // 1250;
 const APP_NAME: &str = "Space ship builder";
-const INPUT_INTERVALL: Duration = Duration::from_secs(1);
 
 const VOX_FILE_PATH: &str = "./assets/space_ship.vox";
 
 const COMPUTE_RENDERER: bool = false;
 
+const RULE_WATCH_INTERVALL: Duration = Duration::from_secs(1);
+
 fn main() -> Result<()> {
     octa_force::run::<SpaceShipBuilder>(EngineConfig {
         name: APP_NAME.to_string(),
@@ -60,13 +58,44 @@ struct SpaceShipBuilder {
 
     #[cfg(debug_assertions)]
     debug_controller: DebugController,
+
+    rule_watcher: FileWatcher,
+}
+
+impl SpaceShipBuilder {
+    fn reload_rules(&mut self, base: &mut BaseApp<Self>) -> Result<()> {
+        log::info!("reloading .vox File");
+        let voxel_loader = VoxelLoader::new(VOX_FILE_PATH)?;
+        let manifest = RuleManifest::load(RULE_MANIFEST_PATH)?;
+        let rules = Rules::new_cached(&voxel_loader, &manifest, Path::new(RULE_CACHE_DIR))?;
+
+        let world_change = self
+            .world_manager
+            .prepare_rules_change(&self.rules, &rules)?;
+        let rule_buffers = self.renderer.create_rule_buffers(&rules, &base.context)?;
+
+        // Nothing fails from here on, so the world and the renderer always use the same rules.
+        self.world_manager.on_rules_changed(world_change);
+        self.renderer
+            .on_rules_changed(rule_buffers, base.num_frames);
+
+        self.voxel_loader = voxel_loader;
+        self.rules = rules;
+
+        log::info!(".vox File loaded");
+        Ok(())
+    }
 }
 
 impl App for SpaceShipBuilder {
     fn new(base: &mut BaseApp<Self>) -> Result<Self> {
         let voxel_loader = VoxelLoader::new(VOX_FILE_PATH)?;
 
-        let mut rules = Rules::new(&voxel_loader)?;
+        let mut rules = Rules::new_cached(
+            &voxel_loader,
+            &RuleManifest::load(RULE_MANIFEST_PATH)?,
+            Path::new(RULE_CACHE_DIR),
+        )?;
 
         let mut renderer = Renderer::new();
         renderer.enable_parallax(
@@ -86,8 +115,9 @@ impl App for SpaceShipBuilder {
             )?;
         }
 
-        let mut world_manager = WorldManager::new(16, &mut rules);
-        world_manager.add_start_data(&rules);
+        let mut world_manager = WorldManager::new(16, &mut rules)?;
+        // The camera starts in region zero, so it is loaded first.
+        world_manager.update_regions(Vec3::ONE, &rules);
 
         #[cfg(debug_assertions)]
         let test_node_id = rules.load_node("Test", &voxel_loader).unwrap();
@@ -126,6 +156,11 @@ impl App for SpaceShipBuilder {
 
             #[cfg(debug_assertions)]
             debug_controller,
+
+            rule_watcher: FileWatcher::new(
+                &[VOX_FILE_PATH, RULE_MANIFEST_PATH],
+                RULE_WATCH_INTERVALL,
+            ),
         })
     }
 
@@ -141,17 +176,16 @@ impl App for SpaceShipBuilder {
         self.renderer
             .update(&self.camera, base.swapchain.size, frame_index)?;
 
-        if base.controls.q && self.last_input + INPUT_INTERVALL < self.total_time {
+        let reload_pressed = base.controls.q && self.last_input + INPUT_INTERVALL < self.total_time;
+        if reload_pressed {
             self.last_input = self.total_time;
+        }
 
-            log::info!("reloading .vox File");
-            self.voxel_loader.reload()?;
-            self.rules = Rules::new(&self.voxel_loader)?;
-
-            self.renderer
-                .on_rules_changed(&mut self.rules, &base.context, base.num_frames)?;
-
-            log::info!(".vox File loaded");
+        if reload_pressed || self.rule_watcher.poll() {
+            // A half written or broken file keeps the old rules.
+            if let Err(err) = self.reload_rules(base) {
+                log::error!("Reloading the rules failed: {err}");
+            }
         }
 
         #[cfg(debug_assertions)]
@@ -160,7 +194,6 @@ impl App for SpaceShipBuilder {
                 self.world_manager.update(
                     &mut self.rules,
                     self.total_time,
-                    delta_time,
                     base.num_frames,
                     frame_index,
                     &base.context,
@@ -187,7 +220,6 @@ impl App for SpaceShipBuilder {
         self.world_manager.update(
             &mut self.rules,
             self.total_time,
-            delta_time,
             base.num_frames,
             frame_index,
             &base.context,
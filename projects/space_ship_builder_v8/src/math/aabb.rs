@@ -1,4 +1,4 @@
-use octa_force::glam::{vec3, Mat4, UVec3, Vec3};
+use glam::{vec3, Mat4, UVec3, Vec3};
 
 pub fn get_aabb_of_transformed_cube(transform: Mat4, cube_size: Vec3) -> (Vec3, Vec3) {
     let corners = [
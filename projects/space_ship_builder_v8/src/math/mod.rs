@@ -1,4 +1,4 @@
-use octa_force::glam::{ivec3, uvec3, BVec3, IVec3, UVec3};
+use glam::{ivec3, uvec3, BVec3, IVec3, UVec3};
 use std::iter;
 
 pub mod aabb;
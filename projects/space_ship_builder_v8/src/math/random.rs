@@ -1,13 +1,42 @@
-use octa_force::glam::{vec3, Vec3};
+use fastrand::Rng;
+use glam::{vec3, IVec3, Vec3};
 
-pub fn get_random_vec3_from_min_max(min: Vec3, max: Vec3) -> Vec3 {
-    get_random_vec3_from_min_size(min, max - min)
+pub fn get_random_vec3_from_min_max(min: Vec3, max: Vec3, rng: &mut Rng) -> Vec3 {
+    get_random_vec3_from_min_size(min, max - min, rng)
 }
 
-pub fn get_random_vec3_from_min_size(min: Vec3, size: Vec3) -> Vec3 {
+pub fn get_random_vec3_from_min_size(min: Vec3, size: Vec3, rng: &mut Rng) -> Vec3 {
     vec3(
-        fastrand::f32() * size.x + min.x,
-        fastrand::f32() * size.y + min.y,
-        fastrand::f32() * size.z + min.z,
+        rng.f32() * size.x + min.x,
+        rng.f32() * size.y + min.y,
+        rng.f32() * size.z + min.z,
     )
 }
+
+/// Deterministic hash of a seed, a position and a value (splitmix64).
+/// The result only depends on the inputs, not on the order it is called in,
+/// so it can be used to break ties independent of the edit history.
+pub fn get_seeded_value(seed: u64, pos: IVec3, value: usize) -> u64 {
+    let mut x = seed;
+    for v in [
+        pos.x as u32 as u64,
+        pos.y as u32 as u64,
+        pos.z as u32 as u64,
+        value as u64,
+    ] {
+        x = x.wrapping_add(v).wrapping_add(0x9E3779B97F4A7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+        x ^= x >> 31;
+    }
+
+    x
+}
+
+/// get_seeded_value for a weighted choice: of several values the one with the smallest result is
+/// picked with a probability proportional to its weight (exponential race). With equal weights
+/// the order is the same as of get_seeded_value.
+pub fn get_weighted_seeded_value(seed: u64, pos: IVec3, value: usize, weight: u32) -> f64 {
+    let u = get_seeded_value(seed, pos, value) as f64 / (u64::MAX as f64 + 1.0);
+    -(1.0 - u).ln() / weight as f64
+}
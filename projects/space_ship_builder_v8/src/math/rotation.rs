@@ -1,12 +1,10 @@
 use std::{collections::HashMap, f32::consts::PI};
 
 use crate::math::all_bvec3s;
-use octa_force::anyhow::bail;
-use octa_force::glam::{ivec3, IVec3};
-use octa_force::{
-    glam::{vec3, BVec3, Mat3, Mat4, Quat, Vec3},
-    log,
-};
+use anyhow::bail;
+use bitcode::{Decode, Encode};
+use glam::{ivec3, IVec3};
+use glam::{vec3, BVec3, Mat3, Mat4, Quat, Vec3};
 
 /// Origanl from https://docs.rs/dot_vox/latest/dot_vox/struct.Rotation.html
 ///
@@ -36,7 +34,7 @@ use octa_force::{
 /// [`Signed Permutation Matrix`]: https://en.wikipedia.org/wiki/Generalized_permutation_matrix#Signed_permutation_group
 /// [ROTATION]: https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox-extension.txt#L24
 /// [^note]: A [`Signed Permutation Matrix`] is a square binary matrix that has exactly one entry of ±1 in each row and each column and 0s elsewhere.
-#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Encode, Decode)]
 pub struct Rot(u8);
 
 impl Rot {
@@ -156,7 +154,7 @@ impl Rot {
             if flip.y { -1.0 } else { 1.0 },
             if flip.z { -1.0 } else { 1.0 },
         );
-        let quat = Quat::from_euler(octa_force::glam::EulerRot::XYZ, r.x, r.y, r.z);
+        let quat = Quat::from_euler(glam::EulerRot::XYZ, r.x, r.y, r.z);
         let trans_rot: Rot = Mat3::from_mat4(Mat4::from_scale_rotation_translation(
             scale,
             quat,
@@ -261,6 +259,65 @@ impl Rot {
         rot
     }
 
+    /// All 48 signed permutation matrices.
+    pub fn all() -> Vec<Rot> {
+        (0..(1u8 << 7))
+            .filter_map(|byte| Rot::try_from(byte).ok())
+            .collect()
+    }
+
+    /// The 24 rotations that do not mirror.
+    pub fn all_proper() -> Vec<Rot> {
+        Self::all()
+            .into_iter()
+            .filter(|rot| rot.is_proper())
+            .collect()
+    }
+
+    /// The 24 rotations that mirror.
+    pub fn all_reflections() -> Vec<Rot> {
+        Self::all()
+            .into_iter()
+            .filter(|rot| !rot.is_proper())
+            .collect()
+    }
+
+    /// 1 for proper rotations and -1 for reflections.
+    pub fn determinant(self) -> i32 {
+        let index_nz1 = self.0 & 0b11;
+        let index_nz2 = (self.0 >> 2) & 0b11;
+
+        // (x, y, z), (y, z, x) and (z, x, y) are the even permutations.
+        let is_even = (index_nz2 + 3 - index_nz1) % 3 == 1;
+        let is_sign_even = ((self.0 >> 4) & 0b111).count_ones() % 2 == 0;
+
+        if is_even == is_sign_even {
+            1
+        } else {
+            -1
+        }
+    }
+
+    pub fn is_proper(self) -> bool {
+        self.determinant() == 1
+    }
+
+    /// Integer-only transpose, which is the inverse of a signed permutation matrix.
+    pub fn inverse(self) -> Rot {
+        let mut rows = [self.0 & 0b11, (self.0 >> 2) & 0b11, 0];
+        rows[2] = 3 - rows[0] - rows[1];
+        let signs = self.0 >> 4;
+
+        let mut inverse_rows = [0; 3];
+        let mut inverse_signs = 0;
+        for (i, row) in rows.into_iter().enumerate() {
+            inverse_rows[row as usize] = i as u8;
+            inverse_signs |= ((signs >> i) & 1) << row;
+        }
+
+        Rot(inverse_rows[0] | (inverse_rows[1] << 2) | (inverse_signs << 4))
+    }
+
     pub fn rot_offset(&self) -> IVec3 {
         let rot_bits: u8 = self.0;
         ivec3(
@@ -272,7 +329,7 @@ impl Rot {
 }
 
 impl TryFrom<u8> for Rot {
-    type Error = octa_force::anyhow::Error;
+    type Error = anyhow::Error;
 
     fn try_from(byte: u8) -> Result<Self, Self::Error> {
         let index_nz1 = byte & 0b11;
@@ -418,6 +475,22 @@ impl std::ops::Mul<Rot> for Rot {
     }
 }
 
+impl std::ops::Mul<IVec3> for Rot {
+    type Output = IVec3;
+
+    /// Integer-only transform of a vector, the same as with the Mat3 of the rotation.
+    fn mul(self, rhs: IVec3) -> IVec3 {
+        let mut rows = [self.0 & 0b11, (self.0 >> 2) & 0b11, 0];
+        rows[2] = 3 - rows[0] - rows[1];
+
+        let v = rhs.to_array();
+        IVec3::from(std::array::from_fn(|i| {
+            let sign = if self.0 & (1 << (4 + i)) == 0 { 1 } else { -1 };
+            sign * v[rows[i] as usize]
+        }))
+    }
+}
+
 impl Default for Rot {
     fn default() -> Self {
         Self::IDENTITY
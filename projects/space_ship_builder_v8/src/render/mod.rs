@@ -1,5 +1,5 @@
 use crate::render::compute_raytracing::renderer::ComputeRaytracingRenderer;
-use crate::render::parallax::renderer::ParallaxRenderer;
+use crate::render::parallax::renderer::{ParallaxRenderer, RuleBuffers};
 use crate::rules::Rules;
 use crate::world::block_object::{BlockChunk, BlockObject, ChunkIndex};
 use crate::world::manager::WorldManager;
@@ -159,23 +159,30 @@ impl Renderer {
         Ok(())
     }
 
-    pub fn on_rules_changed(
-        &mut self,
+    /// Creates the gpu data of the new rules without changing anything.
+    pub fn create_rule_buffers(
+        &self,
         rules: &Rules,
         context: &Context,
-        num_frames: usize,
-    ) -> Result<()> {
+    ) -> Result<Option<RuleBuffers>> {
+        match self.active_renderer {
+            ActiveRenderer::Parallax => {
+                Ok(Some(ParallaxRenderer::create_rule_buffers(context, rules)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn on_rules_changed(&mut self, rule_buffers: Option<RuleBuffers>, num_frames: usize) {
         match self.active_renderer {
             ActiveRenderer::None => {}
             ActiveRenderer::Parallax => {
                 let renderer = self.parallax_renderer.as_mut().unwrap();
-                renderer.on_rules_changed(rules, context, num_frames)?;
+                renderer.on_rules_changed(rule_buffers.unwrap(), num_frames);
             }
             ActiveRenderer::ComputeRaytracer => {}
             ActiveRenderer::Raytracing => {}
         }
-
-        Ok(())
     }
 
     pub fn on_recreate_swapchain(
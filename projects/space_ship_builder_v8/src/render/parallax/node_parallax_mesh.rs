@@ -1,8 +1,5 @@
 use block_mesh::ndshape::ConstShape3u32;
-use block_mesh::{
-    greedy_quads, Axis, AxisPermutation, GreedyQuadsBuffer, MergeVoxel, OrientedBlockFace,
-    QuadCoordinateConfig, Voxel, VoxelVisibility,
-};
+use block_mesh::{greedy_quads, GreedyQuadsBuffer, MergeVoxel, Voxel, VoxelVisibility};
 use log::error;
 use octa_force::anyhow::bail;
 use octa_force::egui::emath::Numeric;
@@ -21,6 +18,7 @@ use std::mem::size_of;
 use std::{iter, mem};
 
 use crate::render::parallax::renderer::Vertex;
+use crate::world::export::mesh::RIGHT_HANDED_Z_UP_CONFIG;
 
 pub const MIN_VERTICES: usize = 8;
 pub const MIN_INDICES: usize = 20;
@@ -130,20 +128,6 @@ impl NodeParallaxMesh {
         Ok(())
     }
 
-    pub const RIGHT_HANDED_Z_UP_CONFIG: QuadCoordinateConfig = QuadCoordinateConfig {
-        // Y is always in the V direction when it's not the normal. When Y is the
-        // normal, right-handedness determines that we must use Yzx permutations.
-        faces: [
-            OrientedBlockFace::new(-1, AxisPermutation::Xzy),
-            OrientedBlockFace::new(-1, AxisPermutation::Zxy),
-            OrientedBlockFace::new(-1, AxisPermutation::Yzx),
-            OrientedBlockFace::new(1, AxisPermutation::Xzy),
-            OrientedBlockFace::new(1, AxisPermutation::Zxy),
-            OrientedBlockFace::new(1, AxisPermutation::Yzx),
-        ],
-        u_flip_face: Axis::X,
-    };
-
     fn run_greedy<const SIZE: u32>(render_nodes: &[RenderNode], buffer: &mut GreedyQuadsBuffer) {
         let shape: ConstShape3u32<SIZE, SIZE, SIZE> = ConstShape3u32 {};
 
@@ -152,7 +136,7 @@ impl NodeParallaxMesh {
             &shape,
             [0; 3],
             [SIZE - 1; 3],
-            &Self::RIGHT_HANDED_Z_UP_CONFIG.faces,
+            &RIGHT_HANDED_Z_UP_CONFIG.faces,
             buffer,
         );
     }
@@ -188,7 +172,7 @@ impl NodeParallaxMesh {
             .quads
             .groups
             .iter()
-            .zip(Self::RIGHT_HANDED_Z_UP_CONFIG.faces.iter())
+            .zip(RIGHT_HANDED_Z_UP_CONFIG.faces.iter())
             .for_each(|(group, of)| {
                 group.iter().for_each(|uf| {
                     vertecies.extend(
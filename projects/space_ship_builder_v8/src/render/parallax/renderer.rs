@@ -41,6 +41,12 @@ pub struct ParallaxRenderer {
     pub to_drop_buffers: Vec<Vec<Buffer>>,
 }
 
+/// The buffers made from the rules.
+pub struct RuleBuffers {
+    pub node_buffer: Buffer,
+    pub mat_buffer: Buffer,
+}
+
 #[derive(Debug, Clone, Copy)]
 #[allow(dead_code)]
 #[repr(C)]
@@ -352,26 +358,32 @@ impl ParallaxRenderer {
         buffer.end_rendering()
     }
 
-    pub fn on_rules_changed(
-        &mut self,
-        rules: &Rules,
-        context: &Context,
-        num_frames: usize,
-    ) -> Result<()> {
+    /// Only creates the buffers, so a failed reload keeps the old rules.
+    pub fn create_rule_buffers(context: &Context, rules: &Rules) -> Result<RuleBuffers> {
         let node_buffer_size = rules.nodes.len() * size_of::<Node>();
         log::info!(
             "Node Buffer Size: {:?} MB",
             node_buffer_size as f32 / 1000000.0
         );
 
-        self.node_buffer = context
+        let node_buffer = context
             .create_gpu_only_buffer_from_data(vk::BufferUsageFlags::STORAGE_BUFFER, &rules.nodes)?;
 
-        self.mat_buffer = context.create_gpu_only_buffer_from_data(
+        let mat_buffer = context.create_gpu_only_buffer_from_data(
             vk::BufferUsageFlags::STORAGE_BUFFER,
             &rules.materials,
         )?;
 
+        Ok(RuleBuffers {
+            node_buffer,
+            mat_buffer,
+        })
+    }
+
+    pub fn on_rules_changed(&mut self, rule_buffers: RuleBuffers, num_frames: usize) {
+        self.node_buffer = rule_buffers.node_buffer;
+        self.mat_buffer = rule_buffers.mat_buffer;
+
         for i in 0..num_frames {
             self.static_descriptor_sets[i].update(&[
                 WriteDescriptorSet {
@@ -394,8 +406,6 @@ impl ParallaxRenderer {
                 },
             ]);
         }
-
-        Ok(())
     }
 }
 
@@ -1,25 +1,27 @@
 use crate::math::rotation::Rot;
 use crate::math::{get_neighbors_without_zero, oct_positions};
 use crate::rules::empty::EMPTY_BLOCK_NAME_INDEX;
-use crate::rules::req_tree::BroadReqTree;
+use crate::rules::manifest::FolderDefinition;
+use crate::rules::req_tree::{BroadReqTree, ReqTreeEntry};
 use crate::rules::solver::SolverCacheIndex;
-use crate::rules::{
-    Prio, Rules, BLOCK_MODEL_IDENTIFIER, BLOCK_TYPE_IDENTIFIER, FOLDER_MODEL_IDENTIFIER,
-    REQ_TYPE_IDENTIFIER,
-};
+use crate::rules::{parse_req_folder_model_name, Prio, ReqFolderModel, Rules};
 use crate::world::block_object::BlockObject;
 use crate::world::data::block::{Block, BlockIndex, BlockNameIndex};
 use crate::world::data::node::NodeID;
 use crate::world::data::voxel_loader::VoxelLoader;
+use anyhow::{bail, Result};
+use glam::{IVec3, Mat4};
 use log::{debug, info};
-use octa_force::anyhow::{bail, Result};
-use octa_force::glam::{IVec3, Mat4};
+#[cfg(feature = "render")]
 use octa_force::puffin_egui::puffin;
 use std::ops::Mul;
 
 #[derive(Clone, Debug)]
 pub struct BasicBlocks {
-    blocks: Vec<(Vec<(IVec3, BlockNameIndex)>, Block, Prio)>,
+    pub blocks: Vec<(Vec<(IVec3, BlockNameIndex)>, Block, Prio)>,
+    /// The weight of each block in blocks.
+    pub weights: Vec<u32>,
+    pub req_tree: BroadReqTree,
 
     #[cfg(debug_assertions)]
     pub debug_basic_blocks: Vec<(Vec<(IVec3, BlockNameIndex)>, Block, Prio)>,
@@ -29,19 +31,16 @@ impl BasicBlocks {
     pub fn new(
         rules: &mut Rules,
         voxel_loader: &VoxelLoader,
-        folder_name_part: &str,
-        folder_amount: usize,
+        folders: &[FolderDefinition],
     ) -> Result<Self> {
         let mut basic_blocks: Vec<(Vec<(IVec3, BlockNameIndex)>, Block, Prio)> = vec![];
+        let mut weights = vec![];
 
-        for i in 0..folder_amount {
-            let (blocks, req_blocks) = load_basic_block_req_folder(
-                &format!("{folder_name_part}-{i}"),
-                voxel_loader,
-                rules,
-            )?;
+        for folder in folders {
+            let (blocks, req_blocks) =
+                load_basic_block_req_folder(&folder.name, folder.prio, voxel_loader, rules)?;
 
-            for (block, pos, prio) in blocks.to_owned().into_iter() {
+            for (block, pos, prio, weight) in blocks.to_owned().into_iter() {
                 let mut reqs = vec![];
 
                 for offset in get_neighbors_without_zero() {
@@ -54,14 +53,20 @@ impl BasicBlocks {
                     }
                 }
 
-                basic_blocks.push((reqs, block, prio))
+                basic_blocks.push((reqs, block, prio));
+                weights.push(weight);
             }
         }
 
-        let mut rotated_basic_blocks = permutate_basic_blocks(&basic_blocks, rules);
+        let (rotated_basic_blocks, rotated_weights) =
+            permutate_basic_blocks(&basic_blocks, &weights, rules);
+
+        let req_tree = BroadReqTree::new(&get_basic_block_entries(&rotated_basic_blocks), 0);
 
         Ok(BasicBlocks {
             blocks: rotated_basic_blocks,
+            weights: rotated_weights,
+            req_tree,
             #[cfg(debug_assertions)]
             debug_basic_blocks: basic_blocks,
         })
@@ -79,13 +84,40 @@ impl BasicBlocks {
         &self.blocks[index]
     }
 
+    pub fn get_weight(&self, index: usize) -> u32 {
+        self.weights[index]
+    }
+
+    /// The first block whose reqs are fulfilled.
     pub fn get_possible_blocks(
         &self,
-        block_object: &mut BlockObject,
+        block_object: &BlockObject,
         world_block_pos: IVec3,
         block_name_index: BlockNameIndex,
     ) -> Vec<SolverCacheIndex> {
-        #[cfg(debug_assertions)]
+        #[cfg(all(debug_assertions, feature = "render"))]
+        puffin::profile_function!();
+
+        let test_block_name_index =
+            block_object.get_block_name_from_world_block_pos(world_block_pos);
+        if test_block_name_index != block_name_index {
+            return vec![];
+        }
+
+        self.req_tree
+            .get_entries(block_object, world_block_pos)
+            .first()
+            .map_or(vec![], |i| vec![*i])
+    }
+
+    /// Tests every block. The req tree gives the same result.
+    pub fn get_possible_blocks_without_req_tree(
+        &self,
+        block_object: &BlockObject,
+        world_block_pos: IVec3,
+        block_name_index: BlockNameIndex,
+    ) -> Vec<SolverCacheIndex> {
+        #[cfg(all(debug_assertions, feature = "render"))]
         puffin::profile_function!();
 
         let test_block_name_index =
@@ -116,11 +148,25 @@ impl BasicBlocks {
     }
 }
 
+pub fn get_basic_block_entries(
+    blocks: &[(Vec<(IVec3, BlockNameIndex)>, Block, Prio)],
+) -> Vec<ReqTreeEntry> {
+    blocks
+        .iter()
+        .map(|(reqs, _, _)| {
+            reqs.iter()
+                .map(|(offset, block_name_index)| (*offset, vec![*block_name_index]))
+                .collect()
+        })
+        .collect()
+}
+
 fn load_basic_block_req_folder(
     folder_name: &str,
+    folder_prio: usize,
     voxel_loader: &VoxelLoader,
     rules: &mut Rules,
-) -> Result<(Vec<(Block, IVec3, Prio)>, Vec<(BlockNameIndex, IVec3)>)> {
+) -> Result<(Vec<(Block, IVec3, Prio, u32)>, Vec<(BlockNameIndex, IVec3)>)> {
     let mut blocks = vec![];
     let mut req_blocks = vec![];
 
@@ -131,32 +177,26 @@ fn load_basic_block_req_folder(
     }
 
     for (name, index, rot, pos) in models {
-        let name_parts: Vec<_> = name.split('-').collect();
-
-        if name_parts[0] == BLOCK_TYPE_IDENTIFIER {
-            let block = if name_parts[1] == BLOCK_MODEL_IDENTIFIER {
-                rules.load_block_from_block_model_by_index(index, voxel_loader)?
-            } else if name_parts[1] == FOLDER_MODEL_IDENTIFIER {
-                rules.load_block_from_node_folder(&name, voxel_loader)?
-            } else {
-                bail!("Part 1 of {name} is not identified.");
-            };
-            let block = block.rotate(rot, rules);
-
-            let prio = name_parts[2].parse::<usize>()?;
-
-            blocks.push((block, pos, Prio::Basic(prio)))
-        } else {
-            let req_block_name = name_parts[0];
-            let index = rules
-                .block_names
-                .iter()
-                .position(|block_name| block_name == req_block_name);
-            if index.is_none() {
-                bail!("{req_block_name} is not a valid Block name!");
+        match parse_req_folder_model_name(&name, true)? {
+            ReqFolderModel::Block { kind, prio, weight } => {
+                let block = rules
+                    .load_req_folder_block(kind, &name, index, voxel_loader)?
+                    .rotate(rot, rules);
+
+                blocks.push((block, pos, Prio::Basic(prio + folder_prio), weight))
             }
+            ReqFolderModel::BlockName(req_block_name) => {
+                let index = rules
+                    .block_names
+                    .iter()
+                    .position(|block_name| block_name == req_block_name);
+                if index.is_none() {
+                    bail!("{req_block_name} is not a valid Block name!");
+                }
 
-            req_blocks.push((index.unwrap() as u8, pos))
+                req_blocks.push((index.unwrap() as u8, pos))
+            }
+            ReqFolderModel::Req(_) => unreachable!(),
         }
     }
 
@@ -165,10 +205,12 @@ fn load_basic_block_req_folder(
 
 fn permutate_basic_blocks(
     blocks: &[(Vec<(IVec3, BlockNameIndex)>, Block, Prio)],
+    weights: &[u32],
     rules: &mut Rules,
-) -> Vec<(Vec<(IVec3, BlockNameIndex)>, Block, Prio)> {
+) -> (Vec<(Vec<(IVec3, BlockNameIndex)>, Block, Prio)>, Vec<u32>) {
     let mut rotated_blocks = vec![];
-    for (reqs, block, prio) in blocks.iter() {
+    let mut rotated_weights = vec![];
+    for ((reqs, block, prio), weight) in blocks.iter().zip(weights.iter()) {
         for rot in Rot::IDENTITY.get_all_permutations() {
             let mat: Mat4 = rot.into();
             let rotated_reqs: Vec<_> = reqs
@@ -194,10 +236,11 @@ fn permutate_basic_blocks(
             }
 
             if !found {
-                rotated_blocks.push((rotated_reqs, rotated_block, *prio))
+                rotated_blocks.push((rotated_reqs, rotated_block, *prio));
+                rotated_weights.push(*weight);
             }
         }
     }
 
-    rotated_blocks
+    (rotated_blocks, rotated_weights)
 }
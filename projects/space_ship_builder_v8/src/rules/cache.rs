@@ -0,0 +1,404 @@
+use crate::rules::basic_blocks::BasicBlocks;
+use crate::rules::empty::EmptySolver;
+use crate::rules::hull::{HullSolver, MultiBlockReq};
+use crate::rules::manifest::RuleManifest;
+use crate::rules::marching_cubes::{MarchingCubes, NodeReq};
+use crate::rules::req_tree::{BroadReqTree, BroadReqTreeNode, ReqTreeChild};
+use crate::rules::solver::Solver;
+use crate::rules::stone::StoneSolver;
+use crate::rules::{Prio, Rules};
+use crate::world::data::block::{Block, BlockNameIndex};
+use crate::world::data::node::{Material, Node, NodeID};
+use crate::world::data::voxel_loader::VoxelLoader;
+use crate::world::save::write_atomic;
+use anyhow::{bail, Result};
+use bitcode::{Decode, Encode};
+use glam::IVec3;
+use log::{info, warn};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Part of the key, so caches of an older format are never loaded.
+const RULE_CACHE_VERSION: u32 = 9;
+const RULE_CACHE_FILE_EXTENSION: &str = "rules";
+
+type BasicBlockCache = (Vec<([i32; 3], BlockNameIndex)>, Block, Prio);
+type MultiBlockCache = (
+    Vec<([i32; 3], Vec<Block>, Vec<BlockNameIndex>)>,
+    Block,
+    Prio,
+);
+
+/// The compiled rules. Everything Rules::new derives from the .vox file and the manifest.
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct RulesCache {
+    pub key: u64,
+    pub materials: Vec<Material>,
+    pub nodes: Vec<Node>,
+    pub duplicate_node_ids: Vec<Vec<Vec<NodeID>>>,
+    pub block_names: Vec<String>,
+    pub solvers: Vec<SolverCache>,
+    pub buildable_block_name_indices: Vec<BlockNameIndex>,
+    pub asteroid_block_name_index: Option<BlockNameIndex>,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub enum SolverCache {
+    Empty,
+    Hull {
+        block_name_index: BlockNameIndex,
+        basic_blocks: Vec<BasicBlockCache>,
+        basic_block_weights: Vec<u32>,
+        basic_req_tree: ReqTreeCache,
+        multi_blocks: Vec<MultiBlockCache>,
+        multi_block_weights: Vec<u32>,
+        multi_broad_req_tree: ReqTreeCache,
+
+        /// Only filled in debug builds.
+        debug_basic_blocks: Vec<BasicBlockCache>,
+        debug_multi_blocks: Vec<MultiBlockCache>,
+    },
+    Stone {
+        block_name_index: BlockNameIndex,
+        node_reqs: Vec<(NodeReq, NodeID)>,
+        empty_block_name_indices: Vec<BlockNameIndex>,
+        synthesized_node_reqs: Vec<NodeReq>,
+        smooth: bool,
+        voxel_masks: Vec<u64>,
+    },
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct ReqTreeCache {
+    pub root: ReqTreeChild,
+    /// (offset, children, other_child)
+    pub nodes: Vec<([i32; 3], Vec<(BlockNameIndex, ReqTreeChild)>, ReqTreeChild)>,
+    pub leafs: Vec<Vec<usize>>,
+}
+
+/// Changes when the .vox file, the frame it is loaded at, the manifest or the cache format changes.
+pub fn get_rule_cache_key(voxel_loader: &VoxelLoader, manifest: &RuleManifest) -> Result<u64> {
+    let data = fs::read(&voxel_loader.path)?;
+
+    let mut hasher = DefaultHasher::new();
+    RULE_CACHE_VERSION.hash(&mut hasher);
+    // Debug builds keep extra blocks for the debug views.
+    cfg!(debug_assertions).hash(&mut hasher);
+    data.hash(&mut hasher);
+    voxel_loader.frame.hash(&mut hasher);
+    manifest.hash(&mut hasher);
+
+    Ok(hasher.finish())
+}
+
+pub fn get_rule_cache_path(dir: &Path, key: u64) -> PathBuf {
+    dir.join(format!("{key:016x}.{RULE_CACHE_FILE_EXTENSION}"))
+}
+
+impl Rules {
+    /// Loads the rules from the cache in dir if it was made from the same .vox file and manifest.
+    /// Otherwise the rules are built and cached. A broken cache is only a warning.
+    pub fn new_cached(
+        voxel_loader: &VoxelLoader,
+        manifest: &RuleManifest,
+        dir: &Path,
+    ) -> Result<Self> {
+        let key = get_rule_cache_key(voxel_loader, manifest)?;
+        let path = get_rule_cache_path(dir, key);
+
+        if path.exists() {
+            match load_rule_cache(&path) {
+                Ok(cache) if cache.key == key => {
+                    info!("Loaded rules from {path:?}");
+                    return Ok(Rules::from_cache(cache));
+                }
+                Ok(_) => warn!("Rule cache {path:?} was made for other rules"),
+                Err(err) => warn!("Could not load rule cache {path:?}: {err}"),
+            }
+        }
+
+        let rules = Rules::new(voxel_loader, manifest)?;
+        if let Err(err) = rules.save_cache(dir, key) {
+            warn!("Could not save rule cache {path:?}: {err}");
+        }
+
+        Ok(rules)
+    }
+
+    /// Custom solvers can not be cached.
+    pub fn get_cache(&self, key: u64) -> Result<RulesCache> {
+        let mut solvers = vec![];
+        for solver in self.solvers.iter() {
+            let solver_cache = match solver {
+                Solver::Empty(_) => SolverCache::Empty,
+                Solver::Hull(hull) => SolverCache::Hull {
+                    block_name_index: hull.block_name_index,
+                    basic_blocks: hull
+                        .basic_blocks
+                        .blocks
+                        .iter()
+                        .map(get_basic_block_cache)
+                        .collect(),
+                    basic_block_weights: hull.basic_blocks.weights.to_owned(),
+                    basic_req_tree: get_req_tree_cache(&hull.basic_blocks.req_tree),
+                    multi_blocks: hull
+                        .multi_blocks
+                        .iter()
+                        .map(get_multi_block_cache)
+                        .collect(),
+                    multi_block_weights: hull.multi_block_weights.to_owned(),
+                    multi_broad_req_tree: get_req_tree_cache(&hull.multi_broad_req_tree),
+
+                    #[cfg(debug_assertions)]
+                    debug_basic_blocks: hull
+                        .basic_blocks
+                        .debug_basic_blocks
+                        .iter()
+                        .map(get_basic_block_cache)
+                        .collect(),
+                    #[cfg(not(debug_assertions))]
+                    debug_basic_blocks: vec![],
+
+                    #[cfg(debug_assertions)]
+                    debug_multi_blocks: hull
+                        .debug_multi_blocks
+                        .iter()
+                        .map(get_multi_block_cache)
+                        .collect(),
+                    #[cfg(not(debug_assertions))]
+                    debug_multi_blocks: vec![],
+                },
+                Solver::Stone(stone) => SolverCache::Stone {
+                    block_name_index: stone.block_name_index,
+                    node_reqs: stone.marching_cubes.node_reqs.to_owned(),
+                    empty_block_name_indices: stone
+                        .marching_cubes
+                        .empty_block_name_indices
+                        .to_owned(),
+                    synthesized_node_reqs: stone.marching_cubes.synthesized_node_reqs.to_owned(),
+                    smooth: stone.marching_cubes.smooth,
+                    voxel_masks: stone.marching_cubes.voxel_masks.to_owned(),
+                },
+                Solver::Custom(_) => bail!("Rules with custom solvers can not be cached"),
+            };
+            solvers.push(solver_cache);
+        }
+
+        Ok(RulesCache {
+            key,
+            materials: self.materials.to_vec(),
+            nodes: self.nodes.to_owned(),
+            duplicate_node_ids: self.duplicate_node_ids.to_owned(),
+            block_names: self.block_names.to_owned(),
+            solvers,
+            buildable_block_name_indices: self.buildable_block_name_indices.to_owned(),
+            asteroid_block_name_index: self.asteroid_block_name_index,
+        })
+    }
+
+    pub fn from_cache(cache: RulesCache) -> Self {
+        let mut materials = [Material::default(); 256];
+        for (material, cached) in materials.iter_mut().zip(cache.materials) {
+            *material = cached;
+        }
+
+        let solvers = cache
+            .solvers
+            .into_iter()
+            .map(|solver_cache| match solver_cache {
+                SolverCache::Empty => Solver::Empty(EmptySolver {}),
+                SolverCache::Hull {
+                    block_name_index,
+                    basic_blocks,
+                    basic_block_weights,
+                    basic_req_tree,
+                    multi_blocks,
+                    multi_block_weights,
+                    multi_broad_req_tree,
+                    debug_basic_blocks,
+                    debug_multi_blocks,
+                } => {
+                    #[cfg(not(debug_assertions))]
+                    let _ = (debug_basic_blocks, debug_multi_blocks);
+
+                    Solver::Hull(HullSolver {
+                        block_name_index,
+
+                        basic_blocks: BasicBlocks {
+                            blocks: basic_blocks.iter().map(from_basic_block_cache).collect(),
+                            weights: basic_block_weights,
+                            req_tree: from_req_tree_cache(basic_req_tree),
+                            #[cfg(debug_assertions)]
+                            debug_basic_blocks: debug_basic_blocks
+                                .iter()
+                                .map(from_basic_block_cache)
+                                .collect(),
+                        },
+
+                        multi_blocks: multi_blocks.iter().map(from_multi_block_cache).collect(),
+                        multi_block_weights,
+                        multi_broad_req_tree: from_req_tree_cache(multi_broad_req_tree),
+
+                        #[cfg(debug_assertions)]
+                        debug_multi_blocks: debug_multi_blocks
+                            .iter()
+                            .map(from_multi_block_cache)
+                            .collect(),
+
+                        #[cfg(debug_assertions)]
+                        use_req_tree: true,
+                    })
+                }
+                SolverCache::Stone {
+                    block_name_index,
+                    node_reqs,
+                    empty_block_name_indices,
+                    synthesized_node_reqs,
+                    smooth,
+                    voxel_masks,
+                } => Solver::Stone(StoneSolver {
+                    block_name_index,
+                    marching_cubes: MarchingCubes {
+                        block_name_index,
+                        node_reqs,
+                        empty_block_name_indices,
+                        synthesized_node_reqs,
+                        smooth,
+                        voxel_masks,
+                    },
+                }),
+            })
+            .collect();
+
+        let mut rules = Rules {
+            materials,
+            nodes: cache.nodes,
+            duplicate_node_ids: cache.duplicate_node_ids,
+            canonical_node_indices: HashMap::new(),
+            duplicate_node_id_lookup: HashMap::new(),
+            block_names: cache.block_names,
+            solvers,
+            buildable_block_name_indices: cache.buildable_block_name_indices,
+            asteroid_block_name_index: cache.asteroid_block_name_index,
+        };
+        rules.update_node_lookups();
+
+        rules
+    }
+
+    /// Caches of other keys are removed, so the cache dir does not grow with every change.
+    pub fn save_cache(&self, dir: &Path, key: u64) -> Result<()> {
+        let cache = self.get_cache(key)?;
+
+        fs::create_dir_all(dir)?;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|ext| ext == RULE_CACHE_FILE_EXTENSION)
+            {
+                fs::remove_file(path)?;
+            }
+        }
+
+        write_atomic(&get_rule_cache_path(dir, key), &bitcode::encode(&cache))
+    }
+}
+
+pub fn load_rule_cache(path: &Path) -> Result<RulesCache> {
+    let data = fs::read(path)?;
+    Ok(bitcode::decode(&data)?)
+}
+
+fn get_basic_block_cache(
+    (reqs, block, prio): &(Vec<(IVec3, BlockNameIndex)>, Block, Prio),
+) -> BasicBlockCache {
+    (
+        reqs.iter()
+            .map(|(offset, block_name_index)| ((*offset).into(), *block_name_index))
+            .collect(),
+        *block,
+        *prio,
+    )
+}
+
+fn from_basic_block_cache(
+    (reqs, block, prio): &BasicBlockCache,
+) -> (Vec<(IVec3, BlockNameIndex)>, Block, Prio) {
+    (
+        reqs.iter()
+            .map(|(offset, block_name_index)| (IVec3::from(*offset), *block_name_index))
+            .collect(),
+        *block,
+        *prio,
+    )
+}
+
+fn get_multi_block_cache(
+    (reqs, block, prio): &(Vec<MultiBlockReq>, Block, Prio),
+) -> MultiBlockCache {
+    (
+        reqs.iter()
+            .map(|(offset, blocks, block_names)| {
+                ((*offset).into(), blocks.to_owned(), block_names.to_owned())
+            })
+            .collect(),
+        *block,
+        *prio,
+    )
+}
+
+fn from_multi_block_cache(
+    (reqs, block, prio): &MultiBlockCache,
+) -> (Vec<MultiBlockReq>, Block, Prio) {
+    (
+        reqs.iter()
+            .map(|(offset, blocks, block_names)| {
+                (
+                    IVec3::from(*offset),
+                    blocks.to_owned(),
+                    block_names.to_owned(),
+                )
+            })
+            .collect(),
+        *block,
+        *prio,
+    )
+}
+
+fn get_req_tree_cache(tree: &BroadReqTree) -> ReqTreeCache {
+    ReqTreeCache {
+        root: tree.root,
+        nodes: tree
+            .nodes
+            .iter()
+            .map(|node| {
+                (
+                    node.offset.into(),
+                    node.children.to_owned(),
+                    node.other_child,
+                )
+            })
+            .collect(),
+        leafs: tree.leafs.to_owned(),
+    }
+}
+
+fn from_req_tree_cache(cache: ReqTreeCache) -> BroadReqTree {
+    BroadReqTree {
+        root: cache.root,
+        nodes: cache
+            .nodes
+            .into_iter()
+            .map(|(offset, children, other_child)| BroadReqTreeNode {
+                offset: offset.into(),
+                children,
+                other_child,
+            })
+            .collect(),
+        leafs: cache.leafs,
+    }
+}
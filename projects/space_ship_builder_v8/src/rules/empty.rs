@@ -4,24 +4,24 @@ use crate::rules::{Prio, Rules};
 use crate::world::block_object::BlockObject;
 use crate::world::data::block::{Block, BlockNameIndex};
 use crate::world::data::node::{Node, NodeID};
-use octa_force::glam::IVec3;
+use glam::IVec3;
 
 pub const EMPTY_BLOCK_NAME_INDEX: BlockNameIndex = 0;
 
 pub struct EmptySolver {}
 
 impl Rules {
-    pub fn make_empty(&mut self) {
-        self.block_names.push("Empty".to_owned());
+    pub fn make_empty(&mut self, name: &str) {
+        self.add_block_name(name);
         self.solvers.push(Solver::Empty(EmptySolver {}));
-        self.nodes.push(Node::default());
+        self.push_node(Node::default());
     }
 }
 
 impl SolverFunctions for EmptySolver {
     fn block_check_reset(
         &self,
-        _: &mut BlockObject,
+        _: &BlockObject,
         _: usize,
         _: usize,
         _: IVec3,
@@ -31,7 +31,7 @@ impl SolverFunctions for EmptySolver {
 
     fn block_check(
         &self,
-        _: &mut BlockObject,
+        _: &BlockObject,
         _: usize,
         _: usize,
         _: IVec3,
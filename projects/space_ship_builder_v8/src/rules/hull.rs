@@ -1,63 +1,68 @@
 use crate::math::get_neighbors_without_zero;
+use crate::math::random::get_weighted_seeded_value;
 use crate::math::rotation::Rot;
 use crate::rules::basic_blocks::BasicBlocks;
 use crate::rules::empty::EMPTY_BLOCK_NAME_INDEX;
-use crate::rules::req_tree::BroadReqTree;
+use crate::rules::manifest::FolderDefinition;
+use crate::rules::req_tree::{BroadReqTree, ReqTreeEntry};
 use crate::rules::solver::{Solver, SolverCacheIndex, SolverFunctions};
 use crate::rules::Prio::Multi;
-use crate::rules::{
-    Prio, Rules, BLOCK_MODEL_IDENTIFIER, BLOCK_TYPE_IDENTIFIER, FOLDER_MODEL_IDENTIFIER,
-    REQ_TYPE_IDENTIFIER,
-};
+use crate::rules::{parse_req_folder_model_name, Prio, ReqFolderModel, Rules};
 use crate::world::block_object::possible_blocks::PossibleBlocks;
 use crate::world::block_object::{BlockObject, CacheIndex};
 use crate::world::data::block::{Block, BlockNameIndex};
 use crate::world::data::node::NodeID;
 use crate::world::data::voxel_loader::VoxelLoader;
+use anyhow::bail;
+use anyhow::Result;
+use glam::{IVec3, Mat4};
 use log::{debug, info};
-use octa_force::anyhow::bail;
+#[cfg(feature = "render")]
 use octa_force::puffin_egui::puffin;
-use octa_force::{
-    anyhow::Result,
-    glam::{IVec3, Mat4},
-    run,
-};
 
 #[allow(unused)]
 const HULL_CACHE_NONE: CacheIndex = CacheIndex::MAX;
-const HULL_BLOCK_NAME: &str = "Hull";
-const HULL_BASE_NAME_PART: &str = "Hull-Base";
-const HULL_MULTI_NAME_PART: &str = "Hull-Multi";
+
+/// The blocks and block names a multi block accepts at an offset. Any of them fulfills the req.
+pub type MultiBlockReq = (IVec3, Vec<Block>, Vec<BlockNameIndex>);
 
 pub struct HullSolver {
     pub block_name_index: BlockNameIndex,
 
     pub basic_blocks: BasicBlocks,
 
-    pub multi_blocks: Vec<(Vec<(IVec3, Vec<Block>)>, Block, Prio)>,
+    pub multi_blocks: Vec<(Vec<MultiBlockReq>, Block, Prio)>,
+    /// The weight of each block in multi_blocks.
+    pub multi_block_weights: Vec<u32>,
     pub multi_broad_req_tree: BroadReqTree,
 
     #[cfg(debug_assertions)]
-    pub debug_multi_blocks: Vec<(Vec<(IVec3, Vec<Block>)>, Block, Prio)>,
+    pub debug_multi_blocks: Vec<(Vec<MultiBlockReq>, Block, Prio)>,
 
     #[cfg(debug_assertions)]
     pub use_req_tree: bool,
 }
 
 impl Rules {
-    pub fn make_hull(&mut self, voxel_loader: &VoxelLoader) -> Result<()> {
-        info!("Making Hull");
-
-        let hull_block_name_index = self.block_names.len() as BlockNameIndex;
-        self.block_names.push(HULL_BLOCK_NAME.to_owned());
-
-        let basic_blocks = BasicBlocks::new(self, voxel_loader, HULL_BASE_NAME_PART, 1)?;
+    pub fn make_hull(
+        &mut self,
+        name: &str,
+        basic_folders: &[FolderDefinition],
+        multi_folders: &[FolderDefinition],
+        voxel_loader: &VoxelLoader,
+    ) -> Result<()> {
+        info!("Making {name}");
+
+        let hull_block_name_index = self.add_block_name(name);
+
+        let basic_blocks = BasicBlocks::new(self, voxel_loader, basic_folders)?;
         let mut hull_solver = HullSolver {
             block_name_index: hull_block_name_index,
 
             basic_blocks,
 
             multi_blocks: vec![],
+            multi_block_weights: vec![],
 
             multi_broad_req_tree: BroadReqTree::default(),
             #[cfg(debug_assertions)]
@@ -68,11 +73,11 @@ impl Rules {
             use_req_tree: true,
         };
 
-        hull_solver.add_multi_blocks(self, voxel_loader)?;
+        hull_solver.add_multi_blocks(self, voxel_loader, multi_folders)?;
 
         self.solvers.push(Solver::Hull(hull_solver));
 
-        info!("Making Hull Done");
+        info!("Making {name} Done");
         Ok(())
     }
 }
@@ -80,33 +85,47 @@ impl Rules {
 impl SolverFunctions for HullSolver {
     fn block_check_reset(
         &self,
-        block_object: &mut BlockObject,
+        block_object: &BlockObject,
         _: usize,
         _: usize,
         world_block_pos: IVec3,
     ) -> Vec<SolverCacheIndex> {
-        #[cfg(debug_assertions)]
+        #[cfg(all(debug_assertions, feature = "render"))]
         puffin::profile_function!();
 
         let mut cache = vec![];
-        cache.append(&mut self.basic_blocks.get_possible_blocks(
-            block_object,
-            world_block_pos,
-            self.block_name_index,
-        ));
 
         #[cfg(debug_assertions)]
         {
             if self.use_req_tree {
+                cache.append(&mut self.basic_blocks.get_possible_blocks(
+                    block_object,
+                    world_block_pos,
+                    self.block_name_index,
+                ));
                 cache.append(
                     &mut self.get_multi_blocks_reset_with_req_tree(block_object, world_block_pos),
                 );
             } else {
+                cache.append(&mut self.basic_blocks.get_possible_blocks_without_req_tree(
+                    block_object,
+                    world_block_pos,
+                    self.block_name_index,
+                ));
                 cache.append(&mut self.get_multi_blocks_reset(block_object, world_block_pos));
             }
         }
         #[cfg(not(debug_assertions))]
-        cache.append(&mut self.get_multi_blocks_reset_with_req_tree(block_object, world_block_pos));
+        {
+            cache.append(&mut self.basic_blocks.get_possible_blocks(
+                block_object,
+                world_block_pos,
+                self.block_name_index,
+            ));
+            cache.append(
+                &mut self.get_multi_blocks_reset_with_req_tree(block_object, world_block_pos),
+            );
+        }
 
         cache
     }
@@ -123,7 +142,7 @@ impl SolverFunctions for HullSolver {
 
     fn block_check(
         &self,
-        ship: &mut BlockObject,
+        ship: &BlockObject,
         _: usize,
         _: usize,
         world_block_pos: IVec3,
@@ -170,31 +189,36 @@ impl SolverFunctions for HullSolver {
 
     fn get_block(
         &self,
-        _: &mut BlockObject,
+        block_object: &mut BlockObject,
         _: usize,
         _: usize,
-        _: IVec3,
+        world_block_pos: IVec3,
         cache: Vec<SolverCacheIndex>,
     ) -> (Block, Prio, usize) {
         let mut best_block = Block::from_single_node_id(NodeID::empty());
         let mut best_prio = Prio::Empty;
         let mut best_index = 0;
+        let mut best_tie_break = f64::INFINITY;
 
         for index in cache {
-            if self.basic_blocks.has_index(index) {
-                let (_, block, prio) = &self.basic_blocks.get_block(index);
-                if best_prio < *prio {
-                    best_block = *block;
-                    best_prio = *prio;
-                    best_index = index;
-                }
+            let (block, prio, weight) = if self.basic_blocks.has_index(index) {
+                let (_, block, prio) = self.basic_blocks.get_block(index);
+                (block, prio, self.basic_blocks.get_weight(index))
             } else {
-                let (_, block, prio) = &self.multi_blocks[index - self.basic_blocks.len()];
-                if best_prio < *prio {
-                    best_block = *block;
-                    best_prio = *prio;
-                    best_index = index;
-                }
+                let multi_index = index - self.basic_blocks.len();
+                let (_, block, prio) = &self.multi_blocks[multi_index];
+                (block, prio, self.multi_block_weights[multi_index])
+            };
+
+            // Equal prios are resolved by a seeded weighted hash so the result does not depend on
+            // the cache order.
+            let tie_break =
+                get_weighted_seeded_value(block_object.seed, world_block_pos, index, weight);
+            if best_prio < *prio || (best_prio == *prio && tie_break < best_tie_break) {
+                best_block = *block;
+                best_prio = *prio;
+                best_index = index;
+                best_tie_break = tie_break;
             }
         }
 
@@ -211,18 +235,20 @@ impl SolverFunctions for HullSolver {
 }
 
 impl HullSolver {
-    fn add_multi_blocks(&mut self, rules: &mut Rules, voxel_loader: &VoxelLoader) -> Result<()> {
-        let mut multi_blocks: Vec<(Vec<(IVec3, Vec<Block>)>, Block, Prio)> = vec![];
-
-        let num = 4;
-        for i in 0..num {
-            let (blocks, req_blocks) = load_multi_block_req_folder(
-                &format!("{HULL_MULTI_NAME_PART}-{i}"),
-                voxel_loader,
-                rules,
-            )?;
-
-            for (block, pos, prio) in blocks.to_owned().into_iter() {
+    fn add_multi_blocks(
+        &mut self,
+        rules: &mut Rules,
+        voxel_loader: &VoxelLoader,
+        folders: &[FolderDefinition],
+    ) -> Result<()> {
+        let mut multi_blocks: Vec<(Vec<MultiBlockReq>, Block, Prio)> = vec![];
+        let mut weights = vec![];
+
+        for folder in folders {
+            let (blocks, req_blocks, req_block_names) =
+                load_multi_block_req_folder(&folder.name, folder.prio, voxel_loader, rules)?;
+
+            for (block, pos, prio, weight) in blocks.to_owned().into_iter() {
                 let mut empty_reqs = vec![];
                 let mut add = false;
 
@@ -259,56 +285,92 @@ impl HullSolver {
                         blocks
                             .to_owned()
                             .into_iter()
-                            .map(|(block, pos, _)| (block.to_owned(), pos.to_owned())),
+                            .map(|(block, pos, _, _)| (block.to_owned(), pos.to_owned())),
                     ) {
                         if neighbor_pos == test_pos {
-                            let blocks = reqs.iter_mut().find_map(|(test_offset, blocks)| {
-                                if *test_offset == offset {
-                                    Some(blocks)
-                                } else {
-                                    None
-                                }
-                            });
-
-                            if blocks.is_some() {
-                                let blocks = blocks.unwrap();
-
-                                if !blocks.contains(&req_block) {
-                                    blocks.push(req_block);
-                                }
-                            } else {
-                                reqs.push((offset, vec![req_block]));
+                            let (_, blocks, _) = get_multi_block_req(reqs, offset);
+                            if !blocks.contains(&req_block) {
+                                blocks.push(req_block);
+                            }
+                        }
+                    }
+
+                    for (req_block_name_index, test_pos) in req_block_names.iter() {
+                        if neighbor_pos == *test_pos {
+                            let (_, _, block_names) = get_multi_block_req(reqs, offset);
+                            if !block_names.contains(req_block_name_index) {
+                                block_names.push(*req_block_name_index);
                             }
                         }
                     }
                 }
 
+                // Rotations of a block that is already added keep its weight.
                 if add {
-                    multi_blocks.push((empty_reqs, block, prio))
+                    multi_blocks.push((empty_reqs, block, prio));
+                    weights.push(weight);
                 }
             }
         }
 
-        let mut rotated_multi_blocks = permutate_multi_blocks(&multi_blocks, rules);
+        let (mut rotated_multi_blocks, mut rotated_weights) =
+            permutate_multi_blocks(&multi_blocks, &weights, rules);
         self.multi_blocks.append(&mut rotated_multi_blocks);
+        self.multi_block_weights.append(&mut rotated_weights);
 
         #[cfg(debug_assertions)]
         self.debug_multi_blocks.append(&mut multi_blocks);
 
         info!("Added {} Hull Multi Blocks", self.multi_blocks.len());
 
-        let broad_req_tree = BroadReqTree::new(&self.multi_blocks, self.basic_blocks.len());
+        let broad_req_tree =
+            BroadReqTree::new(&self.get_multi_block_entries(), self.basic_blocks.len());
         self.multi_broad_req_tree = broad_req_tree;
 
         Ok(())
     }
 
-    fn get_multi_blocks_reset(
+    /// Empty req blocks require the empty block, all other req blocks require a hull block.
+    /// Req block names require a block of that name.
+    fn get_multi_block_entries(&self) -> Vec<ReqTreeEntry> {
+        self.multi_blocks
+            .iter()
+            .map(|(reqs, _, _)| {
+                reqs.iter()
+                    .map(|(offset, req_blocks, req_block_names)| {
+                        let mut block_names = vec![];
+                        for req_block in req_blocks {
+                            let block_name_index =
+                                if *req_block == Block::from_single_node_id(NodeID::empty()) {
+                                    EMPTY_BLOCK_NAME_INDEX
+                                } else {
+                                    self.block_name_index
+                                };
+
+                            if !block_names.contains(&block_name_index) {
+                                block_names.push(block_name_index);
+                            }
+                        }
+                        for block_name_index in req_block_names {
+                            if !block_names.contains(block_name_index) {
+                                block_names.push(*block_name_index);
+                            }
+                        }
+
+                        (*offset, block_names)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Tests every multi block. The req tree gives the same result.
+    pub fn get_multi_blocks_reset(
         &self,
-        ship: &mut BlockObject,
+        ship: &BlockObject,
         world_block_pos: IVec3,
     ) -> Vec<SolverCacheIndex> {
-        #[cfg(debug_assertions)]
+        #[cfg(all(debug_assertions, feature = "render"))]
         puffin::profile_function!();
 
         let mut cache = vec![];
@@ -321,27 +383,12 @@ impl HullSolver {
             if pass {
                 // puffin::profile_scope!("Pass");
 
-                for (req_pos, req_blocks) in reqs {
-                    let req_world_block_pos = world_block_pos + *req_pos;
+                for req in reqs {
+                    let req_world_block_pos = world_block_pos + req.0;
                     let block_name_index =
                         ship.get_block_name_from_world_block_pos(req_world_block_pos);
 
-                    let mut ok = false;
-                    for req_block in req_blocks {
-                        let req_empty = *req_block == Block::from_single_node_id(NodeID::empty());
-                        //let req_base = *req_block == self.basic_blocks[0].1;
-
-                        if !req_empty && block_name_index == self.block_name_index {
-                            ok = true;
-                            break;
-                        }
-                        if req_empty && block_name_index == EMPTY_BLOCK_NAME_INDEX {
-                            ok = true;
-                            break;
-                        }
-                    }
-
-                    if !ok {
+                    if !self.is_req_block_name(req, block_name_index) {
                         pass = false;
                         break;
                     }
@@ -364,26 +411,12 @@ impl HullSolver {
         let mut cache = vec![];
         for (i, (reqs, _, _)) in self.multi_blocks.iter().enumerate() {
             let mut req_results = vec![];
-            for (req_pos, req_blocks) in reqs {
-                let req_world_block_pos = world_block_pos + *req_pos;
+            for req in reqs {
+                let req_world_block_pos = world_block_pos + req.0;
                 let block_name_index =
                     ship.get_block_name_from_world_block_pos(req_world_block_pos);
 
-                let mut ok = false;
-                for req_block in req_blocks {
-                    let req_empty = *req_block == Block::from_single_node_id(NodeID::empty());
-                    //let req_base = *req_block == self.basic_blocks[0].1;
-
-                    if !req_empty && block_name_index == self.block_name_index {
-                        ok = true;
-                        break;
-                    }
-                    if req_empty && block_name_index == EMPTY_BLOCK_NAME_INDEX {
-                        ok = true;
-                        break;
-                    }
-                }
-
+                let ok = self.is_req_block_name(req, block_name_index);
                 req_results.push((req_world_block_pos, ok))
             }
 
@@ -393,12 +426,28 @@ impl HullSolver {
         cache
     }
 
-    fn get_multi_blocks_reset_with_req_tree(
+    /// Whether a block of the name can fulfill the req. The same test as of the req tree entries.
+    fn is_req_block_name(
         &self,
-        ship: &mut BlockObject,
+        (_, req_blocks, req_block_names): &MultiBlockReq,
+        block_name_index: BlockNameIndex,
+    ) -> bool {
+        req_block_names.contains(&block_name_index)
+            || req_blocks.iter().any(|req_block| {
+                if *req_block == Block::from_single_node_id(NodeID::empty()) {
+                    block_name_index == EMPTY_BLOCK_NAME_INDEX
+                } else {
+                    block_name_index == self.block_name_index
+                }
+            })
+    }
+
+    pub fn get_multi_blocks_reset_with_req_tree(
+        &self,
+        ship: &BlockObject,
         world_block_pos: IVec3,
     ) -> Vec<SolverCacheIndex> {
-        #[cfg(debug_assertions)]
+        #[cfg(all(debug_assertions, feature = "render"))]
         puffin::profile_function!();
 
         let block_name_index = ship.get_block_name_from_world_block_pos(world_block_pos);
@@ -406,46 +455,28 @@ impl HullSolver {
             return vec![];
         }
 
-        let mut node_index = 0;
-        loop {
-            let node = self.multi_broad_req_tree.nodes[node_index];
-
-            let req_world_block_pos = world_block_pos + node.offset;
-            let block_name_index = ship.get_block_name_from_world_block_pos(req_world_block_pos);
-
-            if block_name_index == self.block_name_index {
-                if node.positive_leaf {
-                    return self.multi_broad_req_tree.leafs[node.positive_child].to_owned();
-                }
-
-                node_index = node.positive_child;
-            } else if block_name_index == EMPTY_BLOCK_NAME_INDEX {
-                if node.negative_leaf {
-                    return self.multi_broad_req_tree.leafs[node.negative_child].to_owned();
-                }
-
-                node_index = node.negative_child;
-            } else {
-                return vec![];
-            }
-        }
+        self.multi_broad_req_tree
+            .get_entries(ship, world_block_pos)
+            .to_owned()
     }
 
     fn keep_multi_block(
         &self,
-        ship: &mut BlockObject,
+        ship: &BlockObject,
         world_block_pos: IVec3,
         cache_index: CacheIndex,
     ) -> bool {
         let (reqs, _, _) = &self.multi_blocks[cache_index - self.basic_blocks.len()];
 
         let mut pass = true;
-        for (req_pos, req_blocks) in reqs {
+        for (req_pos, req_blocks, req_block_names) in reqs {
             let req_world_block_pos = world_block_pos + *req_pos;
+            let block_name_index = ship.get_block_name_from_world_block_pos(req_world_block_pos);
             let cache =
-                ship.get_cache_from_world_block_pos(req_world_block_pos, self.block_name_index);
+                ship.find_cache_from_world_block_pos(req_world_block_pos, self.block_name_index);
 
-            let mut ok = false;
+            // Block names do not change while solving, so they are still fulfilled.
+            let mut ok = req_block_names.contains(&block_name_index);
             'iter: for req_block in req_blocks {
                 if *req_block == Block::from_single_node_id(NodeID::empty()) {
                     ok = true;
@@ -481,8 +512,9 @@ impl HullSolver {
         let (reqs, _, _) = &self.multi_blocks[cache_index - self.basic_blocks.len()];
 
         let mut reqs_result = vec![];
-        for (req_pos, req_blocks) in reqs {
+        for (req_pos, req_blocks, req_block_names) in reqs {
             let req_world_block_pos = world_block_pos + *req_pos;
+            let block_name_index = ship.get_block_name_from_world_block_pos(req_world_block_pos);
             let in_chunk_block_index =
                 ship.get_block_index_from_world_block_pos(req_world_block_pos);
             let cache = blocks[in_chunk_block_index]
@@ -490,7 +522,7 @@ impl HullSolver {
                 .get_cache(self.block_name_index)
                 .to_owned();
 
-            let mut ok = false;
+            let mut ok = req_block_names.contains(&block_name_index);
             'iter: for req_block in req_blocks {
                 if *req_block == Block::from_single_node_id(NodeID::empty()) {
                     ok = true;
@@ -516,11 +548,17 @@ impl HullSolver {
 
 pub fn load_multi_block_req_folder(
     folder_name: &str,
+    folder_prio: usize,
     voxel_loader: &VoxelLoader,
     rules: &mut Rules,
-) -> Result<(Vec<(Block, IVec3, Prio)>, Vec<(Block, IVec3)>)> {
+) -> Result<(
+    Vec<(Block, IVec3, Prio, u32)>,
+    Vec<(Block, IVec3)>,
+    Vec<(BlockNameIndex, IVec3)>,
+)> {
     let mut blocks = vec![];
     let mut req_blocks = vec![];
+    let mut req_block_names = vec![];
 
     let (models, rot) = voxel_loader.get_name_folder(folder_name)?;
 
@@ -529,47 +567,63 @@ pub fn load_multi_block_req_folder(
     }
 
     for (name, index, rot, pos) in models {
-        let name_parts: Vec<_> = name.split('-').collect();
+        match parse_req_folder_model_name(&name, false)? {
+            ReqFolderModel::Block { kind, prio, weight } => {
+                let block = rules
+                    .load_req_folder_block(kind, &name, index, voxel_loader)?
+                    .rotate(rot, rules);
+                blocks.push((block, pos, Multi(prio + folder_prio), weight))
+            }
+            ReqFolderModel::Req(kind) => {
+                let block = rules
+                    .load_req_folder_block(kind, &name, index, voxel_loader)?
+                    .rotate(rot, rules);
+                req_blocks.push((block, pos))
+            }
+            ReqFolderModel::BlockName(req_block_name) => {
+                req_block_names.push((rules.find_block_name_index(req_block_name)?, pos))
+            }
+        }
+    }
 
-        let block = if name_parts[1] == BLOCK_MODEL_IDENTIFIER {
-            rules.load_block_from_block_model_by_index(index, voxel_loader)?
-        } else if name_parts[1] == FOLDER_MODEL_IDENTIFIER {
-            rules.load_block_from_node_folder(&name, voxel_loader)?
-        } else {
-            bail!("Part 1 of {name} is not identified.");
-        };
-        let block = block.rotate(rot, rules);
+    Ok((blocks, req_blocks, req_block_names))
+}
 
-        if name_parts[0] == BLOCK_TYPE_IDENTIFIER {
-            let prio = name_parts[2].parse::<usize>()?;
-            blocks.push((block, pos, Multi(prio)))
-        } else if name_parts[0] == REQ_TYPE_IDENTIFIER {
-            req_blocks.push((block, pos))
-        } else {
-            bail!("Part 0 of {name} is not identified.");
+/// The req at the offset, it is added if there is none yet.
+fn get_multi_block_req(reqs: &mut Vec<MultiBlockReq>, offset: IVec3) -> &mut MultiBlockReq {
+    let index = match reqs
+        .iter()
+        .position(|(test_offset, _, _)| *test_offset == offset)
+    {
+        Some(index) => index,
+        None => {
+            reqs.push((offset, vec![], vec![]));
+            reqs.len() - 1
         }
-    }
+    };
 
-    Ok((blocks, req_blocks))
+    &mut reqs[index]
 }
 
 fn permutate_multi_blocks(
-    blocks: &[(Vec<(IVec3, Vec<Block>)>, Block, Prio)],
+    blocks: &[(Vec<MultiBlockReq>, Block, Prio)],
+    weights: &[u32],
     rules: &mut Rules,
-) -> Vec<(Vec<(IVec3, Vec<Block>)>, Block, Prio)> {
+) -> (Vec<(Vec<MultiBlockReq>, Block, Prio)>, Vec<u32>) {
     let mut rotated_blocks = vec![];
-    for (reqs, block, prio) in blocks.iter() {
+    let mut rotated_weights = vec![];
+    for ((reqs, block, prio), weight) in blocks.iter().zip(weights.iter()) {
         for rot in Rot::IDENTITY.get_all_permutations() {
             let mat: Mat4 = rot.into();
             let rotated_reqs: Vec<_> = reqs
                 .iter()
-                .map(|(req_pos, req_blocks)| {
+                .map(|(req_pos, req_blocks, req_block_names)| {
                     let rotated_pos = mat
                         .transform_vector3((*req_pos).as_vec3())
                         .round()
                         .as_ivec3();
                     let rotated_blocks = req_blocks.iter().map(|b| b.rotate(rot, rules)).collect();
-                    (rotated_pos, rotated_blocks)
+                    (rotated_pos, rotated_blocks, req_block_names.to_owned())
                 })
                 .collect();
 
@@ -584,10 +638,11 @@ fn permutate_multi_blocks(
             }
 
             if !found {
-                rotated_blocks.push((rotated_reqs, rotated_block, *prio))
+                rotated_blocks.push((rotated_reqs, rotated_block, *prio));
+                rotated_weights.push(*weight);
             }
         }
     }
 
-    rotated_blocks
+    (rotated_blocks, rotated_weights)
 }
@@ -0,0 +1,94 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::fs;
+
+/// Declares all block names of the rules and how their blocks are made.
+/// Blocks can require every block of the manifest, so transition blocks between them are possible.
+#[derive(Deserialize, Clone, Debug, PartialEq, Hash)]
+pub struct RuleManifest {
+    pub blocks: Vec<BlockDefinition>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Hash)]
+pub struct BlockDefinition {
+    pub name: String,
+    pub solver: SolverDefinition,
+    /// The builder can place blocks of this name.
+    #[serde(default)]
+    pub buildable: bool,
+    /// Generated asteroids are made of this block. Only one block can be the asteroid block.
+    #[serde(default)]
+    pub asteroid: bool,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Hash)]
+pub enum SolverDefinition {
+    Empty,
+    Hull {
+        basic_folders: Vec<FolderDefinition>,
+        multi_folders: Vec<FolderDefinition>,
+    },
+    Stone {
+        marching_cubes_folder: String,
+        /// Blocks the marching cubes treat like empty blocks, so their solver can fill the border.
+        #[serde(default)]
+        empty_blocks: Vec<String>,
+        /// Follows the densities of the blocks, like the ones of generated asteroids.
+        #[serde(default)]
+        smooth: bool,
+    },
+    /// A solver kind of the SolverRegistry passed to Rules::new_with_registry.
+    Custom {
+        kind: String,
+        #[serde(default)]
+        folders: Vec<FolderDefinition>,
+    },
+}
+
+/// A folder in the .vox file.
+#[derive(Deserialize, Clone, Debug, PartialEq, Hash)]
+pub struct FolderDefinition {
+    pub name: String,
+    /// Added to the priority each block has in its model name.
+    #[serde(default)]
+    pub prio: usize,
+}
+
+impl RuleManifest {
+    pub fn load(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let manifest: RuleManifest = ron::from_str(text)?;
+        manifest.validate()?;
+
+        Ok(manifest)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.blocks.is_empty() || self.blocks[0].solver != SolverDefinition::Empty {
+            bail!("The first block of the rule manifest has to use the Empty solver");
+        }
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            if self.blocks[..i]
+                .iter()
+                .any(|other| other.name == block.name)
+            {
+                bail!("Block name {} is declared twice", block.name);
+            }
+
+            if i != 0 && block.solver == SolverDefinition::Empty {
+                bail!("Only the first block can use the Empty solver");
+            }
+        }
+
+        if self.blocks.iter().filter(|block| block.asteroid).count() > 1 {
+            bail!("Only one block can be the asteroid block");
+        }
+
+        Ok(())
+    }
+}
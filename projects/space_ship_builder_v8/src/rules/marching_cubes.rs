@@ -1,19 +1,33 @@
 use crate::math::rotation::Rot;
-use crate::math::{get_neighbors, oct_positions, oct_positions_with_minus};
-use crate::rules::empty::EMPTY_BLOCK_NAME_INDEX;
+use crate::math::{get_neighbors, oct_positions, oct_positions_with_minus, to_1d_i, to_3d_i};
 use crate::rules::Rules;
 use crate::world::block_object::BlockObject;
 use crate::world::data::block::{Block, BlockNameIndex};
-use crate::world::data::node::NodeID;
+use crate::world::data::node::{Node, NodeID, Voxel, NODE_SIZE, NODE_VOXEL_LENGTH, VOXEL_EMPTY};
 use crate::world::data::voxel_loader::VoxelLoader;
+use anyhow::bail;
+use anyhow::Result;
+use bitcode::{Decode, Encode};
+use glam::{ivec3, vec3, IVec3, Mat4, Vec3};
 use log::{debug, trace, warn};
-use octa_force::anyhow::bail;
-use octa_force::anyhow::Result;
-use octa_force::glam::{ivec3, vec3, IVec3, Mat4};
+
+/// Blocks with a density above it are inside the stone. The asteroid generator uses the same level.
+pub const DENSITY_ISO_LEVEL: f32 = 0.5;
+
+// The voxels of a node are packed into the bits of a u64.
+const _: () = assert!(NODE_VOXEL_LENGTH <= 64);
 
 pub struct MarchingCubes {
-    block_name_index: BlockNameIndex,
-    node_reqs: Vec<(NodeReq, NodeID)>,
+    pub block_name_index: BlockNameIndex,
+    pub node_reqs: Vec<(NodeReq, NodeID)>,
+    /// Blocks that do not fill the corners of the cubes. Always contains the empty block.
+    pub empty_block_name_indices: Vec<BlockNameIndex>,
+    /// The configs no node of the folder covers. Their nodes are made from the covered configs.
+    pub synthesized_node_reqs: Vec<NodeReq>,
+    /// Picks the nodes by the densities of the blocks instead of the configs.
+    pub smooth: bool,
+    /// The filled voxels of the node of each config as bits.
+    pub voxel_masks: Vec<u64>,
 }
 
 impl MarchingCubes {
@@ -22,6 +36,8 @@ impl MarchingCubes {
         voxel_loader: &VoxelLoader,
         folder_name: &str,
         block_name_index: BlockNameIndex,
+        empty_block_name_indices: Vec<BlockNameIndex>,
+        smooth: bool,
     ) -> Result<Self> {
         let nodes = rules.load_nodes_in_folder(folder_name, voxel_loader)?;
 
@@ -55,7 +71,9 @@ impl MarchingCubes {
         for (node_id, _, name) in nodes.into_iter() {
             let name_parts: Vec<_> = name.split('-').collect();
 
-            let config = configs.iter().find(|(name, _)| name_parts[1] == *name);
+            let config = configs
+                .iter()
+                .find(|(name, _)| name_parts.get(1) == Some(name));
             if config.is_none() {
                 bail!("Name Part 1 of {} is not a valid config name.", name);
             }
@@ -103,22 +121,97 @@ impl MarchingCubes {
             }
         }
 
-        for i in 0..u8::MAX {
-            if node_reqs
+        let mut marching_cubes = MarchingCubes {
+            block_name_index,
+            node_reqs,
+            empty_block_name_indices,
+            synthesized_node_reqs: vec![],
+            smooth,
+            voxel_masks: vec![],
+        };
+        marching_cubes.synthesize_missing_node_reqs(rules);
+        marching_cubes.voxel_masks = marching_cubes
+            .node_reqs
+            .iter()
+            .map(|(_, node_id)| get_voxel_mask(&rules.nodes[node_id.index].rotate(node_id.rot)))
+            .collect();
+
+        Ok(marching_cubes)
+    }
+
+    /// A missing config gets the union of the largest covered configs it contains, so the surface
+    /// has no holes. Configs that contain no covered config are filled per corner.
+    fn synthesize_missing_node_reqs(&mut self, rules: &mut Rules) {
+        let covered = self.node_reqs.to_owned();
+        let fill_voxel = covered
+            .iter()
+            .flat_map(|(_, node_id)| rules.nodes[node_id.index].voxels)
+            .find(|voxel| *voxel != VOXEL_EMPTY)
+            .unwrap_or(1);
+
+        for node_req in self.get_missing_node_reqs() {
+            let sub_reqs: Vec<_> = covered
                 .iter()
-                .find(|(node_req, _)| node_req.0 == i)
-                .is_none()
-            {
-                let req: Vec<_> = NodeReq(i).into_iter().collect();
+                .filter(|(test_req, _)| test_req.0 != 0 && test_req.is_subset(node_req))
+                .collect();
+            let largest_sub_reqs = sub_reqs.iter().filter(|(test_req, _)| {
+                !sub_reqs
+                    .iter()
+                    .any(|(other, _)| other.0 != test_req.0 && test_req.is_subset(*other))
+            });
 
-                warn!("{req:?} missing.");
+            let mut voxels = [VOXEL_EMPTY; NODE_VOXEL_LENGTH];
+            if sub_reqs.is_empty() {
+                voxels = get_corner_voxels(node_req, fill_voxel);
+            }
+            for (_, node_id) in largest_sub_reqs {
+                let node = rules.nodes[node_id.index].rotate(node_id.rot);
+                for (voxel, sub_voxel) in voxels.iter_mut().zip(node.voxels) {
+                    if *voxel == VOXEL_EMPTY {
+                        *voxel = sub_voxel;
+                    }
+                }
             }
+
+            let node_id = rules.add_node(Node::new(voxels), Rot::IDENTITY);
+            let node_id = rules.get_duplicate_node_id(node_id);
+
+            let req: Vec<_> = node_req.into_iter().collect();
+            warn!("{req:?} missing. Using synthesized {node_id:?}.");
+
+            let insert_index = self
+                .node_reqs
+                .binary_search_by(|(test_node_req, _)| test_node_req.0.cmp(&node_req.0))
+                .unwrap_err();
+            self.node_reqs.insert(insert_index, (node_req, node_id));
+            self.synthesized_node_reqs.push(node_req);
         }
+    }
 
-        Ok(MarchingCubes {
-            block_name_index,
-            node_reqs,
-        })
+    /// The configs that are not covered by any node or its permutations.
+    pub fn get_missing_node_reqs(&self) -> Vec<NodeReq> {
+        (0..=u8::MAX)
+            .filter(|i| {
+                self.node_reqs
+                    .iter()
+                    .find(|(node_req, _)| node_req.0 == *i)
+                    .is_none()
+            })
+            .map(NodeReq)
+            .collect()
+    }
+
+    pub fn get_node_id(&self, node_req: NodeReq) -> NodeID {
+        // All configs are covered, so the sorted node_reqs are indexed by the config.
+        self.node_reqs[node_req.0 as usize].1
+    }
+
+    pub fn get_node_ids(&self) -> impl Iterator<Item = NodeID> + '_ {
+        self.node_reqs.iter().map(|(_, node_id)| *node_id)
+    }
+
+    pub fn is_filled(&self, block_name_index: BlockNameIndex) -> bool {
+        !self.empty_block_name_indices.contains(&block_name_index)
     }
 
     pub fn get_block(&self, block_object: &mut BlockObject, world_block_pos: IVec3) -> Block {
@@ -126,7 +219,7 @@ impl MarchingCubes {
 
         let node_pos = block_object.get_node_pos_from_block_pos(world_block_pos);
 
-        let node_ids = oct_positions().map(|offset| {
+        let node_reqs = oct_positions().map(|offset| {
             let pos = node_pos + offset;
 
             /*
@@ -151,13 +244,13 @@ impl MarchingCubes {
                     let req_block_name_index =
                         block_object.get_block_name_from_world_block_pos(block_pos);
 
-                    req_block_name_index != EMPTY_BLOCK_NAME_INDEX
+                    self.is_filled(req_block_name_index)
                 } else {
                     let mut test_offset = |test_offset: IVec3| {
                         let block_pos = (req_pos + test_offset) / 2;
                         let req_block_name_index =
                             block_object.get_block_name_from_world_block_pos(block_pos);
-                        req_block_name_index != EMPTY_BLOCK_NAME_INDEX
+                        self.is_filled(req_block_name_index)
                     };
 
                     // TODO Clean up
@@ -199,18 +292,132 @@ impl MarchingCubes {
                 }
             });
 
-            let test_node_req = NodeReq::from(test_reqs);
+            NodeReq::from(test_reqs)
+        });
+
+        if self.smooth {
+            return self.get_smooth_block(block_object, world_block_pos, node_reqs);
+        }
+
+        Block::from_node_ids(node_reqs.map(|node_req| self.get_node_id(node_req)))
+    }
 
-            self.node_reqs[test_node_req.0 as usize].1
+    /// Picks for every node the variant whose voxels are closest to the iso-surface of the
+    /// interpolated block densities. The node of the config wins ties.
+    fn get_smooth_block(
+        &self,
+        block_object: &BlockObject,
+        world_block_pos: IVec3,
+        node_reqs: [NodeReq; 8],
+    ) -> Block {
+        let mut samples = [0.0; 27];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let offset = to_3d_i(i as i32, IVec3::splat(3)) - 1;
+            *sample = self.get_density_sample(block_object, world_block_pos + offset);
+        }
+
+        let node_ids = std::array::from_fn(|i| {
+            let node_offset = oct_positions()[i];
+
+            let mut mask = 0;
+            for v in 0..NODE_VOXEL_LENGTH {
+                // The nodes of a block span half a block around it.
+                let voxel_pos = to_3d_i(v as i32, NODE_SIZE).as_vec3() + 0.5;
+                let pos = ((node_offset - 1).as_vec3() + voxel_pos / NODE_SIZE.as_vec3()) / 2.0;
+
+                if interpolate_density(&samples, pos) > DENSITY_ISO_LEVEL {
+                    mask |= 1 << v;
+                }
+            }
+
+            let config = node_reqs[i].0 as usize;
+            let mut best = (config, (self.voxel_masks[config] ^ mask).count_ones());
+            for (test_config, test_mask) in self.voxel_masks.iter().enumerate() {
+                let distance = (test_mask ^ mask).count_ones();
+                if distance < best.1 {
+                    best = (test_config, distance);
+                }
+            }
+
+            self.node_reqs[best.0].1
         });
 
         Block::from_node_ids(node_ids)
     }
+
+    /// Blocks without a density are 1.0 if they are filled and 0.0 otherwise.
+    /// Densities never contradict the block names.
+    fn get_density_sample(&self, block_object: &BlockObject, world_block_pos: IVec3) -> f32 {
+        let filled =
+            self.is_filled(block_object.get_block_name_from_world_block_pos(world_block_pos));
+
+        match block_object.get_density_from_world_block_pos(world_block_pos) {
+            Some(density) if filled => density.max(DENSITY_ISO_LEVEL + f32::EPSILON),
+            Some(density) => density.min(DENSITY_ISO_LEVEL),
+            None if filled => 1.0,
+            None => 0.0,
+        }
+    }
+}
+
+/// Trilinear interpolation of the 3x3x3 samples around a block. pos is relative to the block and
+/// in the range -1 to 1.
+fn interpolate_density(samples: &[f32; 27], pos: Vec3) -> f32 {
+    let base = pos.floor();
+    let fract = pos - base;
+    let base = base.as_ivec3() + 1;
+
+    let mut density = 0.0;
+    for corner in oct_positions() {
+        let weights = Vec3::select(corner.cmpeq(IVec3::ONE), fract, Vec3::ONE - fract);
+        let index = to_1d_i(base + corner, IVec3::splat(3));
+        density += samples[index] * weights.x * weights.y * weights.z;
+    }
+
+    density
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+fn get_voxel_mask(node: &Node) -> u64 {
+    let mut mask = 0;
+    for (i, voxel) in node.voxels.iter().enumerate() {
+        if *voxel != VOXEL_EMPTY {
+            mask |= 1 << i;
+        }
+    }
+
+    mask
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Encode, Decode)]
 pub struct NodeReq(u8);
 
+impl NodeReq {
+    /// True if every corner of self is also set in other.
+    pub fn is_subset(self, other: NodeReq) -> bool {
+        self.0 & !other.0 == 0
+    }
+}
+
+/// Fills each quarter of the node that belongs to a set corner.
+fn get_corner_voxels(node_req: NodeReq, fill_voxel: Voxel) -> [Voxel; NODE_VOXEL_LENGTH] {
+    let corners: Vec<_> = node_req.into_iter().collect();
+
+    let mut voxels = [VOXEL_EMPTY; NODE_VOXEL_LENGTH];
+    for (i, voxel) in voxels.iter_mut().enumerate() {
+        let corner = to_3d_i(i as i32, NODE_SIZE) / (NODE_SIZE / 2);
+        let corner_index = oct_positions()
+            .iter()
+            .position(|offset| *offset == corner)
+            .unwrap();
+
+        if corners[corner_index] {
+            *voxel = fill_voxel;
+        }
+    }
+
+    voxels
+}
+
 impl From<Vec<(IVec3, bool)>> for NodeReq {
     fn from(value: Vec<(IVec3, bool)>) -> Self {
         let mut node_req = NodeReq(0);
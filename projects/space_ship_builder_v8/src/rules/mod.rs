@@ -1,27 +1,43 @@
 mod basic_blocks;
+pub mod cache;
 pub mod empty;
 pub mod hull;
+pub mod manifest;
 pub mod marching_cubes;
+pub mod registry;
 pub mod req_tree;
 pub mod solver;
 pub mod stone;
+pub mod validation;
 
 use crate::math::oct_positions;
 use crate::math::rotation::Rot;
+use crate::rules::manifest::{RuleManifest, SolverDefinition};
+use crate::rules::registry::SolverRegistry;
 use crate::rules::solver::Solver;
 use crate::world::data::block::{Block, BlockNameIndex};
-use crate::world::data::node::{Material, Node, NodeID};
+use crate::world::data::node::{Material, Node, NodeID, NodeIndex, Voxel, NODE_VOXEL_LENGTH};
 use crate::world::data::voxel_loader::VoxelLoader;
+use anyhow::{bail, Result};
+use bitcode::{Decode, Encode};
 use dot_vox::SceneNode;
-use octa_force::anyhow::{bail, Ok, Result};
-use octa_force::glam::{IVec3, UVec3};
+use glam::{IVec3, UVec3};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 
 const BLOCK_MODEL_IDENTIFIER: &str = "B";
 const FOLDER_MODEL_IDENTIFIER: &str = "F";
 const BLOCK_TYPE_IDENTIFIER: &str = "Block";
 const REQ_TYPE_IDENTIFIER: &str = "Req";
+const WEIGHT_IDENTIFIER: &str = "W";
 
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Default, Debug)]
+pub const DEFAULT_BLOCK_WEIGHT: u32 = 1;
+
+pub const RULE_MANIFEST_PATH: &str = "./assets/rules.ron";
+pub const RULE_CACHE_DIR: &str = "./cache/rules";
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Default, Debug, Encode, Decode)]
 pub enum Prio {
     #[default]
     Zero,
@@ -36,28 +52,78 @@ pub struct Rules {
     pub nodes: Vec<Node>,
     pub duplicate_node_ids: Vec<Vec<Vec<NodeID>>>,
 
+    /// Nodes by their canonical voxels, so add_node only compares nodes that can be duplicates.
+    pub canonical_node_indices: HashMap<[Voxel; NODE_VOXEL_LENGTH], Vec<NodeIndex>>,
+    /// The results of get_duplicate_node_id.
+    pub duplicate_node_id_lookup: HashMap<NodeID, NodeID>,
+
     pub block_names: Vec<String>,
     pub solvers: Vec<Solver>,
+
+    /// Blocks the builder can place besides the empty block.
+    pub buildable_block_name_indices: Vec<BlockNameIndex>,
+    pub asteroid_block_name_index: Option<BlockNameIndex>,
 }
 
 impl Rules {
-    pub fn new(voxel_loader: &VoxelLoader) -> Result<Self> {
-        let mut rules = Rules {
+    pub fn new(voxel_loader: &VoxelLoader, manifest: &RuleManifest) -> Result<Self> {
+        Self::new_with_registry(voxel_loader, manifest, &SolverRegistry::new())
+    }
+
+    pub fn new_with_registry(
+        voxel_loader: &VoxelLoader,
+        manifest: &RuleManifest,
+        registry: &SolverRegistry,
+    ) -> Result<Self> {
+        let mut rules = Self::new_without_blocks(voxel_loader);
+        rules.add_manifest_block_names(manifest);
+
+        for block in manifest.blocks.iter() {
+            match &block.solver {
+                SolverDefinition::Empty => rules.make_empty(&block.name),
+                SolverDefinition::Hull {
+                    basic_folders,
+                    multi_folders,
+                } => rules.make_hull(&block.name, basic_folders, multi_folders, voxel_loader)?,
+                SolverDefinition::Stone {
+                    marching_cubes_folder,
+                    empty_blocks,
+                    smooth,
+                } => rules.make_stone(
+                    &block.name,
+                    marching_cubes_folder,
+                    empty_blocks,
+                    *smooth,
+                    voxel_loader,
+                )?,
+                SolverDefinition::Custom { kind, folders } => {
+                    rules.make_custom(&block.name, kind, folders, voxel_loader, registry)?
+                }
+            }
+        }
+
+        Ok(rules)
+    }
+
+    fn new_without_blocks(voxel_loader: &VoxelLoader) -> Self {
+        Rules {
             materials: voxel_loader.load_materials(),
             nodes: vec![],
             duplicate_node_ids: vec![vec![vec![NodeID::default()]]],
+            canonical_node_indices: HashMap::new(),
+            duplicate_node_id_lookup: HashMap::new(),
             block_names: vec![],
             solvers: vec![],
-        };
-
-        rules.make_empty();
-        rules.make_hull(voxel_loader)?;
-        rules.make_stone(voxel_loader)?;
-
-        Ok(rules)
+            buildable_block_name_indices: vec![],
+            asteroid_block_name_index: None,
+        }
     }
 
     pub fn get_duplicate_node_id(&mut self, node_id: NodeID) -> NodeID {
+        if let Some(id) = self.duplicate_node_id_lookup.get(&node_id) {
+            return *id;
+        }
+
         let node = &self.nodes[node_id.index];
 
         while self.duplicate_node_ids.len() <= node_id.index {
@@ -83,40 +149,205 @@ impl Rules {
             new_node_id = Some(node_id);
         }
 
+        self.duplicate_node_id_lookup
+            .insert(node_id, new_node_id.unwrap());
         new_node_id.unwrap()
     }
 
     pub fn add_node(&mut self, node: Node, rot: Rot) -> NodeID {
         let rots = Rot::IDENTITY.get_all_permutations();
-
-        let mut id = None;
-        for (i, test_node) in self.nodes.iter().enumerate() {
-            for test_rot in rots.iter() {
-                if node.is_duplicate_node_id(rot, test_node, *test_rot) {
-                    id = Some(NodeID::new(i, *test_rot));
+        let canonical_voxels = node.get_canonical_voxels();
+
+        // Only nodes with the same canonical voxels can be rotations of the node.
+        // Searched backwards, so the last match is returned like in a scan over all nodes.
+        if let Some(indices) = self.canonical_node_indices.get(&canonical_voxels) {
+            for &i in indices.iter().rev() {
+                for test_rot in rots.iter().rev() {
+                    if node.is_duplicate_node_id(rot, &self.nodes[i], *test_rot) {
+                        return NodeID::new(i, *test_rot);
+                    }
                 }
             }
         }
 
-        if id.is_none() {
-            id = Some(NodeID::new(self.nodes.len(), rot));
-            self.nodes.push(node);
+        NodeID::new(self.push_node(node), rot)
+    }
+
+    /// Adds the node without checking for duplicates.
+    pub fn push_node(&mut self, node: Node) -> NodeIndex {
+        let index = self.nodes.len();
+        self.canonical_node_indices
+            .entry(node.get_canonical_voxels())
+            .or_default()
+            .push(index);
+        self.nodes.push(node);
+
+        index
+    }
+
+    /// Rebuilds the lookups from nodes and duplicate_node_ids.
+    pub fn update_node_lookups(&mut self) {
+        self.canonical_node_indices.clear();
+        for (i, node) in self.nodes.iter().enumerate() {
+            self.canonical_node_indices
+                .entry(node.get_canonical_voxels())
+                .or_default()
+                .push(i);
+        }
+
+        self.duplicate_node_id_lookup.clear();
+        for ids in self.duplicate_node_ids.iter().flatten() {
+            for id in ids.iter() {
+                self.duplicate_node_id_lookup.insert(*id, ids[0]);
+            }
+        }
+    }
+
+    /// Changes when the nodes change, so saved node ids are only used with the nodes they index.
+    pub fn get_nodes_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for node in self.nodes.iter() {
+            node.voxels.hash(&mut hasher);
         }
 
-        id.unwrap()
+        hasher.finish()
     }
 
     pub fn get_block_name_index(&self, name: &str) -> BlockNameIndex {
-        self.block_names
+        self.find_block_name_index(name).unwrap()
+    }
+
+    pub fn find_block_name_index(&self, name: &str) -> Result<BlockNameIndex> {
+        match self
+            .block_names
+            .iter()
+            .position(|test_name| test_name == name)
+        {
+            Some(index) => Ok(index as BlockNameIndex),
+            None => bail!("Block name {name} is not in the rules"),
+        }
+    }
+
+    /// Returns the index of the block name and adds the name if it is not known yet.
+    pub fn add_block_name(&mut self, name: &str) -> BlockNameIndex {
+        match self
+            .block_names
             .iter()
             .position(|test_name| test_name == name)
-            .unwrap() as BlockNameIndex
+        {
+            Some(index) => index as BlockNameIndex,
+            None => {
+                self.block_names.push(name.to_owned());
+                (self.block_names.len() - 1) as BlockNameIndex
+            }
+        }
+    }
+
+    /// All names are known before the first solver is made, so blocks can require every block of
+    /// the manifest.
+    fn add_manifest_block_names(&mut self, manifest: &RuleManifest) {
+        for block in manifest.blocks.iter() {
+            let block_name_index = self.add_block_name(&block.name);
+
+            if block.buildable {
+                self.buildable_block_name_indices.push(block_name_index);
+            }
+            if block.asteroid {
+                self.asteroid_block_name_index = Some(block_name_index);
+            }
+        }
     }
 }
 
+/// Blocks can have a weight part after the prio, like Block-B-1-Slope-W3. Blocks with the same prio
+/// are picked with a probability proportional to their weight.
+fn get_block_weight(name_parts: &[&str]) -> Result<u32> {
+    for part in name_parts.iter().skip(3) {
+        let weight = part
+            .strip_prefix(WEIGHT_IDENTIFIER)
+            .and_then(|weight| weight.parse::<u32>().ok());
+
+        if let Some(weight) = weight {
+            if weight == 0 {
+                bail!("Weight of {} has to be at least 1", name_parts.join("-"));
+            }
+            return Ok(weight);
+        }
+    }
+
+    Ok(DEFAULT_BLOCK_WEIGHT)
+}
+
+/// Where the nodes of a block in a req folder come from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum BlockModelKind {
+    BlockModel,
+    NodeFolder,
+}
+
+/// A model of a req folder, described by its name.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ReqFolderModel<'a> {
+    /// Like Block-B-1-W3, a block that gets placed with its prio and weight.
+    Block {
+        kind: BlockModelKind,
+        prio: usize,
+        weight: u32,
+    },
+    /// Like Req-F, a block that multi blocks require.
+    Req(BlockModelKind),
+    /// Like Stone, a req named after the block it requires.
+    BlockName(&'a str),
+}
+
+fn parse_req_folder_model_name(name: &str, basic: bool) -> Result<ReqFolderModel<'_>> {
+    let name_parts: Vec<_> = name.split('-').collect();
+
+    let is_block = match name_parts[0] {
+        BLOCK_TYPE_IDENTIFIER => true,
+        REQ_TYPE_IDENTIFIER if !basic => false,
+        req_block_name => return Ok(ReqFolderModel::BlockName(req_block_name)),
+    };
+
+    let kind = match name_parts.get(1) {
+        Some(&BLOCK_MODEL_IDENTIFIER) => BlockModelKind::BlockModel,
+        Some(&FOLDER_MODEL_IDENTIFIER) => BlockModelKind::NodeFolder,
+        _ => bail!("Part 1 of {name} is not identified."),
+    };
+
+    if !is_block {
+        return Ok(ReqFolderModel::Req(kind));
+    }
+
+    let Some(prio) = name_parts
+        .get(2)
+        .and_then(|prio| prio.parse::<usize>().ok())
+    else {
+        bail!("Part 2 of {name} is not a prio.");
+    };
+    let weight = get_block_weight(&name_parts)?;
+
+    Ok(ReqFolderModel::Block { kind, prio, weight })
+}
+
 // Helper functions
 impl Rules {
-    pub(crate) fn load_node(&mut self, name: &str, voxel_loader: &VoxelLoader) -> Result<NodeID> {
+    fn load_req_folder_block(
+        &mut self,
+        kind: BlockModelKind,
+        name: &str,
+        index: usize,
+        voxel_loader: &VoxelLoader,
+    ) -> Result<Block> {
+        match kind {
+            BlockModelKind::BlockModel => {
+                self.load_block_from_block_model_by_index(index, voxel_loader)
+            }
+            BlockModelKind::NodeFolder => self.load_block_from_node_folder(name, voxel_loader),
+        }
+    }
+
+    pub fn load_node(&mut self, name: &str, voxel_loader: &VoxelLoader) -> Result<NodeID> {
         let (model_index, rot) = voxel_loader.find_model_by_name(name)?;
         let node = voxel_loader.load_node_model(model_index)?;
 
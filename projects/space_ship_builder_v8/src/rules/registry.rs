@@ -0,0 +1,89 @@
+use crate::rules::manifest::FolderDefinition;
+use crate::rules::solver::{Solver, SolverFunctions};
+use crate::rules::Rules;
+use crate::world::data::block::BlockNameIndex;
+use crate::world::data::voxel_loader::VoxelLoader;
+use anyhow::{bail, Result};
+use log::info;
+
+/// Solvers are shared between the rayon threads while ticking.
+pub type DynSolver = Box<dyn SolverFunctions + Send + Sync>;
+
+/// Makes the solver for the block name index from the folders of the manifest entry.
+/// The block name is already added to the rules, so the solver can require its own blocks.
+pub type SolverFactory = Box<
+    dyn Fn(&mut Rules, &VoxelLoader, BlockNameIndex, &[FolderDefinition]) -> Result<DynSolver>
+        + Send
+        + Sync,
+>;
+
+/// Solver kinds that can be used with Custom in the rule manifest.
+#[derive(Default)]
+pub struct SolverRegistry {
+    factories: Vec<(String, SolverFactory)>,
+}
+
+impl SolverRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A later registration of the same kind replaces the earlier one.
+    pub fn register(
+        &mut self,
+        kind: &str,
+        factory: impl Fn(&mut Rules, &VoxelLoader, BlockNameIndex, &[FolderDefinition]) -> Result<DynSolver>
+            + Send
+            + Sync
+            + 'static,
+    ) {
+        self.factories.retain(|(test_kind, _)| test_kind != kind);
+        self.factories.push((kind.to_owned(), Box::new(factory)));
+    }
+
+    pub fn has_kind(&self, kind: &str) -> bool {
+        self.factories
+            .iter()
+            .any(|(test_kind, _)| test_kind == kind)
+    }
+
+    pub fn make(
+        &self,
+        kind: &str,
+        rules: &mut Rules,
+        voxel_loader: &VoxelLoader,
+        block_name_index: BlockNameIndex,
+        folders: &[FolderDefinition],
+    ) -> Result<DynSolver> {
+        let Some((_, factory)) = self
+            .factories
+            .iter()
+            .find(|(test_kind, _)| test_kind == kind)
+        else {
+            bail!("Solver kind {kind} is not registered");
+        };
+
+        factory(rules, voxel_loader, block_name_index, folders)
+    }
+}
+
+impl Rules {
+    pub fn make_custom(
+        &mut self,
+        name: &str,
+        kind: &str,
+        folders: &[FolderDefinition],
+        voxel_loader: &VoxelLoader,
+        registry: &SolverRegistry,
+    ) -> Result<()> {
+        info!("Making {name} with {kind}");
+
+        let block_name_index = self.add_block_name(name);
+
+        let solver = registry.make(kind, self, voxel_loader, block_name_index, folders)?;
+        self.solvers.push(Solver::Custom(solver));
+
+        info!("Making {name} Done");
+        Ok(())
+    }
+}
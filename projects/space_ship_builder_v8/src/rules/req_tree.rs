@@ -1,215 +1,160 @@
-use crate::rules::Prio;
-use crate::world::data::block::Block;
-use crate::world::data::node::NodeID;
+use crate::world::block_object::BlockObject;
+use crate::world::data::block::BlockNameIndex;
+use bitcode::{Decode, Encode};
+use glam::IVec3;
 use log::{debug, info};
-use octa_force::glam::IVec3;
+use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Clone, Debug, Default)]
+/// The reqs of one entry. Each offset lists the block names that are allowed there.
+/// Offsets that are not listed allow every block name.
+pub type ReqTreeEntry = Vec<(IVec3, Vec<BlockNameIndex>)>;
+
+/// Decision tree over the block names around a block. Every node tests the block name at one
+/// offset, the leafs hold the indices of all entries whose reqs are fulfilled.
+/// Subtrees with the same entries are shared.
+#[derive(Clone, Debug)]
 pub struct BroadReqTree {
+    pub root: ReqTreeChild,
     pub nodes: Vec<BroadReqTreeNode>,
+    /// The first leaf is always empty.
     pub leafs: Vec<Vec<usize>>,
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct BroadReqTreeNode {
     pub offset: IVec3,
-    pub positive_child: usize,
-    pub negative_child: usize,
-    pub positive_leaf: bool,
-    pub negative_leaf: bool,
+    /// The child for every block name that an entry requires at the offset.
+    pub children: Vec<(BlockNameIndex, ReqTreeChild)>,
+    /// The child for all other block names.
+    pub other_child: ReqTreeChild,
 }
 
-#[derive(Clone)]
-struct BuildNode {
-    ids: Vec<usize>,
-    positive_child: Option<usize>,
-    negative_child: Option<usize>,
-    level: usize,
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Encode, Decode)]
+pub enum ReqTreeChild {
+    Node(usize),
+    Leaf(usize),
 }
 
-impl BroadReqTree {
-    pub fn new(req_list: &[(Vec<(IVec3, Vec<Block>)>, Block, Prio)], index_offset: usize) -> Self {
-        let offset_usage = Self::get_offset_usage(req_list);
-
-        let mut build_nodes = vec![BuildNode {
-            ids: (0..req_list.len()).collect(),
-            negative_child: None,
-            positive_child: None,
-            level: 0,
-        }];
-        let mut current_build_node = 0;
-
-        loop {
-            if current_build_node % 1000 == 0 {
-                debug!("Building Broad Req Tree Helper ... {current_build_node}");
-            }
-
-            if current_build_node >= build_nodes.len() {
-                break;
-            }
-
-            let build_node = build_nodes[current_build_node].to_owned();
-
-            if build_node.level >= offset_usage.len() {
-                current_build_node += 1;
-                continue;
-            }
-
-            let offset = offset_usage[build_node.level].0;
-
-            let mut empty_ids = vec![];
-            let mut some_ids = vec![];
-
-            for &i in build_node.ids.iter() {
-                let mut hast_offset = false;
-                let mut empty_pass = false;
-                let mut some_pass = false;
-
-                for (req_offset, req_blocks) in req_list[i].0.iter() {
-                    if *req_offset == offset {
-                        hast_offset = true;
-                        for req_block in req_blocks {
-                            let req_empty =
-                                *req_block == Block::from_single_node_id(NodeID::empty());
-
-                            if !req_empty {
-                                some_pass = true;
-                            }
-                            if req_empty {
-                                empty_pass = true;
-                            }
-                        }
-                    }
-                }
-
-                if empty_pass || !hast_offset {
-                    empty_ids.push(i);
-                }
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ReqTreeStats {
+    pub num_nodes: usize,
+    pub num_leafs: usize,
+    pub max_depth: usize,
+    pub max_leaf_size: usize,
+    pub mean_leaf_size: f32,
+}
 
-                if some_pass || !hast_offset {
-                    some_ids.push(i);
-                }
-            }
+struct TreeBuilder<'a> {
+    entries: &'a [ReqTreeEntry],
+    offsets: Vec<IVec3>,
+    index_offset: usize,
+    tree: BroadReqTree,
+    built: HashMap<(Vec<usize>, usize), ReqTreeChild>,
+    leaf_lookup: HashMap<Vec<usize>, usize>,
+}
 
-            if (!empty_ids.is_empty() || !some_ids.is_empty()) && empty_ids != some_ids {
-                build_nodes[current_build_node].negative_child = Some(build_nodes.len());
-                build_nodes.push(BuildNode {
-                    ids: empty_ids,
-                    positive_child: None,
-                    negative_child: None,
-                    level: build_node.level + 1,
-                });
+impl Default for ReqTreeChild {
+    fn default() -> Self {
+        ReqTreeChild::Leaf(0)
+    }
+}
 
-                build_nodes[current_build_node].positive_child = Some(build_nodes.len());
-                build_nodes.push(BuildNode {
-                    ids: some_ids,
-                    positive_child: None,
-                    negative_child: None,
-                    level: build_node.level + 1,
-                });
-                current_build_node += 1;
-            } else if !empty_ids.is_empty() && empty_ids == some_ids {
-                build_nodes[current_build_node].level = build_node.level + 1;
-            } else {
-                current_build_node += 1;
-            }
+impl Default for BroadReqTree {
+    fn default() -> Self {
+        BroadReqTree {
+            root: ReqTreeChild::default(),
+            nodes: vec![],
+            leafs: vec![vec![]],
         }
+    }
+}
 
-        let mut nodes = vec![BroadReqTreeNode::default()];
-        let mut leafs = vec![vec![]];
-        let mut current_node = 0;
-        let mut map_node_to_build_node = vec![0];
+impl BroadReqTree {
+    /// index_offset is added to the entry indices in the leafs.
+    pub fn new(entries: &[ReqTreeEntry], index_offset: usize) -> Self {
+        let mut builder = TreeBuilder {
+            entries,
+            offsets: Self::get_offset_usage(entries)
+                .into_iter()
+                .map(|(offset, _)| offset)
+                .collect(),
+            index_offset,
+            tree: BroadReqTree::default(),
+            built: HashMap::new(),
+            leaf_lookup: HashMap::new(),
+        };
+        builder.leaf_lookup.insert(vec![], 0);
+
+        let root = builder.build((0..entries.len()).collect(), 0);
+        let mut tree = builder.tree;
+        tree.root = root;
+
+        info!("Broad Req Tree: {}", tree.get_stats());
+
+        tree
+    }
 
+    /// The entries whose reqs are fulfilled at world_block_pos, in the order they were passed.
+    pub fn get_entries(&self, block_object: &BlockObject, world_block_pos: IVec3) -> &[usize] {
+        let mut child = self.root;
         loop {
-            if current_node % 1000 == 0 {
-                debug!("Building Broad Req Tree ... {current_node}");
-            }
-
-            if current_node >= nodes.len() {
-                break;
-            }
-
-            let build_node = &build_nodes[map_node_to_build_node[current_node]];
-            nodes[current_node].offset = offset_usage[build_node.level].0;
-
-            assert!(build_node.positive_child.is_some() && build_node.negative_child.is_some());
-
-            let negative_build_node = &build_nodes[build_node.negative_child.unwrap()];
-            if negative_build_node.positive_child.is_none()
-                || negative_build_node.negative_child.is_none()
-            {
-                nodes[current_node].negative_leaf = true;
-
-                if negative_build_node.ids.is_empty() {
-                    nodes[current_node].negative_child = 0;
-                } else {
-                    let new_leaf: Vec<usize> = negative_build_node
-                        .ids
+            match child {
+                ReqTreeChild::Leaf(leaf_index) => return &self.leafs[leaf_index],
+                ReqTreeChild::Node(node_index) => {
+                    let node = &self.nodes[node_index];
+
+                    let block_name_index = block_object
+                        .get_block_name_from_world_block_pos(world_block_pos + node.offset);
+                    child = node
+                        .children
                         .iter()
-                        .map(|i| i + index_offset)
-                        .collect();
-
-                    let leaf_index = leafs.iter().position(|l| **l == new_leaf);
-                    if leaf_index.is_some() {
-                        nodes[current_node].negative_child = leaf_index.unwrap();
-                    } else {
-                        nodes[current_node].negative_child = leafs.len();
-                        leafs.push(new_leaf);
-                    }
+                        .find(|(test_block_name_index, _)| {
+                            *test_block_name_index == block_name_index
+                        })
+                        .map_or(node.other_child, |(_, child)| *child);
                 }
-            } else {
-                nodes[current_node].negative_child = nodes.len();
-                nodes.push(BroadReqTreeNode::default());
-                map_node_to_build_node.push(build_node.negative_child.unwrap());
             }
+        }
+    }
 
-            let positive_build_node = &build_nodes[build_node.positive_child.unwrap()];
-            if positive_build_node.positive_child.is_none()
-                || positive_build_node.negative_child.is_none()
-            {
-                nodes[current_node].positive_leaf = true;
+    pub fn get_stats(&self) -> ReqTreeStats {
+        let num_leaf_entries: usize = self.leafs.iter().map(|leaf| leaf.len()).sum();
 
-                if positive_build_node.ids.is_empty() {
-                    nodes[current_node].positive_child = 0;
-                } else {
-                    let new_leaf: Vec<usize> = positive_build_node
-                        .ids
-                        .iter()
-                        .map(|i| i + index_offset)
-                        .collect();
+        ReqTreeStats {
+            num_nodes: self.nodes.len(),
+            num_leafs: self.leafs.len(),
+            max_depth: self.get_depth(self.root, &mut vec![None; self.nodes.len()]),
+            max_leaf_size: self.leafs.iter().map(|leaf| leaf.len()).max().unwrap_or(0),
+            mean_leaf_size: num_leaf_entries as f32 / self.leafs.len() as f32,
+        }
+    }
 
-                    let leaf_index = leafs.iter().position(|l| **l == new_leaf);
-                    if leaf_index.is_some() {
-                        nodes[current_node].positive_child = leaf_index.unwrap();
-                    } else {
-                        nodes[current_node].positive_child = leafs.len();
-                        leafs.push(new_leaf);
-                    }
+    fn get_depth(&self, child: ReqTreeChild, depths: &mut [Option<usize>]) -> usize {
+        match child {
+            ReqTreeChild::Leaf(_) => 0,
+            ReqTreeChild::Node(node_index) => {
+                if let Some(depth) = depths[node_index] {
+                    return depth;
                 }
-            } else {
-                nodes[current_node].positive_child = nodes.len();
-                nodes.push(BroadReqTreeNode::default());
-                map_node_to_build_node.push(build_node.positive_child.unwrap());
-            }
-
-            current_node += 1;
-        }
 
-        info!(
-            "Broad Req Tree has {} nodes and {} leafs",
-            nodes.len(),
-            leafs.len()
-        );
+                let node = &self.nodes[node_index];
+                let mut depth = self.get_depth(node.other_child, depths);
+                for (_, child) in node.children.iter() {
+                    depth = depth.max(self.get_depth(*child, depths));
+                }
+                depths[node_index] = Some(depth + 1);
 
-        BroadReqTree { nodes, leafs }
+                depth + 1
+            }
+        }
     }
 
-    fn get_offset_usage(
-        req_list: &[(Vec<(IVec3, Vec<Block>)>, Block, Prio)],
-    ) -> Vec<(IVec3, usize)> {
+    /// The offsets sorted by how many entries have reqs at them.
+    fn get_offset_usage(entries: &[ReqTreeEntry]) -> Vec<(IVec3, usize)> {
         let mut offsets = vec![];
 
-        for (reqs, _, _) in req_list {
+        for reqs in entries {
             for (offset, _) in reqs {
                 let counter = offsets.iter_mut().find_map(|(test_offset, counter)| {
                     if test_offset == offset {
@@ -219,8 +164,8 @@ impl BroadReqTree {
                     }
                 });
 
-                if counter.is_some() {
-                    *counter.unwrap() += 1;
+                if let Some(counter) = counter {
+                    *counter += 1;
                 } else {
                     offsets.push((*offset, 1))
                 }
@@ -231,3 +176,105 @@ impl BroadReqTree {
         offsets
     }
 }
+
+impl TreeBuilder<'_> {
+    fn build(&mut self, ids: Vec<usize>, level: usize) -> ReqTreeChild {
+        // Offsets none of the entries has reqs at do not split anything.
+        let mut level = level;
+        while level < self.offsets.len()
+            && !ids
+                .iter()
+                .any(|i| self.get_req(*i, self.offsets[level]).is_some())
+        {
+            level += 1;
+        }
+
+        if ids.is_empty() || level >= self.offsets.len() {
+            return self.add_leaf(ids);
+        }
+
+        let key = (ids, level);
+        if let Some(child) = self.built.get(&key) {
+            return *child;
+        }
+        let (ids, level) = key;
+
+        let offset = self.offsets[level];
+        let mut block_names = vec![];
+        for &i in ids.iter() {
+            for block_name_index in self.get_req(i, offset).into_iter().flatten() {
+                if !block_names.contains(block_name_index) {
+                    block_names.push(*block_name_index);
+                }
+            }
+        }
+        block_names.sort();
+
+        let node_index = self.tree.nodes.len();
+        self.tree.nodes.push(BroadReqTreeNode {
+            offset,
+            ..Default::default()
+        });
+        if node_index.is_multiple_of(1000) {
+            debug!("Building Broad Req Tree ... {node_index}");
+        }
+
+        let mut children = vec![];
+        for block_name_index in block_names {
+            let child_ids = ids
+                .iter()
+                .copied()
+                .filter(|i| {
+                    self.get_req(*i, offset)
+                        .is_none_or(|req_names| req_names.contains(&block_name_index))
+                })
+                .collect();
+            children.push((block_name_index, self.build(child_ids, level + 1)));
+        }
+
+        let other_ids = ids
+            .iter()
+            .copied()
+            .filter(|i| self.get_req(*i, offset).is_none())
+            .collect();
+        let other_child = self.build(other_ids, level + 1);
+
+        self.tree.nodes[node_index].children = children;
+        self.tree.nodes[node_index].other_child = other_child;
+
+        let child = ReqTreeChild::Node(node_index);
+        self.built.insert((ids, level), child);
+        child
+    }
+
+    fn get_req(&self, i: usize, offset: IVec3) -> Option<&Vec<BlockNameIndex>> {
+        self.entries[i]
+            .iter()
+            .find(|(test_offset, _)| *test_offset == offset)
+            .map(|(_, block_names)| block_names)
+    }
+
+    fn add_leaf(&mut self, ids: Vec<usize>) -> ReqTreeChild {
+        let leaf: Vec<usize> = ids.iter().map(|i| i + self.index_offset).collect();
+
+        if let Some(leaf_index) = self.leaf_lookup.get(&leaf) {
+            return ReqTreeChild::Leaf(*leaf_index);
+        }
+
+        let leaf_index = self.tree.leafs.len();
+        self.tree.leafs.push(leaf.to_owned());
+        self.leaf_lookup.insert(leaf, leaf_index);
+
+        ReqTreeChild::Leaf(leaf_index)
+    }
+}
+
+impl fmt::Display for ReqTreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} leafs, max depth {}, max leaf size {}, mean leaf size {:.1}",
+            self.num_nodes, self.num_leafs, self.max_depth, self.max_leaf_size, self.mean_leaf_size
+        )
+    }
+}
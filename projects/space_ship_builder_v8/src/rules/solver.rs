@@ -1,12 +1,13 @@
 use crate::rules::empty::EmptySolver;
 use crate::rules::hull::HullSolver;
+use crate::rules::registry::DynSolver;
 use crate::rules::stone::StoneSolver;
 use crate::rules::Prio;
 use crate::world::block_object::possible_blocks::PossibleBlocks;
 use crate::world::block_object::{BlockObject, ChunkIndex};
 use crate::world::data::block::{Block, BlockIndex};
 use enum_as_inner::EnumAsInner;
-use octa_force::glam::IVec3;
+use glam::IVec3;
 
 pub type SolverCacheIndex = usize;
 
@@ -16,13 +17,15 @@ pub enum Solver {
     Empty(EmptySolver),
     Hull(HullSolver),
     Stone(StoneSolver),
+    /// Solvers registered in a SolverRegistry.
+    Custom(DynSolver),
 }
 
 #[enum_delegate::register]
 pub trait SolverFunctions {
     fn block_check_reset(
         &self,
-        block_object: &mut BlockObject,
+        block_object: &BlockObject,
         block_index: BlockIndex,
         chunk_index: ChunkIndex,
         world_block_pos: IVec3,
@@ -38,9 +41,10 @@ pub trait SolverFunctions {
         vec![]
     }
 
+    /// Only reads the object, so blocks of different chunks can be checked in parallel.
     fn block_check(
         &self,
-        ship: &mut BlockObject,
+        block_object: &BlockObject,
         block_index: BlockIndex,
         chunk_index: ChunkIndex,
         world_block_pos: IVec3,
@@ -69,3 +73,86 @@ pub trait SolverFunctions {
 
     fn get_block_from_cache_index(&self, index: usize) -> Block;
 }
+
+impl SolverFunctions for DynSolver {
+    fn block_check_reset(
+        &self,
+        block_object: &BlockObject,
+        block_index: BlockIndex,
+        chunk_index: ChunkIndex,
+        world_block_pos: IVec3,
+    ) -> Vec<SolverCacheIndex> {
+        self.as_ref()
+            .block_check_reset(block_object, block_index, chunk_index, world_block_pos)
+    }
+
+    fn debug_block_check_reset(
+        &self,
+        block_object: &mut BlockObject,
+        block_index: BlockIndex,
+        chunk_index: ChunkIndex,
+        world_block_pos: IVec3,
+    ) -> Vec<(SolverCacheIndex, Vec<(IVec3, bool)>)> {
+        self.as_ref().debug_block_check_reset(
+            block_object,
+            block_index,
+            chunk_index,
+            world_block_pos,
+        )
+    }
+
+    fn block_check(
+        &self,
+        block_object: &BlockObject,
+        block_index: BlockIndex,
+        chunk_index: ChunkIndex,
+        world_block_pos: IVec3,
+        cache: Vec<SolverCacheIndex>,
+    ) -> Vec<SolverCacheIndex> {
+        self.as_ref().block_check(
+            block_object,
+            block_index,
+            chunk_index,
+            world_block_pos,
+            cache,
+        )
+    }
+
+    fn debug_block_check(
+        &self,
+        block_object: &mut BlockObject,
+        block_index: BlockIndex,
+        chunk_index: ChunkIndex,
+        world_block_pos: IVec3,
+        cache: &[PossibleBlocks],
+    ) -> Vec<(SolverCacheIndex, Vec<(IVec3, bool)>)> {
+        self.as_ref().debug_block_check(
+            block_object,
+            block_index,
+            chunk_index,
+            world_block_pos,
+            cache,
+        )
+    }
+
+    fn get_block(
+        &self,
+        block_object: &mut BlockObject,
+        block_index: BlockIndex,
+        chunk_index: ChunkIndex,
+        world_block_pos: IVec3,
+        cache: Vec<SolverCacheIndex>,
+    ) -> (Block, Prio, usize) {
+        self.as_ref().get_block(
+            block_object,
+            block_index,
+            chunk_index,
+            world_block_pos,
+            cache,
+        )
+    }
+
+    fn get_block_from_cache_index(&self, index: usize) -> Block {
+        self.as_ref().get_block_from_cache_index(index)
+    }
+}
@@ -1,3 +1,4 @@
+use crate::rules::empty::EMPTY_BLOCK_NAME_INDEX;
 use crate::rules::marching_cubes::MarchingCubes;
 use crate::rules::solver::{Solver, SolverCacheIndex, SolverFunctions};
 use crate::rules::{Prio, Rules};
@@ -5,11 +6,9 @@ use crate::world::block_object::{BlockObject, ChunkIndex};
 use crate::world::data::block::{Block, BlockIndex, BlockNameIndex};
 use crate::world::data::node::NodeID;
 use crate::world::data::voxel_loader::VoxelLoader;
+use anyhow::Result;
+use glam::IVec3;
 use log::info;
-use octa_force::{anyhow::Result, glam::IVec3};
-
-const STONE_BLOCK_NAME: &str = "Stone";
-const STONE_MARCHING_CUBES_NAME: &str = "Stone-Marching-Cubes";
 
 const MARCHING_CUBES_CACHE_INDEX: usize = 0;
 
@@ -19,17 +18,30 @@ pub struct StoneSolver {
 }
 
 impl Rules {
-    pub fn make_stone(&mut self, voxel_loader: &VoxelLoader) -> Result<()> {
-        info!("Making Stone");
+    pub fn make_stone(
+        &mut self,
+        name: &str,
+        marching_cubes_folder: &str,
+        empty_blocks: &[String],
+        smooth: bool,
+        voxel_loader: &VoxelLoader,
+    ) -> Result<()> {
+        info!("Making {name}");
+
+        let stone_block_name_index = self.add_block_name(name);
 
-        let stone_block_name_index = self.block_names.len() as BlockNameIndex;
-        self.block_names.push(STONE_BLOCK_NAME.to_owned());
+        let mut empty_block_name_indices = vec![EMPTY_BLOCK_NAME_INDEX];
+        for empty_block in empty_blocks {
+            empty_block_name_indices.push(self.find_block_name_index(empty_block)?);
+        }
 
         let marching_cubes = MarchingCubes::new(
             self,
             voxel_loader,
-            STONE_MARCHING_CUBES_NAME,
+            marching_cubes_folder,
             stone_block_name_index,
+            empty_block_name_indices,
+            smooth,
         )?;
         let stone_solver = StoneSolver {
             block_name_index: stone_block_name_index,
@@ -38,7 +50,7 @@ impl Rules {
 
         self.solvers.push(Solver::Stone(stone_solver));
 
-        info!("Making Stone Done");
+        info!("Making {name} Done");
         Ok(())
     }
 }
@@ -46,7 +58,7 @@ impl Rules {
 impl SolverFunctions for StoneSolver {
     fn block_check_reset(
         &self,
-        block_object: &mut BlockObject,
+        block_object: &BlockObject,
         block_index: BlockIndex,
         chunk_index: ChunkIndex,
         _: IVec3,
@@ -60,7 +72,7 @@ impl SolverFunctions for StoneSolver {
 
     fn block_check(
         &self,
-        _: &mut BlockObject,
+        _: &BlockObject,
         _: BlockIndex,
         _: ChunkIndex,
         _: IVec3,
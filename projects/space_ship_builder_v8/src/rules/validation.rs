@@ -0,0 +1,504 @@
+use crate::math::oct_positions;
+use crate::math::rotation::Rot;
+use crate::rules::hull::{HullSolver, MultiBlockReq};
+use crate::rules::manifest::{FolderDefinition, RuleManifest, SolverDefinition};
+use crate::rules::registry::SolverRegistry;
+use crate::rules::solver::Solver;
+use crate::rules::stone::StoneSolver;
+use crate::rules::{parse_req_folder_model_name, BlockModelKind, ReqFolderModel, Rules};
+use crate::world::data::block::{Block, BlockNameIndex};
+use crate::world::data::node::NodeID;
+use crate::world::data::voxel_loader::VoxelLoader;
+use glam::{IVec3, UVec3};
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RuleIssueKind {
+    /// A folder of the manifest is missing in the .vox file or is rotated.
+    InvalidFolder,
+    /// A model name does not follow the naming scheme of its folder.
+    InvalidName,
+    /// A block model could not be loaded or is not 2x2x2 nodes.
+    InvalidModel,
+    MissingOctPosition,
+    UnsupportedNodeFolderSize,
+    /// Reqs that can never be met or blocks that can never be told apart.
+    ConflictingRequirements,
+    /// A multi block requires a neighbor that no solver places.
+    UnreachableMultiBlock,
+    MissingMarchingCubesPermutation,
+    /// The solver of a manifest entry could not be made.
+    SolverError,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleIssue {
+    pub kind: RuleIssueKind,
+    /// The manifest entry the issue belongs to.
+    pub block_name: String,
+    /// The folder or model the issue was found in.
+    pub source: String,
+    pub message: String,
+}
+
+/// Every problem of the rules in a .vox file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuleReport {
+    pub issues: Vec<RuleIssue>,
+}
+
+impl RuleReport {
+    /// Makes all blocks of the manifest like Rules::new_with_registry, but collects every problem
+    /// instead of stopping at the first.
+    pub fn new(
+        voxel_loader: &VoxelLoader,
+        manifest: &RuleManifest,
+        registry: &SolverRegistry,
+    ) -> Self {
+        let mut report = RuleReport::default();
+        let mut rules = Rules::new_without_blocks(voxel_loader);
+        rules.add_manifest_block_names(manifest);
+
+        for block in manifest.blocks.iter() {
+            let result = match &block.solver {
+                SolverDefinition::Empty => {
+                    rules.make_empty(&block.name);
+                    Ok(())
+                }
+                SolverDefinition::Hull {
+                    basic_folders,
+                    multi_folders,
+                } => {
+                    let num_name_issues = report.count(RuleIssueKind::InvalidName);
+                    for (folder, basic) in basic_folders
+                        .iter()
+                        .map(|folder| (folder, true))
+                        .chain(multi_folders.iter().map(|folder| (folder, false)))
+                    {
+                        report.check_req_folder(
+                            &block.name,
+                            folder,
+                            basic,
+                            &rules.block_names,
+                            voxel_loader,
+                        );
+                    }
+
+                    // Loading fails on the same names and would only report them again.
+                    if report.count(RuleIssueKind::InvalidName) == num_name_issues {
+                        rules.make_hull(&block.name, basic_folders, multi_folders, voxel_loader)
+                    } else {
+                        Ok(())
+                    }
+                }
+                SolverDefinition::Stone {
+                    marching_cubes_folder,
+                    empty_blocks,
+                    smooth,
+                } => rules.make_stone(
+                    &block.name,
+                    marching_cubes_folder,
+                    empty_blocks,
+                    *smooth,
+                    voxel_loader,
+                ),
+                SolverDefinition::Custom { kind, folders } => {
+                    rules.make_custom(&block.name, kind, folders, voxel_loader, registry)
+                }
+            };
+
+            if let Err(err) = result {
+                report.add(
+                    RuleIssueKind::SolverError,
+                    &block.name,
+                    &block.name,
+                    err.to_string(),
+                );
+            }
+        }
+
+        let solvers = std::mem::take(&mut rules.solvers);
+
+        // What a custom solver places is not known, so every block could be reachable.
+        let reachable_node_ids = if solvers
+            .iter()
+            .any(|solver| matches!(solver, Solver::Custom(_)))
+        {
+            None
+        } else {
+            Some(get_reachable_node_ids(&solvers))
+        };
+
+        for solver in solvers.iter() {
+            match solver {
+                Solver::Hull(hull) => {
+                    report.check_hull(hull, reachable_node_ids.as_ref(), &mut rules)
+                }
+                Solver::Stone(stone) => report.check_stone(stone, &rules),
+                _ => {}
+            }
+        }
+
+        report
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn count(&self, kind: RuleIssueKind) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.kind == kind)
+            .count()
+    }
+
+    fn add(&mut self, kind: RuleIssueKind, block_name: &str, source: &str, message: String) {
+        self.issues.push(RuleIssue {
+            kind,
+            block_name: block_name.to_owned(),
+            source: source.to_owned(),
+            message,
+        })
+    }
+
+    fn check_req_folder(
+        &mut self,
+        block_name: &str,
+        folder: &FolderDefinition,
+        basic: bool,
+        block_names: &[String],
+        voxel_loader: &VoxelLoader,
+    ) {
+        let result = voxel_loader.get_name_folder(&folder.name);
+        if let Err(err) = result {
+            self.add(
+                RuleIssueKind::InvalidFolder,
+                block_name,
+                &folder.name,
+                err.to_string(),
+            );
+            return;
+        }
+        let (models, rot) = result.unwrap();
+
+        if rot != Rot::IDENTITY {
+            self.add(
+                RuleIssueKind::InvalidFolder,
+                block_name,
+                &folder.name,
+                format!("Block Req Folder {} Rot should be IDENTITY", folder.name),
+            );
+        }
+
+        let mut req_names: Vec<(IVec3, &str)> = vec![];
+        for (name, index, _, pos) in models.iter() {
+            match parse_req_folder_model_name(name, basic) {
+                Err(err) => {
+                    self.add(
+                        RuleIssueKind::InvalidName,
+                        block_name,
+                        name,
+                        err.to_string(),
+                    );
+                }
+                Ok(ReqFolderModel::BlockName(req_block_name)) => {
+                    if req_block_name != block_name
+                        && !block_names
+                            .iter()
+                            .any(|test_name| test_name == req_block_name)
+                    {
+                        self.add(
+                            RuleIssueKind::InvalidName,
+                            block_name,
+                            name,
+                            format!("{req_block_name} is not a block of the manifest"),
+                        );
+                    }
+
+                    let other = req_names.iter().find(|(test_pos, test_name)| {
+                        test_pos == pos && *test_name != req_block_name
+                    });
+                    if let Some((_, other_name)) = other {
+                        self.add(
+                            RuleIssueKind::ConflictingRequirements,
+                            block_name,
+                            &folder.name,
+                            format!("{pos} requires {other_name} and {req_block_name}"),
+                        );
+                    }
+
+                    req_names.push((*pos, req_block_name));
+                }
+                Ok(ReqFolderModel::Block { kind, .. } | ReqFolderModel::Req(kind)) => match kind {
+                    BlockModelKind::BlockModel => {
+                        self.check_block_model(block_name, name, *index, voxel_loader)
+                    }
+                    BlockModelKind::NodeFolder => {
+                        self.check_node_folder(block_name, name, voxel_loader)
+                    }
+                },
+            }
+        }
+    }
+
+    fn check_block_model(
+        &mut self,
+        block_name: &str,
+        name: &str,
+        index: usize,
+        voxel_loader: &VoxelLoader,
+    ) {
+        let result = voxel_loader
+            .find_model_by_index(index)
+            .and_then(|(model_index, _)| voxel_loader.load_multi_node_model(model_index));
+
+        match result {
+            Err(err) => self.add(
+                RuleIssueKind::InvalidModel,
+                block_name,
+                name,
+                err.to_string(),
+            ),
+            Ok((size, _)) if size != IVec3::ONE * 2 => self.add(
+                RuleIssueKind::InvalidModel,
+                block_name,
+                name,
+                format!("{} not multi block Size of [2, 2, 2]", size),
+            ),
+            _ => {}
+        }
+    }
+
+    fn check_node_folder(&mut self, block_name: &str, name: &str, voxel_loader: &VoxelLoader) {
+        let result = voxel_loader.load_node_folder_models(name);
+        if let Err(err) = result {
+            self.add(
+                RuleIssueKind::InvalidModel,
+                block_name,
+                name,
+                err.to_string(),
+            );
+            return;
+        }
+        let (size, nodes) = result.unwrap();
+
+        if size != UVec3::ONE * 4 {
+            self.add(
+                RuleIssueKind::UnsupportedNodeFolderSize,
+                block_name,
+                name,
+                format!("Node folder size is {} not (4, 4, 4)", size),
+            );
+        }
+
+        for offset in oct_positions() {
+            if !nodes
+                .iter()
+                .any(|(_, _, pos)| offset.as_uvec3() == *pos / 4)
+            {
+                self.add(
+                    RuleIssueKind::MissingOctPosition,
+                    block_name,
+                    name,
+                    format!("Offset {} is not in node folder", offset),
+                );
+            }
+        }
+    }
+
+    fn check_hull(
+        &mut self,
+        hull: &HullSolver,
+        reachable_node_ids: Option<&HashSet<NodeID>>,
+        rules: &mut Rules,
+    ) {
+        let block_name = rules.block_names[hull.block_name_index as usize].to_owned();
+
+        // The blocks are permutated, so every conflict shows up once per rotation.
+        let mut reported: Vec<(Block, Block)> = vec![];
+        for i in 0..hull.basic_blocks.len() {
+            let (reqs, block, prio) = hull.basic_blocks.get_block(i);
+
+            for j in (i + 1)..hull.basic_blocks.len() {
+                let (other_reqs, other_block, other_prio) = hull.basic_blocks.get_block(j);
+
+                if prio == other_prio
+                    && is_same_basic_reqs(reqs, other_reqs)
+                    && block.is_duplicate(other_block, rules).is_none()
+                    && !is_reported(&reported, block, other_block, rules)
+                {
+                    self.add(
+                        RuleIssueKind::ConflictingRequirements,
+                        &block_name,
+                        "Basic Blocks",
+                        format!("Two blocks with {prio:?} have the same reqs {reqs:?}"),
+                    );
+                    reported.push((*block, *other_block));
+                }
+            }
+        }
+
+        let mut reported: Vec<(Block, Block)> = vec![];
+        for (i, (reqs, block, prio)) in hull.multi_blocks.iter().enumerate() {
+            for (other_reqs, other_block, other_prio) in hull.multi_blocks[(i + 1)..].iter() {
+                if prio == other_prio
+                    && is_same_multi_reqs(reqs, other_reqs)
+                    && block.is_duplicate(other_block, rules).is_none()
+                    && !is_reported(&reported, block, other_block, rules)
+                {
+                    self.add(
+                        RuleIssueKind::ConflictingRequirements,
+                        &block_name,
+                        "Multi Blocks",
+                        format!(
+                            "Two blocks with {prio:?} have the same reqs at {:?}",
+                            reqs.iter()
+                                .map(|(offset, _, _)| *offset)
+                                .collect::<Vec<_>>()
+                        ),
+                    );
+                    reported.push((*block, *other_block));
+                }
+            }
+        }
+
+        if reachable_node_ids.is_none() {
+            return;
+        }
+        let reachable_node_ids = reachable_node_ids.unwrap();
+
+        let mut reported: Vec<Block> = vec![];
+        for (reqs, block, prio) in hull.multi_blocks.iter() {
+            let unreachable_offset =
+                reqs.iter()
+                    .find_map(|(offset, req_blocks, req_block_names)| {
+                        let reachable = !req_block_names.is_empty()
+                            || req_blocks.iter().any(|req_block| {
+                                req_block.node_ids.iter().all(|node_id| {
+                                    node_id.is_any() || reachable_node_ids.contains(node_id)
+                                })
+                            });
+
+                        if reachable {
+                            None
+                        } else {
+                            Some(*offset)
+                        }
+                    });
+
+            let Some(unreachable_offset) = unreachable_offset else {
+                continue;
+            };
+
+            if !reported
+                .iter()
+                .any(|test_block| test_block.is_duplicate(block, rules).is_some())
+            {
+                self.add(
+                    RuleIssueKind::UnreachableMultiBlock,
+                    &block_name,
+                    "Multi Blocks",
+                    format!(
+                        "Block with {prio:?} requires a block at {unreachable_offset} that no solver places"
+                    ),
+                );
+                reported.push(*block);
+            }
+        }
+    }
+
+    fn check_stone(&mut self, stone: &StoneSolver, rules: &Rules) {
+        let block_name = &rules.block_names[stone.block_name_index as usize];
+
+        for node_req in stone.marching_cubes.synthesized_node_reqs.iter() {
+            let req: Vec<_> = node_req.into_iter().collect();
+
+            self.add(
+                RuleIssueKind::MissingMarchingCubesPermutation,
+                block_name,
+                "Marching Cubes",
+                format!("{req:?} missing. Its node is synthesized."),
+            );
+        }
+    }
+}
+
+impl fmt::Display for RuleReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for issue in self.issues.iter() {
+            writeln!(
+                f,
+                "{:?} in {} ({}): {}",
+                issue.kind, issue.block_name, issue.source, issue.message
+            )?;
+        }
+
+        write!(f, "{} issues found.", self.issues.len())
+    }
+}
+
+/// All nodes that the solvers can place.
+fn get_reachable_node_ids(solvers: &[Solver]) -> HashSet<NodeID> {
+    let mut node_ids = HashSet::new();
+    node_ids.insert(NodeID::empty());
+
+    for solver in solvers.iter() {
+        match solver {
+            Solver::Hull(hull) => {
+                for i in 0..hull.basic_blocks.len() {
+                    node_ids.extend(hull.basic_blocks.get_block(i).1.node_ids);
+                }
+                for (_, block, _) in hull.multi_blocks.iter() {
+                    node_ids.extend(block.node_ids);
+                }
+            }
+            Solver::Stone(stone) => node_ids.extend(stone.marching_cubes.get_node_ids()),
+            _ => {}
+        }
+    }
+
+    node_ids
+}
+
+fn is_same_basic_reqs(
+    reqs: &[(IVec3, BlockNameIndex)],
+    other_reqs: &[(IVec3, BlockNameIndex)],
+) -> bool {
+    reqs.len() == other_reqs.len() && reqs.iter().all(|req| other_reqs.contains(req))
+}
+
+fn is_same_multi_reqs(reqs: &[MultiBlockReq], other_reqs: &[MultiBlockReq]) -> bool {
+    reqs.len() == other_reqs.len()
+        && reqs.iter().all(|(offset, blocks, block_names)| {
+            other_reqs
+                .iter()
+                .any(|(other_offset, other_blocks, other_block_names)| {
+                    offset == other_offset
+                        && blocks.len() == other_blocks.len()
+                        && blocks.iter().all(|block| other_blocks.contains(block))
+                        && block_names.len() == other_block_names.len()
+                        && block_names
+                            .iter()
+                            .all(|block_name| other_block_names.contains(block_name))
+                })
+        })
+}
+
+/// Whether a rotation of the pair was already reported.
+fn is_reported(
+    reported: &[(Block, Block)],
+    block: &Block,
+    other: &Block,
+    rules: &mut Rules,
+) -> bool {
+    reported.iter().any(|(test_block, test_other)| {
+        [(test_block, test_other), (test_other, test_block)]
+            .into_iter()
+            .any(|(a, b)| {
+                a.is_duplicate(block, rules)
+                    .is_some_and(|rot| b.rotate(rot, rules) == *other)
+            })
+    })
+}
@@ -1,6 +1,7 @@
 use crate::math::random::get_random_vec3_from_min_size;
+use fastrand::Rng;
+use glam::Vec3;
 use log::warn;
-use octa_force::glam::Vec3;
 
 pub struct Metaball {
     pub points: Vec<(Vec3, f32, f32)>,
@@ -18,11 +19,12 @@ impl Metaball {
         num_points: usize,
         point_size: f32,
         point_strength: f32,
+        rng: &mut Rng,
     ) {
         let size = max - min;
 
         for _ in 0..num_points {
-            let pos = get_random_vec3_from_min_size(min, size);
+            let pos = get_random_vec3_from_min_size(min, size, rng);
             self.points.push((pos, point_strength, point_size));
         }
     }
@@ -51,12 +53,13 @@ impl Metaball {
         point_strength: f32,
         point_size: f32,
         iterations_per_point: usize,
+        rng: &mut Rng,
     ) {
         let size = max - min;
 
         for _ in 0..num_points {
             for i in 0..iterations_per_point {
-                let pos = get_random_vec3_from_min_size(min, size);
+                let pos = get_random_vec3_from_min_size(min, size, rng);
 
                 let field = self.get_field(pos);
                 if field < field_min || field > field_max {
@@ -1,17 +1,16 @@
 mod metaball;
 
-use crate::render::parallax::renderer::{ParallaxRenderer, RENDER_MODE_BASE};
-use crate::render::Renderer;
+use crate::rules::marching_cubes::DENSITY_ISO_LEVEL;
 use crate::rules::Rules;
 use crate::world::asteroid::metaball::Metaball;
 use crate::world::block_object::BlockObject;
 use crate::world::data::block::BlockNameIndex;
 use crate::world::data::node::VOXEL_PER_NODE_SIDE;
+use anyhow::{bail, Result};
 use fastnoise_lite::NoiseType;
+use fastrand::Rng;
+use glam::{ivec3, IVec3, Mat4, Vec3};
 use log::{debug, info};
-use octa_force::anyhow::{bail, Result};
-use octa_force::glam::{ivec3, IVec3, Mat4, Vec3};
-use octa_force::vulkan::{CommandBuffer, Context};
 use std::cmp::{max, min};
 use std::time::Duration;
 
@@ -36,26 +35,29 @@ pub struct AsteroidGenerationConfig {
 }
 
 impl AsteroidGenerator {
-    pub fn new(rules: &Rules) -> Self {
-        let asteroid_block_name_index = rules.get_block_name_index("Stone");
+    pub fn new(rules: &Rules) -> Result<Self> {
+        let Some(asteroid_block_name_index) = rules.asteroid_block_name_index else {
+            bail!("No block of the rule manifest is the asteroid block");
+        };
 
-        AsteroidGenerator {
+        Ok(AsteroidGenerator {
             asteroid_block_name_index,
             num_block_names: rules.block_names.len(),
-        }
+        })
     }
 
-    pub fn generate(&self, transform: Mat4, size: i32) -> BlockObject {
+    pub fn generate(&self, transform: Mat4, size: i32, rng: &mut Rng) -> BlockObject {
         let config = get_config_from_size(size).unwrap();
         info!("Asteroid Config: {:?}", config);
 
-        self.generate_from_config(transform, config)
+        self.generate_from_config(transform, config, rng)
     }
 
     pub fn generate_from_config(
         &self,
         transform: Mat4,
         config: AsteroidGenerationConfig,
+        rng: &mut Rng,
     ) -> BlockObject {
         let mut block_object =
             BlockObject::new(transform, ASTEROID_CHUNK_SIZE.x, self.num_block_names);
@@ -67,6 +69,7 @@ impl AsteroidGenerator {
             config.num_points,
             config.cut_off_dist,
             1.0,
+            rng,
         );
         metaball.gravity_merge(config.gravity_merge_strength);
 
@@ -80,6 +83,7 @@ impl AsteroidGenerator {
             -4.0,
             3.0,
             300,
+            rng,
         );
          */
 
@@ -89,7 +93,11 @@ impl AsteroidGenerator {
                 for z in (-size_twice)..size_twice {
                     let world_block_pos = ivec3(x, y, z);
 
-                    if metaball.get_field(world_block_pos.as_vec3()) > 0.5 {
+                    let field = metaball.get_field(world_block_pos.as_vec3());
+                    if field > 0.0 {
+                        block_object.set_density(world_block_pos, field);
+                    }
+                    if field > DENSITY_ISO_LEVEL {
                         block_object.place_block(world_block_pos, self.asteroid_block_name_index)
                     }
                 }
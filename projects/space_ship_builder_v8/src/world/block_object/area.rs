@@ -0,0 +1,111 @@
+use crate::math::all_sides_dirs;
+use crate::world::block_object::BlockObject;
+use anyhow::{bail, Result};
+use glam::{ivec3, IVec3};
+use std::collections::{HashSet, VecDeque};
+
+/// Flood fills bigger than this are stopped, because open space is never bounded.
+pub const MAX_FLOOD_FILL_BLOCKS: usize = 16384;
+
+/// Mirrors positions on a plane through the center of block pos along the axis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mirror {
+    pub axis: usize,
+    pub pos: i32,
+}
+
+impl Mirror {
+    pub fn new(axis: usize, pos: i32) -> Self {
+        Mirror { axis, pos }
+    }
+
+    pub fn mirror_pos(&self, mut world_block_pos: IVec3) -> IVec3 {
+        world_block_pos[self.axis] = self.pos * 2 - world_block_pos[self.axis];
+        world_block_pos
+    }
+
+    /// Returns the positions and their mirrored positions without duplicates.
+    pub fn apply(&self, world_block_poses: &[IVec3]) -> Vec<IVec3> {
+        let mut poses = world_block_poses.to_vec();
+        for world_block_pos in world_block_poses {
+            let mirrored = self.mirror_pos(*world_block_pos);
+            if !poses.contains(&mirrored) {
+                poses.push(mirrored);
+            }
+        }
+        poses
+    }
+}
+
+/// All positions in the box spanned by the two corners.
+pub fn get_box(a: IVec3, b: IVec3) -> Vec<IVec3> {
+    let min = a.min(b);
+    let max = a.max(b);
+
+    let mut poses = vec![];
+    for z in min.z..=max.z {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                poses.push(ivec3(x, y, z));
+            }
+        }
+    }
+    poses
+}
+
+/// Only the outer shell of the box spanned by the two corners.
+pub fn get_hollow_box(a: IVec3, b: IVec3) -> Vec<IVec3> {
+    let min = a.min(b);
+    let max = a.max(b);
+
+    get_box(a, b)
+        .into_iter()
+        .filter(|pos| pos.cmpeq(min).any() || pos.cmpeq(max).any())
+        .collect()
+}
+
+/// A connected line of positions from a to b.
+pub fn get_line(a: IVec3, b: IVec3) -> Vec<IVec3> {
+    let diff = b - a;
+    let steps = diff.abs().max_element();
+    if steps == 0 {
+        return vec![a];
+    }
+
+    (0..=steps)
+        .map(|i| {
+            a + (diff.as_vec3() * (i as f32 / steps as f32))
+                .round()
+                .as_ivec3()
+        })
+        .collect()
+}
+
+impl BlockObject {
+    /// All positions connected over the sides to start that have the same block name as start.
+    pub fn get_flood_fill(&self, start: IVec3, max_blocks: usize) -> Result<Vec<IVec3>> {
+        let block_name_index = self.get_block_name_from_world_block_pos(start);
+
+        let mut poses = vec![];
+        let mut visited = HashSet::from([start]);
+        let mut to_check = VecDeque::from([start]);
+        while let Some(pos) = to_check.pop_front() {
+            poses.push(pos);
+            if poses.len() > max_blocks {
+                bail!("Flood fill at {start} is bigger than {max_blocks} blocks");
+            }
+
+            for dir in all_sides_dirs() {
+                let neighbor = pos + dir;
+                if !visited.contains(&neighbor)
+                    && self.get_block_name_from_world_block_pos(neighbor) == block_name_index
+                {
+                    visited.insert(neighbor);
+                    to_check.push_back(neighbor);
+                }
+            }
+        }
+
+        Ok(poses)
+    }
+}
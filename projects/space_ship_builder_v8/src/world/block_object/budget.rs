@@ -0,0 +1,60 @@
+use crate::rules::Rules;
+use crate::world::block_object::{BlockObject, ChunkIndex};
+use std::time::{Duration, Instant};
+
+/// How many ticks are done between two checks of the clock.
+/// Large enough that reset waves still get solved in parallel.
+const TICKS_PER_TIME_CHECK: usize = 256;
+
+/// Number of orders still waiting in the solver queues.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TickBacklog {
+    pub to_reset: usize,
+    pub to_propergate: usize,
+    pub to_collapse: usize,
+}
+
+impl TickBacklog {
+    pub fn total(&self) -> usize {
+        self.to_reset + self.to_propergate + self.to_collapse
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+}
+
+impl BlockObject {
+    pub fn get_backlog(&self) -> TickBacklog {
+        TickBacklog {
+            to_reset: self.num_to_reset,
+            to_propergate: self.num_to_propergate,
+            to_collapse: self.collapser.len(),
+        }
+    }
+
+    /// Ticks until the queues are empty or the budget is used up.
+    /// Returns the number of ticks done and the changed chunks.
+    pub fn tick_for(&mut self, budget: Duration, rules: &Rules) -> (usize, Vec<ChunkIndex>) {
+        let deadline = Instant::now() + budget;
+
+        let mut ticks = 0;
+        let mut changed_chunks = Vec::new();
+        while Instant::now() < deadline {
+            let (ticks_left, chunks) = self.tick_parallel(TICKS_PER_TIME_CHECK, rules);
+            ticks += TICKS_PER_TIME_CHECK - ticks_left;
+
+            for chunk in chunks {
+                if !changed_chunks.contains(&chunk) {
+                    changed_chunks.push(chunk)
+                }
+            }
+
+            if ticks_left != 0 {
+                break;
+            }
+        }
+
+        (ticks, changed_chunks)
+    }
+}
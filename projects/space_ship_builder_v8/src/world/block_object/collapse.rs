@@ -1,8 +1,10 @@
-use index_queue::IndexQueue;
+use std::collections::BTreeSet;
 
+/// Orders with the same cache size are popped by their key, so the collapse order only depends on
+/// the keys and not on the order the orders were pushed in.
 #[derive(Clone, Debug)]
 pub struct Collapser {
-    orders: Vec<IndexQueue>,
+    orders: Vec<BTreeSet<(u64, usize)>>,
     num_orders: usize,
 }
 
@@ -14,32 +16,31 @@ impl Collapser {
         }
     }
 
-    pub fn push_order(&mut self, order: usize, cache_size: usize) {
+    pub fn push_order(&mut self, order: usize, key: u64, cache_size: usize) {
         while self.orders.len() <= cache_size {
-            self.orders.push(IndexQueue::default());
+            self.orders.push(BTreeSet::new());
         }
 
-        if !self.orders[cache_size].contains(order) {
+        if self.orders[cache_size].insert((key, order)) {
             self.num_orders += 1;
         }
-
-        self.orders[cache_size].push_back(order);
     }
 
-    pub fn remove_order(&mut self, order: usize, cache_size: usize) {
+    pub fn remove_order(&mut self, order: usize, key: u64, cache_size: usize) {
         if self.orders.len() <= cache_size {
             return;
         }
 
-        self.orders[cache_size].remove(order);
-        self.num_orders -= 1;
+        if self.orders[cache_size].remove(&(key, order)) {
+            self.num_orders -= 1;
+        }
     }
 
     pub fn pop_order(&mut self) -> usize {
         for queue in self.orders.iter_mut() {
-            if !queue.is_empty() {
+            if let Some((_, order)) = queue.pop_first() {
                 self.num_orders -= 1;
-                return queue.pop_front().unwrap();
+                return order;
             }
         }
 
@@ -49,4 +50,8 @@ impl Collapser {
     pub fn is_empty(&self) -> bool {
         self.num_orders == 0
     }
+
+    pub fn len(&self) -> usize {
+        self.num_orders
+    }
 }
@@ -0,0 +1,175 @@
+use crate::math::get_neighbors_without_zero;
+use crate::rules::solver::SolverCacheIndex;
+use crate::rules::Rules;
+use crate::world::block_object::BlockObject;
+use crate::world::data::block::BlockNameIndex;
+use glam::IVec3;
+use log::warn;
+use std::collections::VecDeque;
+
+/// Only the newest contradictions are kept, so objects that never solve do not grow forever.
+pub const MAX_CONTRADICTIONS: usize = 64;
+
+/// A block that has a block name but no solver could find a block for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Contradiction {
+    pub world_block_pos: IVec3,
+    pub block_name_index: BlockNameIndex,
+    /// The block names of all neighbors at the time of the collapse.
+    pub reqs: Vec<(IVec3, BlockNameIndex)>,
+    pub backtracked: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Backtracker {
+    /// How many collapse decisions may be undone per placement. 0 disables backtracking.
+    pub max_backtracks: usize,
+    backtracks_left: usize,
+
+    /// The last collapse decisions as (collapse order, block name, cache index).
+    history: VecDeque<(usize, BlockNameIndex, SolverCacheIndex)>,
+    /// Decisions that lead to a contradiction and should not be taken again.
+    banned: Vec<(usize, BlockNameIndex, SolverCacheIndex)>,
+}
+
+impl Backtracker {
+    pub fn new(max_backtracks: usize) -> Self {
+        Backtracker {
+            max_backtracks,
+            backtracks_left: max_backtracks,
+            history: VecDeque::new(),
+            banned: vec![],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.backtracks_left = self.max_backtracks;
+        self.history.clear();
+        self.banned.clear();
+    }
+
+    pub fn push_decision(
+        &mut self,
+        order: usize,
+        block_name_index: BlockNameIndex,
+        cache_index: SolverCacheIndex,
+    ) {
+        if self.max_backtracks == 0 {
+            return;
+        }
+
+        if self.history.len() >= self.max_backtracks {
+            self.history.pop_front();
+        }
+        self.history
+            .push_back((order, block_name_index, cache_index));
+    }
+
+    pub fn get_banned(&self) -> &[(usize, BlockNameIndex, SolverCacheIndex)] {
+        &self.banned
+    }
+
+    pub fn remove_banned(
+        &self,
+        order: usize,
+        block_name_index: BlockNameIndex,
+        cache: &mut Vec<SolverCacheIndex>,
+    ) {
+        if self.banned.is_empty() {
+            return;
+        }
+
+        cache.retain(|cache_index| {
+            !self
+                .banned
+                .contains(&(order, block_name_index, *cache_index))
+        });
+    }
+}
+
+impl BlockObject {
+    /// Records the contradiction and tries to undo the last collapse decision of a neighbor.
+    /// Returns true if a decision was undone and the block will be collapsed again.
+    pub(super) fn on_contradiction(
+        &mut self,
+        order: usize,
+        world_block_pos: IVec3,
+        block_name_index: BlockNameIndex,
+        rules: &Rules,
+    ) -> bool {
+        let reqs: Vec<_> = get_neighbors_without_zero()
+            .into_iter()
+            .map(|offset| {
+                (
+                    offset,
+                    self.get_block_name_from_world_block_pos(world_block_pos + offset),
+                )
+            })
+            .collect();
+
+        let backtracked = self.backtrack(order, world_block_pos, rules);
+
+        warn!(
+            "Contradiction at {world_block_pos} for block name {}. Backtracked: {backtracked}",
+            rules.block_names[block_name_index as usize]
+        );
+
+        if self.contradictions.len() >= MAX_CONTRADICTIONS {
+            self.contradictions.pop_front();
+        }
+        self.contradictions.push_back(Contradiction {
+            world_block_pos,
+            block_name_index,
+            reqs,
+            backtracked,
+        });
+
+        backtracked
+    }
+
+    fn backtrack(&mut self, order: usize, world_block_pos: IVec3, rules: &Rules) -> bool {
+        if self.backtracker.backtracks_left == 0 {
+            return false;
+        }
+
+        // Find the newest decision next to the contradiction.
+        let is_next_to_contradiction = |test_order: usize| {
+            let (block_index, chunk_index) =
+                self.order_controller.unpack_collapse_order(test_order);
+            let pos = self.get_world_block_pos_from_chunk_and_block_index(block_index, chunk_index);
+            (pos - world_block_pos).abs().max_element() <= 1
+        };
+        let Some(decision_index) = self
+            .backtracker
+            .history
+            .iter()
+            .rposition(|(test_order, _, _)| is_next_to_contradiction(*test_order))
+        else {
+            return false;
+        };
+
+        let decision = self.backtracker.history.remove(decision_index).unwrap();
+        self.backtracker.banned.push(decision);
+        self.backtracker.backtracks_left -= 1;
+
+        // Both blocks lost options through the decision, so their caches have to be rebuilt.
+        for collapse_order in [decision.0, order] {
+            let (block_index, chunk_index) =
+                self.order_controller.unpack_collapse_order(collapse_order);
+
+            for block_name_index in 0..rules.block_names.len() {
+                let reset_order = self.order_controller.pack_propergate_order(
+                    block_name_index as BlockNameIndex,
+                    block_index,
+                    chunk_index,
+                );
+                self.push_reset_order(reset_order);
+            }
+
+            self.is_collapsed.remove(collapse_order);
+            self.push_collapse_order(collapse_order);
+        }
+
+        true
+    }
+}
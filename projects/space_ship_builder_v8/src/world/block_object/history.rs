@@ -0,0 +1,153 @@
+use crate::world::block_object::BlockObject;
+use crate::world::data::block::BlockNameIndex;
+use glam::IVec3;
+use std::collections::VecDeque;
+
+/// How many block edits the history keeps before dropping the oldest groups.
+pub const MAX_HISTORY_EDITS: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edit {
+    pub world_block_pos: IVec3,
+    pub old_block_name_index: BlockNameIndex,
+    pub new_block_name_index: BlockNameIndex,
+}
+
+/// Edits that are undone and redone together.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EditGroup {
+    pub edits: Vec<Edit>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EditHistory {
+    pub max_edits: usize,
+    num_edits: usize,
+
+    undo: VecDeque<EditGroup>,
+    redo: Vec<EditGroup>,
+    current: Option<EditGroup>,
+}
+
+impl EditHistory {
+    pub fn new(max_edits: usize) -> Self {
+        EditHistory {
+            max_edits,
+            num_edits: 0,
+            undo: VecDeque::new(),
+            redo: vec![],
+            current: None,
+        }
+    }
+
+    /// All placements until end_group are recorded as one group.
+    pub fn begin_group(&mut self) {
+        self.current.get_or_insert_with(EditGroup::default);
+    }
+
+    pub fn end_group(&mut self) {
+        let Some(mut group) = self.current.take() else {
+            return;
+        };
+
+        group
+            .edits
+            .retain(|edit| edit.old_block_name_index != edit.new_block_name_index);
+        if group.edits.is_empty() {
+            return;
+        }
+
+        self.redo.clear();
+        self.push_undo(group);
+    }
+
+    /// Does nothing if no group is open.
+    pub fn record(
+        &mut self,
+        world_block_pos: IVec3,
+        old_block_name_index: BlockNameIndex,
+        new_block_name_index: BlockNameIndex,
+    ) {
+        if let Some(group) = self.current.as_mut() {
+            // Only the first old and the last new name of a position matter.
+            if let Some(edit) = group
+                .edits
+                .iter_mut()
+                .find(|edit| edit.world_block_pos == world_block_pos)
+            {
+                edit.new_block_name_index = new_block_name_index;
+                return;
+            }
+
+            group.edits.push(Edit {
+                world_block_pos,
+                old_block_name_index,
+                new_block_name_index,
+            });
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.num_edits = 0;
+        self.undo.clear();
+        self.redo.clear();
+        self.current = None;
+    }
+
+    fn push_undo(&mut self, group: EditGroup) {
+        self.num_edits += group.edits.len();
+        self.undo.push_back(group);
+
+        // The newest group is always kept, even if it is larger than the limit.
+        while self.num_edits > self.max_edits && self.undo.len() > 1 {
+            let dropped = self.undo.pop_front().unwrap();
+            self.num_edits -= dropped.edits.len();
+        }
+    }
+}
+
+impl BlockObject {
+    /// Reverts the last edit group. The changed blocks are reset and solved again by the next ticks.
+    /// Returns false if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        self.history.end_group();
+
+        let Some(group) = self.history.undo.pop_back() else {
+            return false;
+        };
+        self.history.num_edits -= group.edits.len();
+
+        for edit in group.edits.iter().rev() {
+            self.set_block_name(edit.world_block_pos, edit.old_block_name_index);
+        }
+        self.restart_solving();
+
+        self.history.redo.push(group);
+        true
+    }
+
+    /// Applies the last undone edit group again. Returns false if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        self.history.end_group();
+
+        let Some(group) = self.history.redo.pop() else {
+            return false;
+        };
+
+        for edit in group.edits.iter() {
+            self.set_block_name(edit.world_block_pos, edit.new_block_name_index);
+        }
+        self.restart_solving();
+
+        self.history.push_undo(group);
+        true
+    }
+}
@@ -1,28 +1,46 @@
+use crate::math::random::get_seeded_value;
 use crate::math::to_1d_i;
 use crate::math::{get_neighbors, oct_positions, to_3d_i};
 use crate::rules::{Prio, Rules};
 use order::NodeOrderController;
 use possible_blocks::PossibleBlocks;
+use replay::{ReplayLog, ReplayOperation};
 
+#[cfg(feature = "render")]
 use crate::render::compute_raytracing::compute_raytracing_data::ComputeRaytracingData;
+#[cfg(feature = "render")]
 use crate::render::parallax::node_parallax_mesh::{NodeParallaxMesh, RenderNode};
 use crate::rules::empty::EMPTY_BLOCK_NAME_INDEX;
 use crate::rules::solver::{SolverCacheIndex, SolverFunctions};
 use crate::world::data::block::{BlockNameIndex, BLOCK_INDEX_EMPTY};
 use crate::world::data::node::NodeID;
 use collapse::Collapser;
+use contradiction::{Backtracker, Contradiction};
+use glam::*;
+use history::{EditHistory, MAX_HISTORY_EDITS};
 use index_queue::IndexQueue;
 use log::{debug, trace};
+#[cfg(feature = "render")]
 use octa_force::puffin_egui::puffin;
-use octa_force::{glam::*, log};
+use std::collections::VecDeque;
 
+pub mod area;
+pub mod budget;
 pub mod collapse;
+pub mod contradiction;
+pub mod history;
 pub mod order;
+pub mod parallel;
 pub mod possible_blocks;
+pub mod replay;
 
 pub type ChunkIndex = usize;
 pub type CacheIndex = usize;
 
+/// The value of get_seeded_value for the collapse order, so it differs from the tie breaks of the
+/// block names.
+const COLLAPSE_ORDER_VALUE: usize = usize::MAX;
+
 pub struct BlockObject {
     pub transform: Mat4,
 
@@ -39,12 +57,23 @@ pub struct BlockObject {
 
     pub order_controller: NodeOrderController,
     pub to_reset: IndexQueue,
+    pub num_to_reset: usize,
     pub was_reset: IndexQueue,
     pub to_propergate: IndexQueue,
+    pub num_to_propergate: usize,
     pub collapser: Collapser,
     pub is_collapsed: IndexQueue,
+    /// Backtracking is off for new objects. Set a Backtracker with a limit to undo collapse
+    /// decisions on contradictions.
+    pub backtracker: Backtracker,
+    pub contradictions: VecDeque<Contradiction>,
 
     pub builder_active: bool,
+    pub history: EditHistory,
+
+    /// Seed for resolving solver ties.
+    pub seed: u64,
+    pub replay_log: Option<ReplayLog>,
 }
 
 pub struct BlockChunk {
@@ -52,9 +81,14 @@ pub struct BlockChunk {
     pub block_names: Vec<BlockNameIndex>,
     pub blocks: Vec<PossibleBlocks>,
     pub node_id_bits: Vec<u32>,
+    /// Density per block for smooth stone. Empty until a density is set, NAN for blocks without.
+    pub densities: Vec<f32>,
 
+    #[cfg(feature = "render")]
     pub render_nodes: Vec<RenderNode>,
+    #[cfg(feature = "render")]
     pub parallax_data: Option<NodeParallaxMesh>,
+    #[cfg(feature = "render")]
     pub compute_raytracing_data: Option<ComputeRaytracingData>,
 }
 
@@ -95,31 +129,121 @@ impl BlockObject {
             order_controller: node_order_controller,
 
             to_reset: IndexQueue::default(),
+            num_to_reset: 0,
             was_reset: IndexQueue::default(),
             to_propergate: IndexQueue::default(),
+            num_to_propergate: 0,
             collapser: Collapser::new(),
             is_collapsed: IndexQueue::default(),
+            backtracker: Backtracker::new(0),
+            contradictions: VecDeque::new(),
 
             builder_active: false,
+            history: EditHistory::new(MAX_HISTORY_EDITS),
+
+            seed: 0,
+            replay_log: None,
         }
     }
 
     pub fn place_block(&mut self, world_block_pos: IVec3, new_block_name_index: BlockNameIndex) {
-        #[cfg(debug_assertions)]
+        #[cfg(all(debug_assertions, feature = "render"))]
+        puffin::profile_function!();
+
+        if self.set_block_name(world_block_pos, new_block_name_index) {
+            self.restart_solving();
+        }
+    }
+
+    /// Places all blocks but restarts the solver only once.
+    pub fn place_blocks(
+        &mut self,
+        world_block_poses: &[IVec3],
+        new_block_name_index: BlockNameIndex,
+    ) {
+        #[cfg(all(debug_assertions, feature = "render"))]
         puffin::profile_function!();
 
+        let mut changed = false;
+        for world_block_pos in world_block_poses {
+            changed |= self.set_block_name(*world_block_pos, new_block_name_index);
+        }
+
+        if changed {
+            self.restart_solving();
+        }
+    }
+
+    /// Sets the block name and queues the block for reset and collapse.
+    /// Returns false if the block already had the name.
+    fn set_block_name(
+        &mut self,
+        world_block_pos: IVec3,
+        new_block_name_index: BlockNameIndex,
+    ) -> bool {
         let chunk_index = self.get_chunk_index_from_world_block_pos(world_block_pos);
         let block_index = self.get_block_index_from_world_block_pos(world_block_pos);
+        self.set_block_name_at_index(
+            world_block_pos,
+            chunk_index,
+            block_index,
+            new_block_name_index,
+        )
+    }
+
+    /// Places all blocks of a chunk by block index and restarts the solver once.
+    /// Used for loading, so the blocks end up in exactly the chunk they were saved from.
+    pub fn place_chunk_blocks(
+        &mut self,
+        chunk_pos: IVec3,
+        blocks: impl IntoIterator<Item = (usize, BlockNameIndex)>,
+    ) {
+        if !self.has_chunk(chunk_pos) {
+            self.add_chunk(chunk_pos);
+        }
+        let chunk_index = self.chunks.iter().position(|c| c.pos == chunk_pos).unwrap();
+
+        let mut changed = false;
+        for (block_index, new_block_name_index) in blocks {
+            let world_block_pos =
+                self.get_world_block_pos_from_chunk_and_block_index(block_index, chunk_index);
+            changed |= self.set_block_name_at_index(
+                world_block_pos,
+                chunk_index,
+                block_index,
+                new_block_name_index,
+            );
+        }
+
+        if changed {
+            self.restart_solving();
+        }
+    }
+
+    fn set_block_name_at_index(
+        &mut self,
+        world_block_pos: IVec3,
+        chunk_index: ChunkIndex,
+        block_index: usize,
+        new_block_name_index: BlockNameIndex,
+    ) -> bool {
         let chunk = &mut self.chunks[chunk_index];
 
         let old_block_name_index = chunk.block_names[block_index];
         if old_block_name_index == new_block_name_index {
-            return;
+            return false;
         }
 
         trace!("Place: {world_block_pos:?}");
         chunk.block_names[block_index] = new_block_name_index;
 
+        self.record(ReplayOperation::Place {
+            pos: world_block_pos.into(),
+            block_name_index: new_block_name_index,
+        });
+        self.history
+            .record(world_block_pos, old_block_name_index, new_block_name_index);
+
         let old_order = self.order_controller.pack_propergate_order(
             old_block_name_index,
             block_index,
@@ -131,37 +255,104 @@ impl BlockObject {
             chunk_index,
         );
 
-        self.to_reset.push_back(old_order);
-        self.to_reset.push_back(new_order);
+        self.push_reset_order(old_order);
+        self.push_reset_order(new_order);
+
+        // Solvers can require other block names, so neighbors with another name check again.
+        for offset in get_neighbors() {
+            let neighbor_world_pos = world_block_pos + offset;
+            let neighbor_block_name_index =
+                self.get_block_name_from_world_block_pos(neighbor_world_pos);
+            if neighbor_block_name_index == EMPTY_BLOCK_NAME_INDEX
+                || neighbor_block_name_index == old_block_name_index
+                || neighbor_block_name_index == new_block_name_index
+            {
+                continue;
+            }
+
+            let neighbor_chunk_index =
+                self.get_chunk_index_from_world_block_pos(neighbor_world_pos);
+            let neighbor_block_index =
+                self.get_block_index_from_world_block_pos(neighbor_world_pos);
+            let neighbor_order = self.order_controller.pack_propergate_order(
+                neighbor_block_name_index,
+                neighbor_block_index,
+                neighbor_chunk_index,
+            );
+            self.push_reset_order(neighbor_order);
+        }
+
+        let collapse_order = self
+            .order_controller
+            .pack_collapse_order(block_index, chunk_index);
+        self.push_collapse_order(collapse_order);
+
+        true
+    }
+
+    /// Orders with the same cache size are collapsed by a seeded value of their position, so the
+    /// result does not depend on the order of the edits.
+    fn push_collapse_order(&mut self, order: usize) {
+        let (block_index, chunk_index) = self.order_controller.unpack_collapse_order(order);
+        let world_block_pos =
+            self.get_world_block_pos_from_chunk_and_block_index(block_index, chunk_index);
+        let key = get_seeded_value(self.seed, world_block_pos, COLLAPSE_ORDER_VALUE);
+        let cache_len = self.chunks[chunk_index].blocks[block_index].get_num_caches();
+
+        self.collapser.push_order(order, key, cache_len);
+    }
 
+    fn restart_solving(&mut self) {
         // Resetting was_reset and is_collapsed
         {
             // Two Options: setting = new empty Queue or drain via while loop
             // A new empty Queue has long allocation times in later ticks so draining is better.
-            #[cfg(debug_assertions)]
+            #[cfg(all(debug_assertions, feature = "render"))]
             puffin::profile_scope!("Drain_was_reset_and_is_collapsed");
 
             while self.was_reset.pop_front().is_some() {}
             while self.is_collapsed.pop_front().is_some() {}
         }
+        self.backtracker.reset();
+    }
 
-        let collapse_order = self
-            .order_controller
-            .pack_collapse_order(block_index, chunk_index);
-        let neighbor_cache_len = self.chunks[chunk_index].blocks[block_index].get_num_caches();
+    pub fn get_block_name_from_world_block_pos(&self, world_block_pos: IVec3) -> BlockNameIndex {
+        // Chunks that do not exist yet only contain empty blocks.
+        let chunk_pos = self.get_chunk_node_pos_from_world_block_pos(world_block_pos);
+        let Some(chunk) = self.chunks.iter().find(|c| c.pos == chunk_pos) else {
+            return BLOCK_INDEX_EMPTY;
+        };
 
-        self.collapser
-            .push_order(collapse_order, neighbor_cache_len);
+        let in_chunk_block_index = self.get_block_index_from_world_block_pos(world_block_pos);
+        chunk.block_names[in_chunk_block_index]
     }
 
-    pub fn get_block_name_from_world_block_pos(
-        &mut self,
-        world_block_pos: IVec3,
-    ) -> BlockNameIndex {
+    pub fn set_density(&mut self, world_block_pos: IVec3, density: f32) {
+        self.record(ReplayOperation::Density {
+            pos: world_block_pos.into(),
+            density,
+        });
+
         let chunk_index = self.get_chunk_index_from_world_block_pos(world_block_pos);
-        let in_chunk_block_index = self.get_block_index_from_world_block_pos(world_block_pos);
+        let block_index = self.get_block_index_from_world_block_pos(world_block_pos);
+
+        let chunk = &mut self.chunks[chunk_index];
+        if chunk.densities.is_empty() {
+            chunk.densities = vec![f32::NAN; self.block_length];
+        }
+        chunk.densities[block_index] = density;
+    }
+
+    pub fn get_density_from_world_block_pos(&self, world_block_pos: IVec3) -> Option<f32> {
+        let chunk_pos = self.get_chunk_node_pos_from_world_block_pos(world_block_pos);
+        let chunk = self.chunks.iter().find(|c| c.pos == chunk_pos)?;
+        if chunk.densities.is_empty() {
+            return None;
+        }
 
-        self.chunks[chunk_index].block_names[in_chunk_block_index]
+        let in_chunk_block_index = self.get_block_index_from_world_block_pos(world_block_pos);
+        let density = chunk.densities[in_chunk_block_index];
+        (!density.is_nan()).then_some(density)
     }
 
     pub fn get_cache_from_world_block_pos(
@@ -175,8 +366,24 @@ impl BlockObject {
         self.chunks[chunk_index].blocks[in_chunk_block_index].get_cache(block_name_index)
     }
 
+    /// Same as get_cache_from_world_block_pos, but without adding chunks. Blocks of chunks that do
+    /// not exist have no cache.
+    pub fn find_cache_from_world_block_pos(
+        &self,
+        world_block_pos: IVec3,
+        block_name_index: BlockNameIndex,
+    ) -> &[SolverCacheIndex] {
+        let chunk_pos = self.get_chunk_node_pos_from_world_block_pos(world_block_pos);
+        let Some(chunk) = self.chunks.iter().find(|c| c.pos == chunk_pos) else {
+            return &[];
+        };
+
+        let in_chunk_block_index = self.get_block_index_from_world_block_pos(world_block_pos);
+        chunk.blocks[in_chunk_block_index].find_cache(block_name_index)
+    }
+
     pub fn tick(&mut self, ticks: usize, rules: &Rules) -> (usize, Vec<ChunkIndex>) {
-        #[cfg(debug_assertions)]
+        #[cfg(all(debug_assertions, feature = "render"))]
         puffin::profile_function!();
 
         let mut changed_chunks = Vec::new();
@@ -193,56 +400,109 @@ impl BlockObject {
                     changed_chunks.push(changed_chunk)
                 }
             } else {
+                if i != 0 {
+                    self.record(ReplayOperation::Tick(i));
+                }
+
                 return (ticks - i, changed_chunks);
             }
         }
 
+        if ticks != 0 {
+            self.record(ReplayOperation::Tick(ticks));
+        }
+
         (0, changed_chunks)
     }
 
     fn reset(&mut self, rules: &Rules) {
-        #[cfg(debug_assertions)]
+        #[cfg(all(debug_assertions, feature = "render"))]
         puffin::profile_function!();
 
-        let order = self.to_reset.pop_front().unwrap();
+        let order = self.pop_reset_order().unwrap();
+        let new_cache = self.get_reset_cache(order, rules);
+        self.apply_reset(order, new_cache, None);
+    }
+
+    /// The queues ignore orders that are already queued, so only new orders are counted.
+    fn push_reset_order(&mut self, order: usize) {
+        if self.to_reset.push_back(order) {
+            self.num_to_reset += 1;
+        }
+    }
+
+    fn pop_reset_order(&mut self) -> Option<usize> {
+        let order = self.to_reset.pop_front()?;
+        self.num_to_reset -= 1;
+        Some(order)
+    }
+
+    fn push_propergate_order(&mut self, order: usize) {
+        if self.to_propergate.push_back(order) {
+            self.num_to_propergate += 1;
+        }
+    }
+
+    fn pop_propergate_order(&mut self) -> Option<usize> {
+        let order = self.to_propergate.pop_front()?;
+        self.num_to_propergate -= 1;
+        Some(order)
+    }
+
+    fn get_reset_cache(&self, order: usize, rules: &Rules) -> Vec<SolverCacheIndex> {
         let (block_name_index, block_index, chunk_index) =
             self.order_controller.unpack_propergate_order(order);
         let world_block_pos =
             self.get_world_block_pos_from_chunk_and_block_index(block_index, chunk_index);
 
-        let new_cache = rules.solvers[block_name_index as usize].block_check_reset(
+        rules.solvers[block_name_index as usize].block_check_reset(
             self,
             block_index,
             chunk_index,
             world_block_pos,
-        );
+        )
+    }
+
+    /// Stores the new cache and queues the follow up orders.
+    /// Orders in pending are still going to be reset and are not queued again.
+    fn apply_reset(
+        &mut self,
+        order: usize,
+        new_cache: Vec<SolverCacheIndex>,
+        pending: Option<&IndexQueue>,
+    ) {
+        let (block_name_index, block_index, chunk_index) =
+            self.order_controller.unpack_propergate_order(order);
+        let world_block_pos =
+            self.get_world_block_pos_from_chunk_and_block_index(block_index, chunk_index);
+
         let old_cache = self.chunks[chunk_index].blocks[block_index].get_cache(block_name_index);
         if new_cache != old_cache {
-            #[cfg(debug_assertions)]
+            #[cfg(all(debug_assertions, feature = "render"))]
             puffin::profile_scope!("Cache_was_changed");
 
             self.chunks[chunk_index].blocks[block_index].set_cache(block_name_index, &new_cache);
 
             if cfg!(debug_assertions) {
                 {
-                    #[cfg(debug_assertions)]
+                    #[cfg(all(debug_assertions, feature = "render"))]
                     puffin::profile_scope!("Push_propergate_order");
-                    self.to_propergate.push_back(order);
+                    self.push_propergate_order(order);
                 }
                 {
-                    #[cfg(debug_assertions)]
+                    #[cfg(all(debug_assertions, feature = "render"))]
                     puffin::profile_scope!("Push_was_reset_order");
 
                     // Takes sometimes very long
                     self.was_reset.push_back(order);
                 }
             } else {
-                self.to_propergate.push_back(order);
+                self.push_propergate_order(order);
                 self.was_reset.push_back(order);
             }
 
             for offset in get_neighbors() {
-                #[cfg(debug_assertions)]
+                #[cfg(all(debug_assertions, feature = "render"))]
                 puffin::profile_scope!("Push_Neighbor_Rest");
 
                 let neighbor_world_pos = world_block_pos + offset;
@@ -256,34 +516,50 @@ impl BlockObject {
                     neighbor_chunk_index,
                 );
 
-                if !self.was_reset.contains(neighbor_order) {
-                    self.to_reset.push_back(neighbor_order);
+                if !self.was_reset.contains(neighbor_order)
+                    && !pending.is_some_and(|pending| pending.contains(neighbor_order))
+                {
+                    self.push_reset_order(neighbor_order);
                 }
             }
         }
     }
 
     fn propergate(&mut self, rules: &Rules) {
-        #[cfg(debug_assertions)]
+        #[cfg(all(debug_assertions, feature = "render"))]
         puffin::profile_function!();
 
-        let order = self.to_propergate.pop_front().unwrap();
+        let order = self.pop_propergate_order().unwrap();
+        let new_cache = self.get_propergate_cache(order, rules);
+        self.apply_propergate(order, new_cache);
+    }
+
+    fn get_propergate_cache(&self, order: usize, rules: &Rules) -> Vec<SolverCacheIndex> {
         let (block_name_index, block_index, chunk_index) =
             self.order_controller.unpack_propergate_order(order);
         let world_block_pos =
             self.get_world_block_pos_from_chunk_and_block_index(block_index, chunk_index);
 
         let old_cache = self.chunks[chunk_index].blocks[block_index]
-            .get_cache(block_name_index)
+            .find_cache(block_name_index)
             .to_owned();
-        let new_cache = rules.solvers[block_name_index as usize].block_check(
+        rules.solvers[block_name_index as usize].block_check(
             self,
             block_index,
             chunk_index,
             world_block_pos,
-            old_cache.to_owned(),
-        );
+            old_cache,
+        )
+    }
 
+    /// Stores the new cache and queues the neighbors that are not collapsed yet.
+    fn apply_propergate(&mut self, order: usize, new_cache: Vec<SolverCacheIndex>) {
+        let (block_name_index, block_index, chunk_index) =
+            self.order_controller.unpack_propergate_order(order);
+        let world_block_pos =
+            self.get_world_block_pos_from_chunk_and_block_index(block_index, chunk_index);
+
+        let old_cache = self.chunks[chunk_index].blocks[block_index].get_cache(block_name_index);
         if new_cache != old_cache {
             self.chunks[chunk_index].blocks[block_index].set_cache(block_name_index, &new_cache);
 
@@ -306,13 +582,13 @@ impl BlockObject {
                     neighbor_block_index,
                     neighbor_chunk_index,
                 );
-                self.to_propergate.push_back(propergate_order);
+                self.push_propergate_order(propergate_order);
             }
         }
     }
 
     fn collapse(&mut self, rules: &Rules) -> ChunkIndex {
-        #[cfg(debug_assertions)]
+        #[cfg(all(debug_assertions, feature = "render"))]
         puffin::profile_function!();
 
         let order = self.collapser.pop_order();
@@ -325,23 +601,47 @@ impl BlockObject {
         let mut best_prio = Prio::Zero;
         let mut best_block_name_index = EMPTY_BLOCK_NAME_INDEX;
         let mut best_cache_index = 0;
+        let mut best_tie_break = u64::MAX;
         for (block_name_index, solver) in rules.solvers.iter().enumerate() {
             let block_name_index = block_name_index as BlockNameIndex;
 
-            let old_cache = self.chunks[chunk_index].blocks[block_index]
+            let mut old_cache = self.chunks[chunk_index].blocks[block_index]
                 .get_cache(block_name_index)
                 .to_owned();
+            self.backtracker
+                .remove_banned(order, block_name_index, &mut old_cache);
             let (block, prio, cache_index) =
                 solver.get_block(self, block_index, chunk_index, world_block_pos, old_cache);
 
-            if best_prio < prio {
+            // Only real blocks take part in tie breaking. Empty results keep the first solver.
+            let tie_break = get_seeded_value(self.seed, world_block_pos, block_name_index as usize);
+            if best_prio < prio
+                || (best_prio == prio && prio > Prio::Empty && tie_break < best_tie_break)
+            {
                 best_prio = prio;
                 best_block = Some(block);
                 best_block_name_index = block_name_index;
                 best_cache_index = cache_index;
+                best_tie_break = tie_break;
             }
         }
 
+        let block_name_index = self.chunks[chunk_index].block_names[block_index];
+        if block_name_index != EMPTY_BLOCK_NAME_INDEX && best_prio <= Prio::Empty {
+            if self.on_contradiction(order, world_block_pos, block_name_index, rules) {
+                return chunk_index;
+            }
+        } else {
+            self.backtracker
+                .push_decision(order, best_block_name_index, best_cache_index);
+        }
+
+        self.record(ReplayOperation::Collapse {
+            pos: world_block_pos.into(),
+            block_name_index: best_block_name_index,
+            cache_index: best_cache_index,
+        });
+
         // Update Cache to the chosen index
         self.chunks[chunk_index].blocks[block_index]
             .set_all_caches_with_one(best_block_name_index, best_cache_index);
@@ -365,14 +665,20 @@ impl BlockObject {
             {
                 self.chunks[chunk_index].node_id_bits[index] = node_id.into();
 
-                self.chunks[chunk_index].render_nodes[index_with_padding] =
-                    RenderNode(node_id.is_some());
+                #[cfg(feature = "render")]
+                {
+                    self.chunks[chunk_index].render_nodes[index_with_padding] =
+                        RenderNode(node_id.is_some());
+                }
             }
         } else {
             for (index, index_with_padding) in indices.into_iter() {
                 self.chunks[chunk_index].node_id_bits[index] = NodeID::empty().into();
 
-                self.chunks[chunk_index].render_nodes[index_with_padding] = RenderNode(false);
+                #[cfg(feature = "render")]
+                {
+                    self.chunks[chunk_index].render_nodes[index_with_padding] = RenderNode(false);
+                }
             }
         }
 
@@ -397,7 +703,7 @@ impl BlockObject {
                 neighbor_block_index,
                 neighbor_chunk_index,
             );
-            self.to_propergate.push_back(propergate_order);
+            self.push_propergate_order(propergate_order);
 
             if self.get_block_name_from_world_block_pos(neighbor_world_pos)
                 == EMPTY_BLOCK_NAME_INDEX
@@ -408,11 +714,7 @@ impl BlockObject {
             let collapse_order = self
                 .order_controller
                 .pack_collapse_order(neighbor_block_index, neighbor_chunk_index);
-            let neighbor_cache_len =
-                self.chunks[neighbor_chunk_index].blocks[neighbor_block_index].get_num_caches();
-
-            self.collapser
-                .push_order(collapse_order, neighbor_cache_len);
+            self.push_collapse_order(collapse_order);
         }
 
         chunk_index
@@ -424,9 +726,13 @@ impl BlockObject {
             block_names: vec![BLOCK_INDEX_EMPTY; self.block_length],
             blocks: vec![PossibleBlocks::default(); self.block_length],
             node_id_bits: vec![0; self.nodes_length],
+            densities: vec![],
 
+            #[cfg(feature = "render")]
             render_nodes: vec![RenderNode::default(); self.nodes_length_with_padding],
+            #[cfg(feature = "render")]
             parallax_data: None,
+            #[cfg(feature = "render")]
             compute_raytracing_data: None,
         };
 
@@ -0,0 +1,136 @@
+use crate::rules::solver::SolverCacheIndex;
+use crate::rules::Rules;
+use crate::world::block_object::replay::ReplayOperation;
+use crate::world::block_object::{BlockObject, ChunkIndex};
+use index_queue::IndexQueue;
+use rayon::prelude::*;
+
+/// Waves smaller than this are solved on the calling thread.
+const MIN_PARALLEL_WAVE: usize = 64;
+
+impl BlockObject {
+    /// Same as tick, but all queued resets and propergations are solved as waves on the rayon
+    /// thread pool.
+    ///
+    /// A wave is split into one work queue per chunk and the new caches are computed in parallel
+    /// from the caches before the wave. The results are applied in queue order afterward. That is
+    /// the only point where chunks exchange results: a changed block at a chunk border pushes its
+    /// neighbors in the other chunk into the next wave, so they are checked against the finished
+    /// wave. For blocks placed before the first tick, solving all queued resets and then all
+    /// propergations as waves ends with the same nodes as tick. Interleaved calls are not checked.
+    /// Collapsing stays on the calling thread, because every collapse changes the caches its
+    /// neighbors are picked from.
+    pub fn tick_parallel(&mut self, ticks: usize, rules: &Rules) -> (usize, Vec<ChunkIndex>) {
+        #[cfg(all(debug_assertions, feature = "render"))]
+        octa_force::puffin_egui::puffin::profile_function!();
+
+        let mut changed_chunks = Vec::new();
+
+        let mut ticks_left = ticks;
+        while ticks_left != 0 {
+            if !self.to_reset.is_empty() {
+                ticks_left -= self.reset_wave(ticks_left, rules);
+            } else if !self.to_propergate.is_empty() {
+                ticks_left -= self.propergate_wave(ticks_left, rules);
+            } else if !self.collapser.is_empty() {
+                let changed_chunk = self.collapse(rules);
+                ticks_left -= 1;
+
+                if !changed_chunks.contains(&changed_chunk) {
+                    changed_chunks.push(changed_chunk)
+                }
+            } else {
+                break;
+            }
+        }
+
+        if ticks != ticks_left {
+            self.record(ReplayOperation::TickParallel(ticks - ticks_left));
+        }
+
+        (ticks_left, changed_chunks)
+    }
+
+    /// Resets up to max_orders orders and returns how many were reset.
+    fn reset_wave(&mut self, max_orders: usize, rules: &Rules) -> usize {
+        #[cfg(all(debug_assertions, feature = "render"))]
+        octa_force::puffin_egui::puffin::profile_function!();
+
+        let mut pending = IndexQueue::default();
+        let mut orders = vec![];
+        while let Some(order) = self.pop_reset_order() {
+            pending.push_back(order);
+            orders.push(order);
+
+            if orders.len() == max_orders {
+                break;
+            }
+        }
+
+        let new_caches = self.solve_wave(&orders, |this, order| this.get_reset_cache(order, rules));
+
+        let num_orders = orders.len();
+        for (order, new_cache) in orders.into_iter().zip(new_caches) {
+            pending.remove(order);
+            self.apply_reset(order, new_cache, Some(&pending));
+        }
+
+        num_orders
+    }
+
+    /// Propergates up to max_orders orders and returns how many were propergated.
+    /// Orders of the wave whose neighbors changed are queued again for the next wave.
+    fn propergate_wave(&mut self, max_orders: usize, rules: &Rules) -> usize {
+        #[cfg(all(debug_assertions, feature = "render"))]
+        octa_force::puffin_egui::puffin::profile_function!();
+
+        let mut orders = vec![];
+        while let Some(order) = self.pop_propergate_order() {
+            orders.push(order);
+
+            if orders.len() == max_orders {
+                break;
+            }
+        }
+
+        let new_caches = self.solve_wave(&orders, |this, order| {
+            this.get_propergate_cache(order, rules)
+        });
+
+        let num_orders = orders.len();
+        for (order, new_cache) in orders.into_iter().zip(new_caches) {
+            self.apply_propergate(order, new_cache);
+        }
+
+        num_orders
+    }
+
+    /// The new caches of the orders in the same order. Only reads the object.
+    fn solve_wave(
+        &self,
+        orders: &[usize],
+        solve: impl Fn(&Self, usize) -> Vec<SolverCacheIndex> + Sync,
+    ) -> Vec<Vec<SolverCacheIndex>> {
+        if orders.len() < MIN_PARALLEL_WAVE {
+            return orders.iter().map(|order| solve(self, *order)).collect();
+        }
+
+        let mut chunk_queues: Vec<Vec<usize>> = vec![vec![]; self.chunks.len()];
+        for (i, order) in orders.iter().enumerate() {
+            let (_, _, chunk_index) = self.order_controller.unpack_propergate_order(*order);
+            chunk_queues[chunk_index].push(i);
+        }
+
+        let solve = &solve;
+        let solved: Vec<_> = chunk_queues
+            .into_par_iter()
+            .flat_map_iter(move |queue| queue.into_iter().map(move |i| (i, solve(self, orders[i]))))
+            .collect();
+
+        let mut new_caches = vec![vec![]; orders.len()];
+        for (i, cache) in solved {
+            new_caches[i] = cache;
+        }
+        new_caches
+    }
+}
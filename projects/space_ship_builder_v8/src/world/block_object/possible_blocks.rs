@@ -1,5 +1,6 @@
 use crate::rules::solver::SolverCacheIndex;
 use crate::world::data::block::BlockNameIndex;
+#[cfg(feature = "render")]
 use octa_force::puffin_egui::puffin;
 
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
@@ -9,7 +10,7 @@ pub struct PossibleBlocks {
 
 impl PossibleBlocks {
     fn get_index(&mut self, block_name_index: BlockNameIndex) -> usize {
-        #[cfg(debug_assertions)]
+        #[cfg(all(debug_assertions, feature = "render"))]
         puffin::profile_function!();
 
         let res = self
@@ -25,7 +26,7 @@ impl PossibleBlocks {
     }
 
     pub fn set_cache(&mut self, block_name_index: BlockNameIndex, cache: &[SolverCacheIndex]) {
-        #[cfg(debug_assertions)]
+        #[cfg(all(debug_assertions, feature = "render"))]
         puffin::profile_function!();
 
         let index = self.get_index(block_name_index);
@@ -48,6 +49,17 @@ impl PossibleBlocks {
         self.blocks[index].1.as_slice()
     }
 
+    /// Same as get_cache, but without adding the block name.
+    pub fn find_cache(&self, block_name_index: BlockNameIndex) -> &[SolverCacheIndex] {
+        match self
+            .blocks
+            .binary_search_by(|(test_index, _)| test_index.cmp(&block_name_index))
+        {
+            Ok(index) => self.blocks[index].1.as_slice(),
+            Err(_) => &[],
+        }
+    }
+
     pub fn get_all_caches(&mut self) -> Vec<(BlockNameIndex, Vec<SolverCacheIndex>)> {
         self.blocks.to_owned()
     }
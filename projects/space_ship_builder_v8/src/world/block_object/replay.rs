@@ -0,0 +1,136 @@
+use crate::rules::solver::SolverCacheIndex;
+use crate::rules::Rules;
+use crate::world::block_object::BlockObject;
+use crate::world::data::block::BlockNameIndex;
+use anyhow::{bail, Result};
+use bitcode::{Decode, Encode};
+use glam::{IVec3, Mat4};
+use std::fs;
+
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Debug)]
+pub enum ReplayOperation {
+    Place {
+        pos: [i32; 3],
+        block_name_index: BlockNameIndex,
+    },
+    /// Densities change which nodes smooth stone can pick.
+    Density { pos: [i32; 3], density: f32 },
+    /// Number of ticks that did actual work.
+    Tick(usize),
+    /// Same as Tick for tick_parallel. It solves in waves, so it has to be replayed with it.
+    TickParallel(usize),
+    /// The block the solver picked while ticking.
+    Collapse {
+        pos: [i32; 3],
+        block_name_index: BlockNameIndex,
+        cache_index: SolverCacheIndex,
+    },
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct ReplayLog {
+    pub seed: u64,
+    pub transform: [f32; 16],
+    pub nodes_per_chunk: i32,
+    pub operations: Vec<ReplayOperation>,
+}
+
+impl ReplayLog {
+    pub fn new(seed: u64, transform: Mat4, nodes_per_chunk: i32) -> Self {
+        ReplayLog {
+            seed,
+            transform: transform.to_cols_array(),
+            nodes_per_chunk,
+            operations: vec![],
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let data: Vec<u8> = bitcode::encode(self);
+        fs::write(path, data)?;
+
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let data = fs::read(path)?;
+        let log: ReplayLog = bitcode::decode(&data)?;
+
+        Ok(log)
+    }
+}
+
+impl BlockObject {
+    /// Starts recording all placements, density edits, ticks and collapse decisions.
+    /// A running recording is discarded.
+    pub fn start_recording(&mut self) {
+        self.replay_log = Some(ReplayLog::new(
+            self.seed,
+            self.transform,
+            self.nodes_per_chunk.x,
+        ));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<ReplayLog> {
+        self.replay_log.take()
+    }
+
+    pub(super) fn record(&mut self, operation: ReplayOperation) {
+        if let Some(log) = self.replay_log.as_mut() {
+            log.operations.push(operation);
+        }
+    }
+
+    /// Rebuilds the object from the log and checks that every collapse decision is the same.
+    pub fn new_from_replay(log: &ReplayLog, rules: &Rules) -> Result<Self> {
+        let mut block_object = BlockObject::new(
+            Mat4::from_cols_array(&log.transform),
+            log.nodes_per_chunk,
+            rules.block_names.len(),
+        );
+        block_object.seed = log.seed;
+        block_object.start_recording();
+
+        for operation in log.operations.iter() {
+            match *operation {
+                ReplayOperation::Place {
+                    pos,
+                    block_name_index,
+                } => block_object.place_block(IVec3::from(pos), block_name_index),
+                ReplayOperation::Density { pos, density } => {
+                    block_object.set_density(IVec3::from(pos), density)
+                }
+                ReplayOperation::Tick(ticks) => {
+                    block_object.tick(ticks, rules);
+                }
+                ReplayOperation::TickParallel(ticks) => {
+                    block_object.tick_parallel(ticks, rules);
+                }
+                ReplayOperation::Collapse { .. } => {}
+            }
+        }
+
+        let replayed = block_object.stop_recording().unwrap();
+        let divergence = log
+            .operations
+            .iter()
+            .zip(replayed.operations.iter())
+            .position(|(a, b)| a != b);
+        if let Some(i) = divergence {
+            bail!(
+                "Replay diverged at operation {i}: expected {:?} got {:?}",
+                log.operations[i],
+                replayed.operations[i]
+            );
+        }
+        if log.operations.len() != replayed.operations.len() {
+            bail!(
+                "Replay has {} operations but the log has {}",
+                replayed.operations.len(),
+                log.operations.len()
+            );
+        }
+
+        Ok(block_object)
+    }
+}
@@ -1,14 +1,49 @@
+use crate::rules::empty::EMPTY_BLOCK_NAME_INDEX;
 use crate::rules::Rules;
+use crate::world::block_object::area::{
+    get_box, get_hollow_box, get_line, Mirror, MAX_FLOOD_FILL_BLOCKS,
+};
 use crate::world::block_object::BlockObject;
 use crate::world::data::block::BlockNameIndex;
 use crate::world::profile::{TickProfile, ENABLE_SHIP_PROFILING};
+use log::{info, warn};
+use octa_force::anyhow::{bail, Result};
 use octa_force::glam::{vec3, IVec3};
-use octa_force::{anyhow::Result, camera::Camera, controls::Controls};
+use octa_force::{camera::Camera, controls::Controls};
 use std::time::Duration;
 
 const SCROLL_SPEED: f32 = 0.01;
 const PLACE_SPEED: Duration = Duration::from_millis(100);
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildTool {
+    Single,
+    Box,
+    HollowBox,
+    Line,
+    FloodFill,
+}
+
+impl BuildTool {
+    fn next(self) -> Self {
+        match self {
+            BuildTool::Single => BuildTool::Box,
+            BuildTool::Box => BuildTool::HollowBox,
+            BuildTool::HollowBox => BuildTool::Line,
+            BuildTool::Line => BuildTool::FloodFill,
+            BuildTool::FloodFill => BuildTool::Single,
+        }
+    }
+
+    /// Tools that span between a first and a second click.
+    fn needs_anchor(self) -> bool {
+        matches!(
+            self,
+            BuildTool::Box | BuildTool::HollowBox | BuildTool::Line
+        )
+    }
+}
+
 pub struct BlockBuilder {
     possible_blocks: Vec<BlockNameIndex>,
     block_to_build: BlockNameIndex,
@@ -19,25 +54,28 @@ pub struct BlockBuilder {
     last_pos: IVec3,
     last_block_to_build: BlockNameIndex,
     last_block_index: Option<BlockNameIndex>,
+
+    pub tool: BuildTool,
+    pub anchor: Option<IVec3>,
+    pub mirror: Option<Mirror>,
 }
 
 impl BlockBuilder {
-    pub fn new(rules: &Rules) -> BlockBuilder {
-        let mut possible_blocks = Vec::new();
-        possible_blocks.push(rules.get_block_name_index("Empty"));
-        possible_blocks.push(rules.get_block_name_index("Hull"));
-        //possible_blocks.push(rules.get_block_name_index("Stone"));
-
-        BlockBuilder {
+    pub fn new(rules: &Rules) -> Result<BlockBuilder> {
+        Ok(BlockBuilder {
             block_to_build: 1,
-            possible_blocks,
+            possible_blocks: Self::get_possible_blocks(rules)?,
             distance: 3.0,
 
             last_action_time: Duration::default(),
             last_pos: IVec3::ZERO,
             last_block_to_build: BlockNameIndex::MAX,
             last_block_index: None,
-        }
+
+            tool: BuildTool::Single,
+            anchor: None,
+            mirror: None,
+        })
     }
 
     pub fn update(
@@ -59,11 +97,33 @@ impl BlockBuilder {
         }
         self.distance -= controls.scroll_delta * SCROLL_SPEED;
 
+        if controls.t && (self.last_action_time + PLACE_SPEED) < total_time {
+            self.last_action_time = total_time;
+
+            self.tool = self.tool.next();
+            self.anchor = None;
+            info!("Build tool: {:?}", self.tool);
+        }
+
         let pos = (((camera.position + camera.direction * self.distance) - vec3(1.0, 1.0, 1.0))
             / 2.0)
             .round()
             .as_ivec3();
 
+        // The mirror plane goes through the block under the cursor.
+        if controls.f7 && (self.last_action_time + PLACE_SPEED) < total_time {
+            self.last_action_time = total_time;
+
+            self.mirror = match self.mirror {
+                None => Some(Mirror::new(0, pos.x)),
+                Some(mirror) if mirror.axis < 2 => {
+                    Some(Mirror::new(mirror.axis + 1, pos[mirror.axis + 1]))
+                }
+                Some(_) => None,
+            };
+            info!("Build mirror: {:?}", self.mirror);
+        }
+
         if self.last_pos != pos || self.last_block_to_build != self.block_to_build {
             if self.last_block_index.is_some() {
                 block_object.place_block(self.last_pos, self.last_block_index.unwrap());
@@ -85,13 +145,89 @@ impl BlockBuilder {
 
         if controls.mouse_left && (self.last_action_time + PLACE_SPEED) < total_time {
             self.last_action_time = total_time;
-            self.last_block_index = None;
+
+            if self.tool.needs_anchor() && self.anchor.is_none() {
+                self.anchor = Some(pos);
+            } else {
+                self.remove_preview(block_object);
+
+                match self.get_tool_poses(block_object, pos) {
+                    Ok(poses) => {
+                        let poses = if let Some(mirror) = self.mirror {
+                            mirror.apply(&poses)
+                        } else {
+                            poses
+                        };
+
+                        let block_index = self.possible_blocks[self.block_to_build as usize];
+                        block_object.history.begin_group();
+                        block_object.place_blocks(&poses, block_index);
+                        block_object.history.end_group();
+                    }
+                    Err(err) => warn!("{err}"),
+                }
+                self.anchor = None;
+
+                if ENABLE_SHIP_PROFILING {
+                    tick_profile.reset();
+                }
+            }
+        }
+
+        if controls.r && (self.last_action_time + PLACE_SPEED) < total_time {
+            self.last_action_time = total_time;
+
+            // Remove the preview so it does not end up in the history.
+            self.remove_preview(block_object);
+
+            if controls.lshift {
+                block_object.redo();
+            } else {
+                block_object.undo();
+            }
+
+            if ENABLE_SHIP_PROFILING {
+                tick_profile.reset();
+            }
         }
 
         Ok(())
     }
 
-    pub fn on_rules_changed(&mut self) {
+    /// Restores the block under the preview. The preview is placed again in the next update.
+    fn remove_preview(&mut self, block_object: &mut BlockObject) {
+        if let Some(block_index) = self.last_block_index.take() {
+            block_object.place_block(self.last_pos, block_index);
+        }
+        self.last_block_to_build = BlockNameIndex::MAX;
+    }
+
+    fn get_tool_poses(&self, block_object: &BlockObject, pos: IVec3) -> Result<Vec<IVec3>> {
+        let poses = match self.tool {
+            BuildTool::Single => vec![pos],
+            BuildTool::Box => get_box(self.anchor.unwrap(), pos),
+            BuildTool::HollowBox => get_hollow_box(self.anchor.unwrap(), pos),
+            BuildTool::Line => get_line(self.anchor.unwrap(), pos),
+            BuildTool::FloodFill => block_object.get_flood_fill(pos, MAX_FLOOD_FILL_BLOCKS)?,
+        };
+
+        Ok(poses)
+    }
+
+    pub fn get_possible_blocks(rules: &Rules) -> Result<Vec<BlockNameIndex>> {
+        if rules.buildable_block_name_indices.is_empty() {
+            bail!("No block of the rule manifest is buildable");
+        }
+
+        // The empty block removes blocks.
+        let mut possible_blocks = vec![EMPTY_BLOCK_NAME_INDEX];
+        possible_blocks.extend_from_slice(&rules.buildable_block_name_indices);
+
+        Ok(possible_blocks)
+    }
+
+    pub fn on_rules_changed(&mut self, possible_blocks: Vec<BlockNameIndex>) {
+        self.possible_blocks = possible_blocks;
         self.last_block_to_build = BlockNameIndex::MAX;
     }
 }
@@ -2,7 +2,8 @@ use crate::math::rotation::Rot;
 use crate::math::{oct_positions, to_1d_i};
 use crate::rules::Rules;
 use crate::world::data::node::NodeID;
-use octa_force::glam::{IVec3, Mat4};
+use bitcode::{Decode, Encode};
+use glam::{IVec3, Mat4};
 
 pub type BlockNameIndex = u8;
 pub const BLOCK_INDEX_EMPTY: BlockNameIndex = 0;
@@ -10,7 +11,7 @@ pub const BLOCK_INDEX_EMPTY: BlockNameIndex = 0;
 pub type BlockIndex = usize;
 pub const VOXEL_PER_BLOCK_SIDE: i32 = 8;
 
-#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Ord, PartialOrd, Encode, Decode)]
 pub struct Block {
     pub node_ids: [NodeID; 8],
 }
@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Polls the modification times of files, so no extra thread or platform api is needed.
+pub struct FileWatcher {
+    pub paths: Vec<PathBuf>,
+    pub poll_intervall: Duration,
+
+    modified: Vec<Option<SystemTime>>,
+    last_poll: Option<Instant>,
+}
+
+impl FileWatcher {
+    pub fn new(paths: &[&str], poll_intervall: Duration) -> Self {
+        let paths: Vec<_> = paths.iter().map(PathBuf::from).collect();
+        let modified = paths.iter().map(|path| get_modified(path)).collect();
+
+        FileWatcher {
+            paths,
+            poll_intervall,
+            modified,
+            last_poll: None,
+        }
+    }
+
+    /// Like has_changed, but checks the files at most once per poll_intervall.
+    pub fn poll(&mut self) -> bool {
+        if self
+            .last_poll
+            .is_some_and(|last_poll| last_poll.elapsed() < self.poll_intervall)
+        {
+            return false;
+        }
+        self.last_poll = Some(Instant::now());
+
+        self.has_changed()
+    }
+
+    /// True if a file was written, created or removed since the last check.
+    pub fn has_changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, modified) in self.paths.iter().zip(self.modified.iter_mut()) {
+            let new_modified = get_modified(path);
+            if new_modified != *modified {
+                *modified = new_modified;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+fn get_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
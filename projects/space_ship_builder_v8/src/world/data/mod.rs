@@ -1,3 +1,4 @@
 pub mod block;
+pub mod file_watcher;
 pub mod node;
 pub mod voxel_loader;
@@ -1,9 +1,9 @@
+use bitcode::{Decode, Encode};
 use dot_vox::Color;
-use octa_force::glam::{ivec3, uvec3, IVec3, Mat4, UVec3};
+use glam::{ivec3, uvec3, IVec3, Mat4, UVec3};
 
 use crate::math::rotation::Rot;
 use crate::math::{to_1d, to_1d_i, to_3d, to_3d_i};
-use octa_force::glam::Mat3;
 use std::hash::Hash;
 use std::iter::repeat;
 
@@ -22,18 +22,18 @@ pub const NODE_VOXEL_LENGTH: usize = (NODE_SIZE.x * NODE_SIZE.y * NODE_SIZE.z) a
 pub const NODE_INDEX_EMPTY: NodeIndex = 0;
 pub const NODE_INDEX_ANY: NodeIndex = NodeIndex::MAX;
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Encode, Decode)]
 pub struct Node {
     pub voxels: [Voxel; NODE_VOXEL_LENGTH],
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, PartialOrd, Ord)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, PartialOrd, Ord, Encode, Decode)]
 pub struct NodeID {
     pub index: NodeIndex,
     pub rot: Rot,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Encode, Decode)]
 pub struct Material {
     pub r: u8,
     pub g: u8,
@@ -67,12 +67,29 @@ impl Node {
             })
     }
 
+    pub fn rotate(&self, rot: Rot) -> Node {
+        let mut voxels = [VOXEL_EMPTY; NODE_VOXEL_LENGTH];
+        for (pos, voxel) in self.get_rotated_voxels(rot) {
+            voxels[to_1d_i(pos, NODE_SIZE)] = voxel;
+        }
+
+        Node::new(voxels)
+    }
+
+    /// The smallest voxels of all rotations. All rotations of a node have the same canonical voxels.
+    pub fn get_canonical_voxels(&self) -> [Voxel; NODE_VOXEL_LENGTH] {
+        Rot::IDENTITY
+            .get_all_permutations()
+            .into_iter()
+            .map(|rot| self.rotate(rot).voxels)
+            .min()
+            .unwrap()
+    }
+
     pub fn is_duplicate_node_id(&self, rot: Rot, other_node: &Node, other_rot: Rot) -> bool {
         let mut same = true;
 
-        let mat: Mat3 = rot.into();
-        let inv_rot: Rot = mat.inverse().into();
-        let combined_rot = inv_rot * other_rot;
+        let combined_rot = rot.inverse() * other_rot;
 
         for (rotated_pos, voxel) in other_node.get_rotated_voxels(combined_rot) {
             let voxel_index = to_1d_i(rotated_pos, NODE_SIZE) as usize;
@@ -199,6 +216,18 @@ impl Into<u32> for NodeID {
     }
 }
 
+/// Reverses Into<u32>, so node_id_bits can be turned back into node ids.
+impl From<u32> for NodeID {
+    fn from(bits: u32) -> Self {
+        if bits == 0 {
+            return NodeID::empty();
+        }
+
+        let rot = Rot::try_from((bits & 0b1111111) as u8).unwrap().to_glsl();
+        NodeID::new((bits >> 7) as NodeIndex, rot)
+    }
+}
+
 impl From<NodeIndex> for NodeID {
     fn from(value: NodeIndex) -> Self {
         NodeID::new(value, Rot::default())
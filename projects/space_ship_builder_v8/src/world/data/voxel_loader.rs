@@ -1,13 +1,24 @@
 use crate::math::rotation::Rot;
-use crate::math::{to_1d, to_1d_i};
+use crate::math::to_1d_i;
 use crate::world::data::node::{Material, Node, NODE_SIZE, NODE_VOXEL_LENGTH};
-use dot_vox::{DotVoxData, Position, SceneNode};
-use octa_force::anyhow::{anyhow, bail, Result};
-use octa_force::glam::{ivec3, uvec3, IVec3, UVec3};
+use anyhow::{anyhow, bail, Result};
+use dot_vox::{Dict, DotVoxData, Frame, SceneNode};
+use glam::{ivec3, IVec3, UVec3};
 
 pub struct VoxelLoader {
     pub path: String,
     pub data: DotVoxData,
+    /// The animation frame models and transforms are loaded at.
+    pub frame: u32,
+}
+
+/// A visible transform node with the transforms of its parents applied.
+struct SceneTransform<'a> {
+    index: usize,
+    name: Option<&'a String>,
+    child: usize,
+    rot: Rot,
+    pos: IVec3,
 }
 
 #[allow(unused)]
@@ -28,6 +39,7 @@ impl VoxelLoader {
         let voxel_loader = Self {
             path: path.to_owned(),
             data,
+            frame: 0,
         };
 
         Ok(voxel_loader)
@@ -57,62 +69,31 @@ impl VoxelLoader {
     pub fn find_model_by_index(&self, index: usize) -> Result<(usize, Rot)> {
         match &self.data.scenes[index] {
             SceneNode::Transform { child, frames, .. } => {
-                let model_id = match &self.data.scenes[*child as usize] {
-                    SceneNode::Shape { models, .. } => Some(models[0].model_id as usize),
-                    _ => None,
-                };
-
+                let model_id = self.get_shape_model_id(*child as usize);
                 if model_id.is_none() {
                     bail!("No model id found")
                 }
 
-                let r = frames[0].attributes.get("_r");
-                let rot = if r.is_some() {
-                    Rot::from(r.unwrap().as_str())
-                } else {
-                    Rot::IDENTITY
-                };
+                let (rot, _) = self.get_local_transform(frames);
                 Ok((model_id.unwrap(), rot))
             }
             _ => bail!("Index is not transform node"),
         }
     }
 
+    /// The rot is the rotation of the model in the world, so rotated groups are applied.
     pub fn find_model_by_name(&self, name: &str) -> Result<(usize, Rot)> {
-        self.data
-            .scenes
-            .iter()
-            .find_map(|n| match n {
-                SceneNode::Transform {
-                    attributes,
-                    child,
-                    frames,
-                    ..
-                } => {
-                    if attributes.get("_name").is_some_and(|s| s == name) {
-                        let model_id = match &self.data.scenes[*child as usize] {
-                            SceneNode::Shape { models, .. } => Some(models[0].model_id as usize),
-                            _ => None,
-                        };
-
-                        if model_id.is_none() {
-                            return None;
-                        }
-
-                        let r = frames[0].attributes.get("_r");
-                        let rot = if r.is_some() {
-                            Rot::from(r.unwrap().as_str())
-                        } else {
-                            Rot::IDENTITY
-                        };
-                        Some((model_id.unwrap(), rot))
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
-            })
-            .ok_or(anyhow!("No node or model found for {name}."))
+        let mut found = None;
+        self.walk_scene(&mut |transform| {
+            if found.is_none() && transform.name.is_some_and(|s| s == name) {
+                found = self
+                    .get_shape_model_id(transform.child)
+                    .map(|model_id| (model_id, transform.rot));
+            }
+            true
+        });
+
+        found.ok_or(anyhow!("No node or model found for {name}."))
     }
 
     pub fn load_node_model(&self, model_index: usize) -> Result<Node> {
@@ -210,129 +191,185 @@ impl VoxelLoader {
         Ok((size, final_nodes))
     }
 
+    /// All models below the folder, also those in nested groups.
+    /// Their rot and position are relative to the folder.
     pub fn get_model_folder(&self, name: &str) -> Result<(Vec<(usize, Rot, IVec3)>, Rot)> {
-        self.data
-            .scenes
-            .iter()
-            .find_map(|n| match n {
-                SceneNode::Transform {
-                    attributes,
-                    child,
-                    frames,
-                    ..
-                } => {
-                    if attributes.get("_name").is_some_and(|s| s == name) {
-                        let child_ids = match &self.data.scenes[*child as usize] {
-                            SceneNode::Group { children, .. } => children
-                                .iter()
-                                .map(|child| match &self.data.scenes[*child as usize] {
-                                    SceneNode::Transform { frames, child, .. } => {
-                                        let model_id = match &self.data.scenes[*child as usize] {
-                                            SceneNode::Shape { models, .. } => {
-                                                Some(models[0].model_id as usize)
-                                            }
-                                            _ => None,
-                                        }
-                                        .unwrap();
-
-                                        let r = frames[0].attributes.get("_r");
-                                        let rot = if r.is_some() {
-                                            Rot::from(r.unwrap().as_str())
-                                        } else {
-                                            Rot::IDENTITY
-                                        };
-
-                                        let p = frames[0].position().unwrap();
-                                        let pos = ivec3(p.x, p.y, p.z);
-
-                                        Some((model_id, rot, pos))
-                                    }
-                                    _ => None,
-                                })
-                                .collect(),
-                            _ => None,
-                        };
-
-                        if child_ids.is_none() {
-                            return None;
-                        }
-
-                        let r = frames[0].attributes.get("_r");
-                        let rot = if r.is_some() {
-                            Rot::from(r.unwrap().as_str())
-                        } else {
-                            Rot::IDENTITY
-                        };
-                        Some((child_ids.unwrap(), rot))
-                    } else {
-                        None
+        let (children, rot) = self
+            .find_folder(name)
+            .ok_or(anyhow!("No node or model found for {name}."))?;
+
+        let mut models = vec![];
+        for child in children {
+            self.walk_transform(
+                *child as usize,
+                Rot::IDENTITY,
+                IVec3::ZERO,
+                &mut |transform| {
+                    if let Some(model_id) = self.get_shape_model_id(transform.child) {
+                        models.push((model_id, transform.rot, transform.pos));
                     }
-                }
-                _ => None,
-            })
-            .ok_or(anyhow!("No node or model found for {name}."))
+                    true
+                },
+            );
+        }
+
+        Ok((models, rot))
     }
 
+    /// All named models and named groups below the folder. Groups without a name only organize
+    /// the folder, so their children are returned with the transform of the group applied.
     pub fn get_name_folder(&self, name: &str) -> Result<(Vec<(String, usize, Rot, IVec3)>, Rot)> {
-        self.data
-            .scenes
-            .iter()
-            .find_map(|n| match n {
-                SceneNode::Transform {
-                    attributes,
-                    child,
-                    frames,
-                    ..
-                } => {
-                    if attributes.get("_name").is_some_and(|s| s == name) {
-                        let child_ids = match &self.data.scenes[*child as usize] {
-                            SceneNode::Group { children, .. } => children
-                                .iter()
-                                .map(|child| match &self.data.scenes[*child as usize] {
-                                    SceneNode::Transform {
-                                        frames, attributes, ..
-                                    } => {
-                                        let r = frames[0].attributes.get("_r");
-                                        let rot = if r.is_some() {
-                                            Rot::from(r.unwrap().as_str())
-                                        } else {
-                                            Rot::IDENTITY
-                                        };
-
-                                        let p = frames[0]
-                                            .position()
-                                            .unwrap_or(Position::from((0, 0, 0)));
-                                        let pos = ivec3(p.x, p.y, p.z);
-
-                                        let name = attributes.get("_name").unwrap().to_owned();
-
-                                        let index = *child as usize;
-
-                                        Some((name, index, rot, pos))
-                                    }
-                                    _ => None,
-                                })
-                                .collect(),
-                            _ => None,
-                        };
-
-                        if child_ids.is_none() {
-                            return None;
-                        }
-
-                        let r = frames[0].attributes.get("_r");
-                        let rot = if r.is_some() {
-                            Rot::from(r.unwrap().as_str())
-                        } else {
-                            Rot::IDENTITY
-                        };
-                        Some((child_ids.unwrap(), rot))
-                    } else {
-                        None
+        let (children, rot) = self
+            .find_folder(name)
+            .ok_or(anyhow!("No node or model found for {name}."))?;
+
+        let mut models = vec![];
+        for child in children {
+            self.walk_transform(
+                *child as usize,
+                Rot::IDENTITY,
+                IVec3::ZERO,
+                &mut |transform| {
+                    let is_group =
+                        matches!(self.data.scenes[transform.child], SceneNode::Group { .. });
+
+                    if let Some(model_name) = transform.name {
+                        models.push((
+                            model_name.to_owned(),
+                            transform.index,
+                            transform.rot,
+                            transform.pos,
+                        ));
+                        return false;
                     }
+
+                    is_group
+                },
+            );
+        }
+
+        Ok((models, rot))
+    }
+
+    /// The children of the first visible group with the name and the rot of the group in the
+    /// world, so rotated groups around it are applied.
+    fn find_folder(&self, name: &str) -> Option<(&[u32], Rot)> {
+        let mut found = None;
+        self.walk_scene(&mut |transform| {
+            if found.is_none() && transform.name.is_some_and(|s| s == name) {
+                if let SceneNode::Group { children, .. } = &self.data.scenes[transform.child] {
+                    found = Some((children.as_slice(), transform.rot));
                 }
-                _ => None,
-            })
-            .ok_or(anyhow!("No node or model found for {name}."))
+            }
+            true
+        });
+
+        found
     }
+
+    fn walk_scene(&self, visit: &mut impl FnMut(&SceneTransform) -> bool) {
+        if !self.data.scenes.is_empty() {
+            self.walk_transform(0, Rot::IDENTITY, IVec3::ZERO, visit);
+        }
+    }
+
+    /// Calls visit for the transform node at index and, if visit returns true and the node holds
+    /// a group, for all transform nodes below it. Hidden nodes and nodes on hidden layers are
+    /// skipped together with everything below them.
+    fn walk_transform(
+        &self,
+        index: usize,
+        parent_rot: Rot,
+        parent_pos: IVec3,
+        visit: &mut impl FnMut(&SceneTransform) -> bool,
+    ) {
+        let SceneNode::Transform {
+            attributes,
+            frames,
+            child,
+            layer_id,
+        } = &self.data.scenes[index]
+        else {
+            return;
+        };
+
+        if self.is_hidden(attributes, *layer_id) {
+            return;
+        }
+
+        let (local_rot, local_pos) = self.get_local_transform(frames);
+        let transform = SceneTransform {
+            index,
+            name: attributes.get("_name"),
+            child: *child as usize,
+            rot: parent_rot * local_rot,
+            pos: parent_pos + parent_rot * local_pos,
+        };
+
+        if !visit(&transform) {
+            return;
+        }
+
+        if let SceneNode::Group { children, .. } = &self.data.scenes[transform.child] {
+            for child in children {
+                self.walk_transform(*child as usize, transform.rot, transform.pos, visit);
+            }
+        }
+    }
+
+    /// The rot and position of a transform node at the current frame.
+    fn get_local_transform(&self, frames: &[Frame]) -> (Rot, IVec3) {
+        let frame = get_keyframe(frames, self.frame, |frame| &frame.attributes);
+
+        let rot = frame
+            .and_then(|frame| frame.attributes.get("_r"))
+            .map_or(Rot::IDENTITY, |r| Rot::from(r.as_str()));
+        let pos = frame
+            .and_then(|frame| frame.position())
+            .map_or(IVec3::ZERO, |p| ivec3(p.x, p.y, p.z));
+
+        (rot, pos)
+    }
+
+    /// The model of the shape node at index at the current frame.
+    fn get_shape_model_id(&self, index: usize) -> Option<usize> {
+        match &self.data.scenes[index] {
+            SceneNode::Shape { models, .. } => {
+                get_keyframe(models, self.frame, |model| &model.attributes)
+                    .map(|model| model.model_id as usize)
+            }
+            _ => None,
+        }
+    }
+
+    fn is_hidden(&self, attributes: &Dict, layer_id: u32) -> bool {
+        is_hidden(attributes)
+            || self
+                .data
+                .layers
+                .get(layer_id as usize)
+                .is_some_and(|layer| is_hidden(&layer.attributes))
+    }
+}
+
+/// The last keyframe that starts at or before frame. Keyframes without _f start at 0.
+fn get_keyframe<T>(keyframes: &[T], frame: u32, attributes: impl Fn(&T) -> &Dict) -> Option<&T> {
+    let get_start = |keyframe: &T| {
+        attributes(keyframe)
+            .get("_f")
+            .and_then(|f| f.parse::<u32>().ok())
+            .unwrap_or(0)
+    };
+
+    keyframes
+        .iter()
+        .filter(|keyframe| get_start(keyframe) <= frame)
+        .max_by_key(|keyframe| get_start(keyframe))
+        .or(keyframes.first())
+}
+
+fn is_hidden(attributes: &Dict) -> bool {
+    attributes
+        .get("_hidden")
+        .is_some_and(|hidden| hidden == "1")
 }
@@ -0,0 +1,356 @@
+use crate::math::{to_1d_i, to_3d_i};
+use crate::rules::Rules;
+use crate::world::block_object::BlockObject;
+use crate::world::data::node::{Material, Voxel, VOXEL_EMPTY};
+use crate::world::export::VoxelGrid;
+use anyhow::Result;
+use block_mesh::ndshape::RuntimeShape;
+use block_mesh::{
+    greedy_quads, Axis, AxisPermutation, GreedyQuadsBuffer, MergeVoxel, OrientedBlockFace,
+    QuadCoordinateConfig, VoxelVisibility,
+};
+use glam::{vec3, IVec3, Vec3};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+pub const RIGHT_HANDED_Z_UP_CONFIG: QuadCoordinateConfig = QuadCoordinateConfig {
+    // Y is always in the V direction when it's not the normal. When Y is the
+    // normal, right-handedness determines that we must use Yzx permutations.
+    faces: [
+        OrientedBlockFace::new(-1, AxisPermutation::Xzy),
+        OrientedBlockFace::new(-1, AxisPermutation::Zxy),
+        OrientedBlockFace::new(-1, AxisPermutation::Yzx),
+        OrientedBlockFace::new(1, AxisPermutation::Xzy),
+        OrientedBlockFace::new(1, AxisPermutation::Zxy),
+        OrientedBlockFace::new(1, AxisPermutation::Yzx),
+    ],
+    u_flip_face: Axis::X,
+};
+
+const GLB_MAGIC: u32 = 0x46546C67;
+const GLB_JSON_CHUNK: u32 = 0x4E4F534A;
+const GLB_BIN_CHUNK: u32 = 0x004E4942;
+
+/// Greedy meshed voxels in world voxel positions with z up. Every quad has four own vertices and
+/// only merges voxels of the same material.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VoxelMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    /// Two triangles per quad.
+    pub indices: Vec<u32>,
+    /// The voxel of every quad. Quad i uses the vertices 4 * i to 4 * i + 3.
+    pub quad_voxels: Vec<Voxel>,
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+struct MeshVoxel(Voxel);
+
+impl BlockObject {
+    pub fn get_mesh(&self, rules: &Rules) -> VoxelMesh {
+        VoxelMesh::new(&self.get_voxel_grid(rules))
+    }
+
+    /// Writes the .mtl file with the materials next to the .obj file.
+    pub fn save_obj(&self, path: &str, rules: &Rules) -> Result<()> {
+        let mtl_path = Path::new(path).with_extension("mtl");
+        let mtl_name = mtl_path.file_name().unwrap().to_string_lossy();
+
+        let mesh = self.get_mesh(rules);
+        fs::write(path, mesh.get_obj(&mtl_name))?;
+        fs::write(&mtl_path, mesh.get_mtl(&rules.materials))?;
+
+        Ok(())
+    }
+
+    /// Writes a binary .glb file.
+    pub fn save_gltf(&self, path: &str, rules: &Rules) -> Result<()> {
+        fs::write(path, self.get_mesh(rules).get_glb(&rules.materials))?;
+
+        Ok(())
+    }
+}
+
+impl VoxelMesh {
+    pub fn new(grid: &VoxelGrid) -> Self {
+        let mut mesh = VoxelMesh::default();
+        if grid.is_empty() {
+            return mesh;
+        }
+
+        // Greedy meshing needs one empty voxel of padding on every side.
+        let size = grid.size + 2;
+        let mut voxels = vec![MeshVoxel::default(); size.element_product() as usize];
+        for (i, voxel) in grid.voxels.iter().enumerate() {
+            let pos = to_3d_i(i as i32, grid.size) + 1;
+            voxels[to_1d_i(pos, size)] = MeshVoxel(*voxel);
+        }
+
+        let shape = RuntimeShape::<u32, 3>::new(size.as_uvec3().to_array());
+        let mut buffer = GreedyQuadsBuffer::new(voxels.len());
+        greedy_quads(
+            &voxels,
+            &shape,
+            [0; 3],
+            (size - 1).as_uvec3().to_array(),
+            &RIGHT_HANDED_Z_UP_CONFIG.faces,
+            &mut buffer,
+        );
+
+        let offset = (grid.min - 1).as_vec3();
+        for (group, face) in buffer
+            .quads
+            .groups
+            .iter()
+            .zip(RIGHT_HANDED_Z_UP_CONFIG.faces.iter())
+        {
+            for quad in group.iter() {
+                let start = mesh.positions.len() as u32;
+                mesh.positions.extend(
+                    face.quad_mesh_positions(quad, 1.0)
+                        .map(|p| Vec3::from(p) + offset),
+                );
+                mesh.normals
+                    .extend(face.quad_mesh_normals().map(Vec3::from));
+                mesh.indices.extend(face.quad_mesh_indices(start));
+
+                let pos = IVec3::from(quad.minimum.map(|x| x as i32));
+                mesh.quad_voxels.push(voxels[to_1d_i(pos, size)].0);
+            }
+        }
+
+        mesh
+    }
+
+    pub fn num_quads(&self) -> usize {
+        self.quad_voxels.len()
+    }
+
+    /// The voxels used by the quads, sorted.
+    pub fn get_used_voxels(&self) -> Vec<Voxel> {
+        let mut voxels = self.quad_voxels.to_owned();
+        voxels.sort();
+        voxels.dedup();
+        voxels
+    }
+
+    /// The indices of all quads with the voxel.
+    fn get_voxel_indices(&self, voxel: Voxel) -> impl Iterator<Item = u32> + '_ {
+        self.quad_voxels
+            .iter()
+            .enumerate()
+            .filter(move |(_, quad_voxel)| **quad_voxel == voxel)
+            .flat_map(move |(i, _)| self.indices[(i * 6)..(i * 6 + 6)].iter().copied())
+    }
+
+    /// OBJ has y up, so the mesh is rotated. Every voxel gets its own material of the .mtl file.
+    pub fn get_obj(&self, mtl_name: &str) -> String {
+        let mut obj = String::new();
+        writeln!(obj, "mtllib {mtl_name}").unwrap();
+
+        for pos in self.positions.iter() {
+            let pos = to_y_up(*pos);
+            writeln!(obj, "v {} {} {}", pos.x, pos.y, pos.z).unwrap();
+        }
+        for normal in self.normals.iter() {
+            let normal = to_y_up(*normal);
+            writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z).unwrap();
+        }
+
+        for voxel in self.get_used_voxels() {
+            writeln!(obj, "usemtl {}", get_material_name(voxel)).unwrap();
+
+            let indices: Vec<_> = self.get_voxel_indices(voxel).collect();
+            for triangle in indices.chunks(3) {
+                // OBJ indices start at 1.
+                let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+                writeln!(obj, "f {a}//{a} {b}//{b} {c}//{c}").unwrap();
+            }
+        }
+
+        obj
+    }
+
+    pub fn get_mtl(&self, materials: &[Material; 256]) -> String {
+        let mut mtl = String::new();
+        for voxel in self.get_used_voxels() {
+            let material = materials[voxel as usize];
+            writeln!(mtl, "newmtl {}", get_material_name(voxel)).unwrap();
+            writeln!(
+                mtl,
+                "Kd {} {} {}",
+                material.r as f32 / 255.0,
+                material.g as f32 / 255.0,
+                material.b as f32 / 255.0
+            )
+            .unwrap();
+        }
+
+        mtl
+    }
+
+    /// glTF 2.0 in the binary .glb container. glTF has y up, so the mesh is rotated.
+    /// Every voxel is one primitive with its own material, all primitives share the vertices.
+    pub fn get_glb(&self, materials: &[Material; 256]) -> Vec<u8> {
+        let mut json = String::new();
+        let mut bin = vec![];
+
+        if self.positions.is_empty() {
+            json.push_str(r#"{"asset":{"version":"2.0"},"scene":0,"scenes":[{}]}"#);
+            return get_glb_container(json, bin);
+        }
+
+        let positions: Vec<_> = self.positions.iter().map(|pos| to_y_up(*pos)).collect();
+        let min = positions.iter().fold(Vec3::MAX, |min, pos| min.min(*pos));
+        let max = positions.iter().fold(Vec3::MIN, |max, pos| max.max(*pos));
+        for pos in positions.iter() {
+            bin.extend(pos.to_array().map(f32::to_le_bytes).concat());
+        }
+        for normal in self.normals.iter() {
+            bin.extend(to_y_up(*normal).to_array().map(f32::to_le_bytes).concat());
+        }
+        let vertices_length = positions.len() * 12;
+
+        let mut accessors = vec![
+            format!(
+                r#"{{"bufferView":0,"componentType":5126,"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+                positions.len(),
+                min.x,
+                min.y,
+                min.z,
+                max.x,
+                max.y,
+                max.z
+            ),
+            format!(
+                r#"{{"bufferView":1,"componentType":5126,"count":{},"type":"VEC3"}}"#,
+                positions.len()
+            ),
+        ];
+        let mut primitives = vec![];
+        let mut gltf_materials = vec![];
+
+        let mut indices_length = 0;
+        for voxel in self.get_used_voxels() {
+            let indices: Vec<_> = self.get_voxel_indices(voxel).collect();
+            for index in indices.iter() {
+                bin.extend(index.to_le_bytes());
+            }
+
+            primitives.push(format!(
+                r#"{{"attributes":{{"POSITION":0,"NORMAL":1}},"indices":{},"material":{}}}"#,
+                accessors.len(),
+                gltf_materials.len()
+            ));
+            accessors.push(format!(
+                r#"{{"bufferView":2,"byteOffset":{},"componentType":5125,"count":{},"type":"SCALAR"}}"#,
+                indices_length,
+                indices.len()
+            ));
+
+            let material = materials[voxel as usize];
+            gltf_materials.push(format!(
+                r#"{{"name":"{}","pbrMetallicRoughness":{{"baseColorFactor":[{},{},{},{}],"metallicFactor":0,"roughnessFactor":1}}}}"#,
+                get_material_name(voxel),
+                srgb_to_linear(material.r),
+                srgb_to_linear(material.g),
+                srgb_to_linear(material.b),
+                material.a as f32 / 255.0
+            ));
+
+            indices_length += indices.len() * 4;
+        }
+
+        write!(
+            json,
+            concat!(
+                r#"{{"asset":{{"version":"2.0"}},"scene":0,"scenes":[{{"nodes":[0]}}],"#,
+                r#""nodes":[{{"mesh":0}}],"meshes":[{{"primitives":[{}]}}],"materials":[{}],"#,
+                r#""accessors":[{}],"bufferViews":["#,
+                r#"{{"buffer":0,"byteOffset":0,"byteLength":{},"target":34962}},"#,
+                r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":34962}},"#,
+                r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":34963}}"#,
+                r#"],"buffers":[{{"byteLength":{}}}]}}"#
+            ),
+            primitives.join(","),
+            gltf_materials.join(","),
+            accessors.join(","),
+            vertices_length,
+            vertices_length,
+            vertices_length,
+            vertices_length * 2,
+            indices_length,
+            bin.len()
+        )
+        .unwrap();
+
+        get_glb_container(json, bin)
+    }
+}
+
+impl block_mesh::Voxel for MeshVoxel {
+    fn get_visibility(&self) -> VoxelVisibility {
+        if self.0 == VOXEL_EMPTY {
+            VoxelVisibility::Empty
+        } else {
+            VoxelVisibility::Opaque
+        }
+    }
+}
+
+impl MergeVoxel for MeshVoxel {
+    type MergeValue = Voxel;
+    fn merge_value(&self) -> Self::MergeValue {
+        self.0
+    }
+}
+
+/// Both chunks are padded to 4 bytes. The length of the binary buffer in the json is not changed
+/// by the padding.
+fn get_glb_container(json: String, mut bin: Vec<u8>) -> Vec<u8> {
+    let mut json = json.into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let mut length = 12 + 8 + json.len();
+    if !bin.is_empty() {
+        length += 8 + bin.len();
+    }
+
+    let mut data = Vec::with_capacity(length);
+    data.extend(GLB_MAGIC.to_le_bytes());
+    data.extend(2u32.to_le_bytes());
+    data.extend((length as u32).to_le_bytes());
+
+    data.extend((json.len() as u32).to_le_bytes());
+    data.extend(GLB_JSON_CHUNK.to_le_bytes());
+    data.extend(json);
+
+    if !bin.is_empty() {
+        data.extend((bin.len() as u32).to_le_bytes());
+        data.extend(GLB_BIN_CHUNK.to_le_bytes());
+        data.extend(bin);
+    }
+
+    data
+}
+
+fn get_material_name(voxel: Voxel) -> String {
+    format!("material_{voxel}")
+}
+
+/// Rotates z up to y up.
+fn to_y_up(v: Vec3) -> Vec3 {
+    vec3(v.x, v.z, -v.y)
+}
+
+/// glTF colors are linear, the palette is sRGB.
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
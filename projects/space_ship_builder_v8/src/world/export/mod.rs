@@ -0,0 +1,73 @@
+use crate::math::{to_1d_i, to_3d_i};
+use crate::rules::Rules;
+use crate::world::block_object::BlockObject;
+use crate::world::data::node::{NodeID, Voxel, NODE_SIZE, VOXEL_EMPTY};
+use glam::IVec3;
+
+pub mod mesh;
+pub mod vox;
+
+/// The collapsed voxels of a block object in a dense grid around all non empty nodes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VoxelGrid {
+    /// The world voxel position of the first voxel.
+    pub min: IVec3,
+    pub size: IVec3,
+    pub voxels: Vec<Voxel>,
+}
+
+impl VoxelGrid {
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+
+    /// pos is relative to min. Voxels outside of the grid are empty.
+    pub fn get_voxel(&self, pos: IVec3) -> Voxel {
+        if pos.cmplt(IVec3::ZERO).any() || pos.cmpge(self.size).any() {
+            return VOXEL_EMPTY;
+        }
+
+        self.voxels[to_1d_i(pos, self.size)]
+    }
+}
+
+impl BlockObject {
+    /// Expands the node_id_bits of all chunks through Rules::nodes.
+    pub fn get_voxel_grid(&self, rules: &Rules) -> VoxelGrid {
+        let mut nodes = vec![];
+        for chunk in self.chunks.iter() {
+            for (node_index, bits) in chunk.node_id_bits.iter().enumerate() {
+                let node_id = NodeID::from(*bits);
+                if !node_id.is_some() {
+                    continue;
+                }
+
+                let node_pos = chunk.pos + to_3d_i(node_index as i32, self.nodes_per_chunk);
+                nodes.push((node_pos, node_id));
+            }
+        }
+
+        if nodes.is_empty() {
+            return VoxelGrid::default();
+        }
+
+        let min_node_pos = nodes
+            .iter()
+            .fold(IVec3::MAX, |min, (node_pos, _)| min.min(*node_pos));
+        let max_node_pos = nodes
+            .iter()
+            .fold(IVec3::MIN, |max, (node_pos, _)| max.max(*node_pos));
+
+        let min = min_node_pos * NODE_SIZE;
+        let size = (max_node_pos - min_node_pos + IVec3::ONE) * NODE_SIZE;
+        let mut voxels = vec![VOXEL_EMPTY; size.element_product() as usize];
+        for (node_pos, node_id) in nodes {
+            let node_voxel_pos = node_pos * NODE_SIZE - min;
+            for (pos, voxel) in rules.nodes[node_id.index].get_rotated_voxels(node_id.rot) {
+                voxels[to_1d_i(node_voxel_pos + pos, size)] = voxel;
+            }
+        }
+
+        VoxelGrid { min, size, voxels }
+    }
+}
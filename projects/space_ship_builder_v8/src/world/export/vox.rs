@@ -0,0 +1,174 @@
+use crate::math::to_3d_i;
+use crate::rules::Rules;
+use crate::world::block_object::BlockObject;
+use crate::world::data::node::{Material, Voxel, VOXEL_EMPTY};
+use crate::world::export::VoxelGrid;
+use anyhow::{bail, Result};
+use glam::IVec3;
+use std::fs;
+
+/// MagicaVoxel can not load models that are larger in any direction.
+pub const VOX_MAX_MODEL_SIZE: i32 = 256;
+const VOX_VERSION: i32 = 150;
+/// dot_vox loads palette index i + 1 of the file as voxel i, so the last voxel has no index.
+const VOX_MAX_VOXEL: Voxel = 254;
+
+impl BlockObject {
+    pub fn save_vox(&self, path: &str, rules: &Rules) -> Result<()> {
+        fs::write(path, self.get_vox(rules)?)?;
+
+        Ok(())
+    }
+
+    /// The collapsed voxels as .vox file with the palette of the rules.
+    pub fn get_vox(&self, rules: &Rules) -> Result<Vec<u8>> {
+        get_vox(&self.get_voxel_grid(rules), &rules.materials)
+    }
+}
+
+/// Grids larger than VOX_MAX_MODEL_SIZE are split into several models, that are placed next to
+/// each other by the scene graph.
+/// Fails for grids without voxels, because MagicaVoxel can not open files without models, and for
+/// voxel 255, because it has no palette index.
+pub fn get_vox(grid: &VoxelGrid, materials: &[Material; 256]) -> Result<Vec<u8>> {
+    let mut children = vec![];
+    let mut model_positions = vec![];
+
+    let num_models = (grid.size + VOX_MAX_MODEL_SIZE - 1) / VOX_MAX_MODEL_SIZE;
+    for i in 0..num_models.element_product() {
+        let start = to_3d_i(i, num_models) * VOX_MAX_MODEL_SIZE;
+        let size = (grid.size - start).min(IVec3::ONE * VOX_MAX_MODEL_SIZE);
+
+        let mut voxels = vec![];
+        for j in 0..size.element_product() {
+            let pos = to_3d_i(j, size);
+            let voxel = grid.get_voxel(start + pos);
+            if voxel == VOXEL_EMPTY {
+                continue;
+            }
+            if voxel > VOX_MAX_VOXEL {
+                bail!(
+                    "Voxel {voxel} at {} has no .vox palette index",
+                    grid.min + start + pos
+                );
+            }
+
+            voxels.extend([pos.x as u8, pos.y as u8, pos.z as u8, voxel + 1]);
+        }
+
+        if voxels.is_empty() {
+            continue;
+        }
+
+        let mut content = vec![];
+        push_ivec3(&mut content, size);
+        push_chunk(&mut children, b"SIZE", &content);
+
+        let mut content = vec![];
+        push_i32(&mut content, (voxels.len() / 4) as i32);
+        content.extend(voxels);
+        push_chunk(&mut children, b"XYZI", &content);
+
+        // MagicaVoxel places models by their center.
+        model_positions.push(grid.min + start + size / 2);
+    }
+
+    if model_positions.is_empty() {
+        bail!("Can not export an object without voxels");
+    }
+
+    push_scene_graph(&mut children, &model_positions);
+
+    let mut content = vec![];
+    for material in materials.iter() {
+        content.extend(<[u8; 4]>::from(material));
+    }
+    push_chunk(&mut children, b"RGBA", &content);
+
+    let mut data = vec![];
+    data.extend_from_slice(b"VOX ");
+    push_i32(&mut data, VOX_VERSION);
+    data.extend_from_slice(b"MAIN");
+    push_i32(&mut data, 0);
+    push_i32(&mut data, children.len() as i32);
+    data.extend(children);
+
+    Ok(data)
+}
+
+/// A root transform with a group, that holds a transform and a shape for every model.
+fn push_scene_graph(data: &mut Vec<u8>, model_positions: &[IVec3]) {
+    let mut content = vec![];
+    push_i32(&mut content, 0);
+    push_dict(&mut content, &[]);
+    push_i32(&mut content, 1);
+    push_i32(&mut content, -1);
+    push_i32(&mut content, -1);
+    push_i32(&mut content, 1);
+    push_dict(&mut content, &[]);
+    push_chunk(data, b"nTRN", &content);
+
+    let mut content = vec![];
+    push_i32(&mut content, 1);
+    push_dict(&mut content, &[]);
+    push_i32(&mut content, model_positions.len() as i32);
+    for i in 0..model_positions.len() {
+        push_i32(&mut content, 2 + i as i32 * 2);
+    }
+    push_chunk(data, b"nGRP", &content);
+
+    for (i, pos) in model_positions.iter().enumerate() {
+        let transform_id = 2 + i as i32 * 2;
+
+        let mut content = vec![];
+        push_i32(&mut content, transform_id);
+        push_dict(&mut content, &[]);
+        push_i32(&mut content, transform_id + 1);
+        push_i32(&mut content, -1);
+        push_i32(&mut content, 0);
+        push_i32(&mut content, 1);
+        push_dict(
+            &mut content,
+            &[("_t", format!("{} {} {}", pos.x, pos.y, pos.z))],
+        );
+        push_chunk(data, b"nTRN", &content);
+
+        let mut content = vec![];
+        push_i32(&mut content, transform_id + 1);
+        push_dict(&mut content, &[]);
+        push_i32(&mut content, 1);
+        push_i32(&mut content, i as i32);
+        push_dict(&mut content, &[]);
+        push_chunk(data, b"nSHP", &content);
+    }
+}
+
+fn push_chunk(data: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    data.extend_from_slice(id);
+    push_i32(data, content.len() as i32);
+    push_i32(data, 0);
+    data.extend_from_slice(content);
+}
+
+fn push_dict(data: &mut Vec<u8>, dict: &[(&str, String)]) {
+    push_i32(data, dict.len() as i32);
+    for (key, value) in dict {
+        push_string(data, key);
+        push_string(data, value);
+    }
+}
+
+fn push_string(data: &mut Vec<u8>, text: &str) {
+    push_i32(data, text.len() as i32);
+    data.extend_from_slice(text.as_bytes());
+}
+
+fn push_ivec3(data: &mut Vec<u8>, v: IVec3) {
+    push_i32(data, v.x);
+    push_i32(data, v.y);
+    push_i32(data, v.z);
+}
+
+fn push_i32(data: &mut Vec<u8>, v: i32) {
+    data.extend_from_slice(&v.to_le_bytes());
+}
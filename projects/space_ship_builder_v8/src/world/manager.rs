@@ -3,21 +3,27 @@ use crate::rules::Rules;
 use crate::world::asteroid::AsteroidGenerator;
 use crate::world::block_object::BlockObject;
 use crate::world::builder::BlockBuilder;
+use crate::world::data::block::BlockNameIndex;
 use crate::world::profile::{TickProfile, ENABLE_SHIP_PROFILING};
 use crate::world::region::Region;
+use crate::world::save::{rotate_backups, save_regions};
+use crate::world::scheduler::{TickScheduler, TICK_BUDGET};
+use crate::world::streaming::RegionStreamer;
 use crate::INPUT_INTERVALL;
-use log::info;
+use log::{error, info};
+use octa_force::anyhow::Result;
 use octa_force::camera::Camera;
 use octa_force::controls::Controls;
-use octa_force::glam::{vec3, IVec3, Mat4};
+use octa_force::glam::{Mat4, Vec3};
 use octa_force::vulkan::{CommandBuffer, Context};
-use std::cmp::{max, min};
 use std::iter::repeat;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
-pub const MIN_TICK_LENGTH: Duration = Duration::from_millis(20);
-pub const MAX_TICK_LENGTH: Duration = Duration::from_millis(25);
 pub const CHUNK_SIZE: i32 = 32;
+pub const SAVE_DIR: &str = "./saves/world";
+pub const AUTOSAVE_INTERVALL: Duration = Duration::from_secs(300);
+pub const NUM_SAVE_BACKUPS: usize = 3;
 
 pub struct WorldManager {
     pub asteroid_generator: AsteroidGenerator,
@@ -25,51 +31,100 @@ pub struct WorldManager {
     pub loaded_regions: Vec<Region>,
     pub region_size: i32,
 
-    pub ticks: usize,
-    pub last_ticks_left: usize,
+    pub tick_scheduler: TickScheduler,
+    pub last_backlog: usize,
     pub tick_profile: TickProfile,
     pub builder: BlockBuilder,
 
     pub last_input: Instant,
+
+    pub streamer: RegionStreamer,
+    pub last_save: Instant,
+}
+
+/// The parts of the world made for new rules by WorldManager::prepare_rules_change.
+pub struct RulesChange {
+    pub asteroid_generator: AsteroidGenerator,
+    pub possible_blocks: Vec<BlockNameIndex>,
+    pub region_objects: Vec<Vec<BlockObject>>,
 }
 
 impl WorldManager {
-    pub fn new(region_size: i32, rules: &mut Rules) -> WorldManager {
-        WorldManager {
-            asteroid_generator: AsteroidGenerator::new(rules),
+    pub fn new(region_size: i32, rules: &mut Rules) -> Result<WorldManager> {
+        Ok(WorldManager {
+            asteroid_generator: AsteroidGenerator::new(rules)?,
 
             region_size,
             loaded_regions: vec![],
 
-            ticks: 4,
-            last_ticks_left: 0,
+            tick_scheduler: TickScheduler::new(TICK_BUDGET),
+            last_backlog: 0,
             tick_profile: TickProfile::new(),
-            builder: BlockBuilder::new(rules),
+            builder: BlockBuilder::new(rules)?,
 
             last_input: Instant::now(),
+
+            streamer: RegionStreamer::new(
+                (region_size * CHUNK_SIZE) as f32,
+                PathBuf::from(SAVE_DIR),
+            ),
+            last_save: Instant::now(),
+        })
+    }
+
+    /// Loads regions around the camera and unloads the far ones. Errors are only logged, so a
+    /// broken region file never ends the session.
+    pub fn update_regions(&mut self, camera_pos: Vec3, rules: &Rules) {
+        if let Err(err) = self.streamer.update(
+            &mut self.loaded_regions,
+            camera_pos,
+            rules,
+            &self.asteroid_generator,
+        ) {
+            error!("Streaming regions failed: {err}");
         }
     }
 
-    pub fn add_start_data(&mut self, rules: &Rules) {
-        let mut region = Region::new(IVec3::ZERO);
+    /// Solves all loaded objects again with the new rules. Nothing is changed yet, so the old
+    /// objects are kept if any object or block name does not work with the new rules.
+    pub fn prepare_rules_change(&self, old_rules: &Rules, rules: &Rules) -> Result<RulesChange> {
+        let mut region_objects = vec![];
+        for region in self.loaded_regions.iter() {
+            region_objects.push(region.get_reloaded_objects(old_rules, rules)?);
+        }
 
-        // let mut ship = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
-        // ship.builder_active = true;
-        // region.loaded_objects.push(ship);
+        Ok(RulesChange {
+            asteroid_generator: AsteroidGenerator::new(rules)?,
+            possible_blocks: BlockBuilder::get_possible_blocks(rules)?,
+            region_objects,
+        })
+    }
 
-        let asteroid = self
-            .asteroid_generator
-            .generate(Mat4::from_translation(vec3(50.0, 0.0, 0.0)), 11);
-        region.loaded_objects.push(asteroid);
+    pub fn on_rules_changed(&mut self, change: RulesChange) {
+        for (region, objects) in self.loaded_regions.iter_mut().zip(change.region_objects) {
+            region.loaded_objects = objects;
+        }
+        self.asteroid_generator = change.asteroid_generator;
+        self.builder.on_rules_changed(change.possible_blocks);
+    }
 
-        self.loaded_regions.push(region);
+    pub fn save(&mut self, rules: &Rules) -> Result<()> {
+        rotate_backups(&self.streamer.save_dir, NUM_SAVE_BACKUPS)?;
+        save_regions(&self.streamer.save_dir, &self.loaded_regions, rules)?;
+        self.last_save = Instant::now();
+
+        info!(
+            "Saved {} regions to {:?}",
+            self.loaded_regions.len(),
+            self.streamer.save_dir
+        );
+        Ok(())
     }
 
     pub fn update(
         &mut self,
         rules: &Rules,
         total_time: Duration,
-        delta_time: Duration,
 
         num_frames: usize,
         frame_index: usize,
@@ -78,14 +133,9 @@ impl WorldManager {
         controls: &Controls,
         camera: &Camera,
         renderer: &mut Renderer,
-    ) -> octa_force::anyhow::Result<()> {
-        if delta_time < MIN_TICK_LENGTH && self.last_ticks_left == 0 {
-            self.ticks = min(self.ticks * 2, usize::MAX / 2);
-        } else if delta_time > MAX_TICK_LENGTH {
-            self.ticks = max(self.ticks / 2, 4);
-        }
+    ) -> Result<()> {
+        self.update_regions(camera.position, rules);
 
-        let mut ticks = self.ticks;
         for region in self.loaded_regions.iter_mut() {
             for object in region.loaded_objects.iter_mut() {
                 if object.builder_active {
@@ -98,37 +148,56 @@ impl WorldManager {
                         &mut self.tick_profile,
                     )?;
                 }
+            }
+        }
 
-                if ENABLE_SHIP_PROFILING {
-                    self.tick_profile.ship_computing_start(self.ticks);
-                }
+        if ENABLE_SHIP_PROFILING {
+            self.tick_profile.ship_computing_start();
+        }
 
-                let (ticks_left, changed_chunks) = object.tick(ticks, rules);
-                if ticks != ticks_left {
-                    info!("Ticked: {}", ticks - ticks_left)
-                }
-                ticks = ticks_left;
+        let mut changed = self.tick_scheduler.tick(
+            &mut self.loaded_regions,
+            self.streamer.region_world_size,
+            camera.position,
+            rules,
+        );
 
-                if ENABLE_SHIP_PROFILING {
-                    self.tick_profile.ship_computing_done();
-                }
+        let backlog = self.tick_scheduler.get_total_backlog();
+        if ENABLE_SHIP_PROFILING {
+            self.tick_profile
+                .ship_computing_done(self.tick_scheduler.get_total_ticks());
+
+            if self.last_backlog != 0 && backlog == 0 {
+                self.tick_profile.print_state();
+            }
+        }
+        self.last_backlog = backlog;
+
+        for (region_index, region) in self.loaded_regions.iter_mut().enumerate() {
+            for (object_index, object) in region.loaded_objects.iter_mut().enumerate() {
+                let changed_chunks = changed
+                    .iter()
+                    .position(|(r, o, _)| *r == region_index && *o == object_index)
+                    .map(|i| changed.swap_remove(i).2)
+                    .unwrap_or_default();
 
                 object.transform = Mat4::from_rotation_x(self.last_input.elapsed().as_secs_f32());
 
                 renderer.update_object(object, changed_chunks, context, frame_index, num_frames)?;
             }
         }
-        if ENABLE_SHIP_PROFILING && self.last_ticks_left == 0 && ticks != 0 {
-            self.tick_profile.print_state();
-        }
 
-        self.last_ticks_left = ticks;
-
-        if controls.f12 && self.last_input.elapsed() > INPUT_INTERVALL {
+        let save_pressed = controls.f12 && self.last_input.elapsed() > INPUT_INTERVALL;
+        if save_pressed {
             self.last_input = Instant::now();
+        }
 
-            // TODO
-            info!("Saving");
+        if save_pressed || self.last_save.elapsed() > AUTOSAVE_INTERVALL {
+            // A failed save should not end the session.
+            if let Err(err) = self.save(rules) {
+                error!("Saving failed: {err}");
+                self.last_save = Instant::now();
+            }
         }
 
         Ok(())
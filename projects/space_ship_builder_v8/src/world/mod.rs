@@ -1,10 +1,15 @@
 // pub mod asteroid;
 pub mod block_object;
 pub mod data;
+pub mod export;
 // pub mod ship;
 pub mod asteroid;
+#[cfg(feature = "render")]
 pub mod builder;
+#[cfg(feature = "render")]
 pub mod manager;
 pub mod profile;
 pub mod region;
 pub mod save;
+pub mod scheduler;
+pub mod streaming;
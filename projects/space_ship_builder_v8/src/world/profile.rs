@@ -26,12 +26,12 @@ impl TickProfile {
         self.tick_counter = 0;
     }
 
-    pub fn ship_computing_start(&mut self, ticks: usize) {
-        self.tick_counter += ticks;
+    pub fn ship_computing_start(&mut self) {
         self.start_ship_computing = Instant::now();
     }
 
-    pub fn ship_computing_done(&mut self) {
+    pub fn ship_computing_done(&mut self, ticks: usize) {
+        self.tick_counter += ticks;
         self.time_spent_computing += self.start_ship_computing.elapsed();
     }
 
@@ -1,5 +1,5 @@
 use crate::world::block_object::BlockObject;
-use octa_force::glam::IVec3;
+use glam::IVec3;
 
 pub struct Region {
     pub pos: IVec3,
@@ -1,74 +1,444 @@
+#[cfg(feature = "render")]
+use crate::render::parallax::node_parallax_mesh::RenderNode;
 use crate::rules::Rules;
 use crate::world::block_object::BlockObject;
 use crate::world::data::block::{BlockNameIndex, BLOCK_INDEX_EMPTY};
+#[cfg(feature = "render")]
+use crate::world::data::node::NodeID;
+use crate::world::region::Region;
+use anyhow::{bail, Result};
 use bitcode::{Decode, Encode};
-use octa_force::anyhow::Result;
-use octa_force::glam::Mat4;
-use std::fs;
-use std::fs::File;
-use std::io::{Read, Write};
+use glam::{IVec3, Mat4};
+use log::warn;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
+pub const SAVE_MAGIC: [u8; 4] = *b"SSBS";
+pub const SAVE_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 8;
+
+const REGION_FILE_EXTENSION: &str = "region";
+
+/// The first save format. It has no header and stores the indices of Rules::block_names.
 #[derive(Encode, Decode, PartialEq, Debug)]
+pub struct ShipSaveV0 {
+    pub blocks: Vec<([i32; 3], BlockNameIndex)>,
+    pub nodes_per_chunk: [i32; 3],
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
 pub struct ShipSave {
-    blocks: Vec<([i32; 3], BlockNameIndex)>,
-    nodes_per_chunk: [i32; 3],
+    /// Block names by string, so the save survives reordering of Rules::block_names.
+    pub palette: Vec<String>,
+    pub transform: [f32; 16],
+    pub nodes_per_chunk: [i32; 3],
+    pub seed: u64,
+    /// Rules::get_nodes_key of the rules the node_id_bits were made with.
+    pub nodes_key: u64,
+    /// Only chunks that contain non empty blocks or saved nodes.
+    pub chunks: Vec<ChunkSave>,
 }
 
-impl BlockObject {
-    pub fn save(&self, path: &str) -> Result<()> {
-        let save = self.get_save();
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct ChunkSave {
+    pub pos: [i32; 3],
+    /// Runs of (length, palette index) over the block names of the chunk.
+    pub block_runs: Vec<(u32, u16)>,
+    /// The collapsed nodes, so the object can be shown before the solver is done.
+    pub node_id_bits: Option<Vec<u32>>,
+    /// The block densities of smooth solvers. Blocks without a density are NaN.
+    pub densities: Option<Vec<f32>>,
+}
 
-        let mut file = File::create(path)?;
-        let data: Vec<u8> = bitcode::encode(&save);
-        file.write_all(&data)?;
+impl ShipSave {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HEADER_SIZE);
+        data.extend_from_slice(&SAVE_MAGIC);
+        data.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+        data.extend(bitcode::encode(self));
+        data
+    }
+
+    /// Reads every version of the save format. Saves without header are read as ShipSaveV0.
+    pub fn from_bytes(data: &[u8], rules: &Rules) -> Result<Self> {
+        if data.len() < HEADER_SIZE || data[0..4] != SAVE_MAGIC {
+            let save: ShipSaveV0 = bitcode::decode(data)?;
+            return Self::from_v0(save, rules);
+        }
+
+        let version = u32::from_le_bytes(data[4..8].try_into().unwrap());
+        match version {
+            1 => Ok(bitcode::decode(&data[HEADER_SIZE..])?),
+            _ => bail!("Save version {version} is not supported. The newest is {SAVE_VERSION}"),
+        }
+    }
+
+    pub fn from_v0(save: ShipSaveV0, rules: &Rules) -> Result<Self> {
+        check_nodes_per_chunk(save.nodes_per_chunk)?;
+
+        // V0 only stored the block name indices of the rules it was saved with.
+        let mut block_object = BlockObject::new(
+            Mat4::IDENTITY,
+            save.nodes_per_chunk[0],
+            rules.block_names.len(),
+        );
+        for (pos, block_name_index) in save.blocks {
+            if block_name_index as usize >= rules.block_names.len() {
+                bail!("Block name index {block_name_index} is not in the rules");
+            }
+            block_object.place_block(pos.into(), block_name_index);
+        }
+
+        Ok(block_object.get_save(rules, false))
+    }
+}
+
+impl BlockObject {
+    pub fn save(&self, path: &str, rules: &Rules) -> Result<()> {
+        let save = self.get_save(rules, true);
+        write_atomic(Path::new(path), &save.to_bytes())?;
 
         Ok(())
     }
 
-    pub fn get_save(&self) -> ShipSave {
-        let mut blocks = Vec::new();
+    pub fn get_save(&self, rules: &Rules, with_node_id_bits: bool) -> ShipSave {
+        let mut palette = vec![];
+        let mut palette_indices = vec![None; rules.block_names.len()];
+
+        let mut chunks = vec![];
         for chunk in self.chunks.iter() {
-            for (i, block) in chunk.block_names.iter().enumerate() {
-                if *block == BLOCK_INDEX_EMPTY {
-                    continue;
-                }
+            let is_empty = chunk
+                .block_names
+                .iter()
+                .all(|block_name_index| *block_name_index == BLOCK_INDEX_EMPTY);
+            let has_nodes = chunk.node_id_bits.iter().any(|bits| *bits != 0);
+            let has_densities = !chunk.densities.is_empty();
+            if is_empty && !(with_node_id_bits && has_nodes) && !has_densities {
+                continue;
+            }
 
-                let block_pos =
-                    self.get_block_world_pos_from_block_index_and_chunk_pos(i, chunk.pos / 2);
+            let mut block_runs: Vec<(u32, u16)> = vec![];
+            for block_name_index in chunk.block_names.iter() {
+                let palette_index = *palette_indices[*block_name_index as usize]
+                    .get_or_insert_with(|| {
+                        palette.push(rules.block_names[*block_name_index as usize].to_owned());
+                        (palette.len() - 1) as u16
+                    });
 
-                blocks.push((block_pos.into(), *block))
+                match block_runs.last_mut() {
+                    Some((len, index)) if *index == palette_index => *len += 1,
+                    _ => block_runs.push((1, palette_index)),
+                }
             }
+
+            chunks.push(ChunkSave {
+                pos: chunk.pos.into(),
+                block_runs,
+                node_id_bits: with_node_id_bits.then(|| chunk.node_id_bits.clone()),
+                densities: has_densities.then(|| chunk.densities.clone()),
+            })
         }
 
         ShipSave {
-            blocks,
+            palette,
+            transform: self.transform.to_cols_array(),
             nodes_per_chunk: self.nodes_per_chunk.into(),
+            seed: self.seed,
+            nodes_key: if with_node_id_bits {
+                rules.get_nodes_key()
+            } else {
+                0
+            },
+            chunks,
         }
     }
 
-    pub fn load(path: &str, rules: &Rules) -> Result<Self> {
-        let mut file = File::open(path)?;
+    /// Rebuilds the object from its block names with the new rules, because the caches and node ids
+    /// of the old rules are not valid anymore. The object is solved again.
+    pub fn reload_rules(&mut self, old_rules: &Rules, rules: &Rules) -> Result<()> {
+        *self = self.get_reloaded(old_rules, rules)?;
+        Ok(())
+    }
 
-        let metadata = fs::metadata(path)?;
-        let mut data = vec![0; metadata.len() as usize];
-        file.read(&mut data)?;
-        let ship_save: ShipSave = bitcode::decode(&data)?;
+    /// A copy of the object with the same blocks for the new rules. Fails if a block name of the
+    /// object is not in the new rules, instead of loading its blocks as empty like new_from_save.
+    pub fn get_reloaded(&self, old_rules: &Rules, rules: &Rules) -> Result<Self> {
+        let save = self.get_save(old_rules, false);
+        if let Some(name) = save
+            .palette
+            .iter()
+            .find(|name| !rules.block_names.contains(name))
+        {
+            bail!("Block name {name} is not in the new rules");
+        }
+
+        let mut block_object = BlockObject::new_from_save(save, rules)?;
+        block_object.builder_active = self.builder_active;
 
-        let ship = Self::new_from_save(ship_save, rules);
-        Ok(ship)
+        Ok(block_object)
     }
 
-    pub fn new_from_save(save: ShipSave, rules: &Rules) -> Self {
-        let mut ship = BlockObject::new(
-            Mat4::IDENTITY,
+    pub fn load(path: &str, rules: &Rules) -> Result<Self> {
+        let data = fs::read(path)?;
+        let save = ShipSave::from_bytes(&data, rules)?;
+
+        Self::new_from_save(save, rules)
+    }
+
+    /// Places all saved blocks. Saved node_id_bits are shown until the solver has collapsed the
+    /// blocks again. They are dropped if the nodes of the rules changed since saving.
+    pub fn new_from_save(save: ShipSave, rules: &Rules) -> Result<Self> {
+        check_nodes_per_chunk(save.nodes_per_chunk)?;
+
+        let mut block_object = BlockObject::new(
+            Mat4::from_cols_array(&save.transform),
             save.nodes_per_chunk[0],
             rules.block_names.len(),
         );
+        block_object.seed = save.seed;
+        let nodes_match = save.nodes_key == rules.get_nodes_key();
+
+        let palette: Vec<_> = save
+            .palette
+            .iter()
+            .map(|name| {
+                let index = rules.block_names.iter().position(|test| test == name);
+                if index.is_none() {
+                    warn!("Block name {name} of the save is not in the rules. Using Empty.");
+                }
+                index.map_or(BLOCK_INDEX_EMPTY, |i| i as BlockNameIndex)
+            })
+            .collect();
+
+        for chunk_save in save.chunks {
+            let chunk_pos = IVec3::from(chunk_save.pos);
+
+            let mut blocks = vec![];
+            let mut block_index = 0;
+            for (len, palette_index) in chunk_save.block_runs {
+                if palette_index as usize >= palette.len() {
+                    bail!("Palette index {palette_index} is out of range");
+                }
+                let block_name_index = palette[palette_index as usize];
+
+                for _ in 0..len {
+                    if block_name_index != BLOCK_INDEX_EMPTY {
+                        blocks.push((block_index, block_name_index));
+                    }
+                    block_index += 1;
+                }
+            }
+            if block_index > block_object.block_length {
+                bail!("Chunk {chunk_pos} has more blocks than fit in a chunk");
+            }
+            block_object.place_chunk_blocks(chunk_pos, blocks);
+
+            let Some(chunk_index) = block_object.chunks.iter().position(|c| c.pos == chunk_pos)
+            else {
+                bail!("Chunk {chunk_pos} was not created");
+            };
+            if let Some(node_id_bits) = chunk_save.node_id_bits {
+                if node_id_bits.len() != block_object.nodes_length {
+                    bail!("Chunk {chunk_pos} has the wrong number of nodes");
+                }
+                if nodes_match {
+                    #[cfg(feature = "render")]
+                    for (node_index, bits) in node_id_bits.iter().enumerate() {
+                        let index_with_padding =
+                            block_object.get_node_index_plus_padding_from_node_index(node_index);
+                        block_object.chunks[chunk_index].render_nodes[index_with_padding] =
+                            RenderNode(NodeID::from(*bits).is_some());
+                    }
+
+                    block_object.chunks[chunk_index].node_id_bits = node_id_bits;
+                }
+            }
+
+            if let Some(densities) = chunk_save.densities {
+                if densities.len() != block_object.block_length {
+                    bail!("Chunk {chunk_pos} has the wrong number of densities");
+                }
+                block_object.chunks[chunk_index].densities = densities;
+            }
+        }
+
+        Ok(block_object)
+    }
+}
+
+/// The objects are stored as encoded ShipSaves so each keeps its own version.
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct RegionSave {
+    pub pos: [i32; 3],
+    pub objects: Vec<Vec<u8>>,
+}
+
+impl Region {
+    pub fn get_save(&self, rules: &Rules) -> RegionSave {
+        RegionSave {
+            pos: self.pos.into(),
+            objects: self
+                .loaded_objects
+                .iter()
+                .map(|object| object.get_save(rules, true).to_bytes())
+                .collect(),
+        }
+    }
+
+    /// Reloads every object. All old objects are kept if any of them fails to reload.
+    pub fn reload_rules(&mut self, old_rules: &Rules, rules: &Rules) -> Result<()> {
+        self.loaded_objects = self.get_reloaded_objects(old_rules, rules)?;
+        Ok(())
+    }
+
+    pub fn get_reloaded_objects(
+        &self,
+        old_rules: &Rules,
+        rules: &Rules,
+    ) -> Result<Vec<BlockObject>> {
+        self.loaded_objects
+            .iter()
+            .map(|object| object.get_reloaded(old_rules, rules))
+            .collect()
+    }
+
+    pub fn new_from_save(save: RegionSave, rules: &Rules) -> Result<Self> {
+        let mut region = Region::new(save.pos.into());
+        for data in save.objects {
+            let object_save = ShipSave::from_bytes(&data, rules)?;
+            region
+                .loaded_objects
+                .push(BlockObject::new_from_save(object_save, rules)?);
+        }
+
+        Ok(region)
+    }
+}
+
+/// Chunks are cubes of a power of two blocks and every block has two nodes per side.
+fn check_nodes_per_chunk(nodes_per_chunk: [i32; 3]) -> Result<()> {
+    let [size, ..] = nodes_per_chunk;
+    let is_cube = nodes_per_chunk == [size; 3];
+    if !is_cube || size < 2 || size % 2 != 0 || !((size / 2) as u32).is_power_of_two() {
+        bail!("Chunk size {nodes_per_chunk:?} is not valid");
+    }
+
+    Ok(())
+}
+
+/// Writes to a temporary file first, so a crash never leaves a half written file behind.
+/// The data is on disk before the rename, and the rename is on disk before returning.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)?;
+
+    // Directories can not be opened as files on windows.
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+pub fn get_region_path(dir: &Path, pos: IVec3) -> PathBuf {
+    dir.join(format!(
+        "{}_{}_{}.{REGION_FILE_EXTENSION}",
+        pos.x, pos.y, pos.z
+    ))
+}
+
+pub fn save_region(dir: &Path, region: &Region, rules: &Rules) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let data = bitcode::encode(&region.get_save(rules));
+    write_atomic(&get_region_path(dir, region.pos), &data)
+}
+
+pub fn load_region(dir: &Path, pos: IVec3, rules: &Rules) -> Result<Region> {
+    let data = fs::read(get_region_path(dir, pos))?;
+    let save: RegionSave = bitcode::decode(&data)?;
+
+    Region::new_from_save(save, rules)
+}
+
+/// Regions that are not passed keep their file, because they might only be unloaded.
+pub fn save_regions(dir: &Path, regions: &[Region], rules: &Rules) -> Result<()> {
+    for region in regions.iter() {
+        save_region(dir, region, rules)?;
+    }
+
+    Ok(())
+}
+
+pub fn load_regions(dir: &Path, rules: &Rules) -> Result<Vec<Region>> {
+    let mut regions = vec![];
+    for path in get_region_files(dir)? {
+        let data = fs::read(&path)?;
+        let save: RegionSave = bitcode::decode(&data)?;
+        regions.push(Region::new_from_save(save, rules)?);
+    }
 
-        for (pos, block) in save.blocks {
-            ship.place_block(pos.into(), block);
+    Ok(regions)
+}
+
+fn get_region_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|ext| ext == REGION_FILE_EXTENSION)
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    Ok(paths)
+}
+
+pub fn get_backup_path(dir: &Path, index: usize) -> PathBuf {
+    let mut path = dir.as_os_str().to_owned();
+    path.push(format!(".backup{index}"));
+    PathBuf::from(path)
+}
+
+/// Shifts the backups of the save dir by one and copies the current save into the first backup.
+/// The oldest backup is removed.
+pub fn rotate_backups(dir: &Path, num_backups: usize) -> Result<()> {
+    if num_backups == 0 || !dir.exists() {
+        return Ok(());
+    }
+
+    let oldest = get_backup_path(dir, num_backups - 1);
+    if oldest.exists() {
+        fs::remove_dir_all(&oldest)?;
+    }
+    for i in (0..num_backups - 1).rev() {
+        let path = get_backup_path(dir, i);
+        if path.exists() {
+            fs::rename(&path, get_backup_path(dir, i + 1))?;
         }
+    }
 
-        ship
+    let backup = get_backup_path(dir, 0);
+    fs::create_dir_all(&backup)?;
+    for path in get_region_files(dir)? {
+        fs::copy(&path, backup.join(path.file_name().unwrap()))?;
     }
+
+    Ok(())
 }
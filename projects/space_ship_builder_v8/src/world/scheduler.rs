@@ -0,0 +1,130 @@
+use crate::rules::Rules;
+use crate::world::block_object::budget::TickBacklog;
+use crate::world::block_object::ChunkIndex;
+use crate::world::region::Region;
+use glam::Vec3;
+use std::time::{Duration, Instant};
+
+pub const TICK_BUDGET: Duration = Duration::from_millis(10);
+pub const MAX_TICKED_REGIONS: usize = 8;
+
+/// Objects the builder works on get this much more time than an object at the camera.
+const BUILDER_PRIORITY: f32 = 4.0;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ObjectTickStats {
+    pub region_index: usize,
+    pub object_index: usize,
+
+    pub priority: f32,
+    pub budget: Duration,
+    pub time_used: Duration,
+    pub ticks: usize,
+    pub backlog: TickBacklog,
+}
+
+/// Splits a fixed time budget per frame between all objects that have work left.
+pub struct TickScheduler {
+    pub budget: Duration,
+    /// Only the regions closest to the camera and regions with builder objects are ticked.
+    pub max_ticked_regions: usize,
+    /// Stats of the last frame for every object with work left, sorted by priority.
+    pub stats: Vec<ObjectTickStats>,
+}
+
+impl TickScheduler {
+    pub fn new(budget: Duration) -> Self {
+        TickScheduler {
+            budget,
+            max_ticked_regions: MAX_TICKED_REGIONS,
+            stats: vec![],
+        }
+    }
+
+    /// Ticks the objects in order of priority. Every object gets its share of the time that is
+    /// left, so time an object does not need goes to the following objects.
+    /// Returns the changed chunks as (region index, object index, chunks).
+    pub fn tick(
+        &mut self,
+        regions: &mut [Region],
+        region_world_size: f32,
+        camera_pos: Vec3,
+        rules: &Rules,
+    ) -> Vec<(usize, usize, Vec<ChunkIndex>)> {
+        let start = Instant::now();
+
+        let mut region_order: Vec<_> = regions
+            .iter()
+            .enumerate()
+            .map(|(region_index, region)| {
+                let builder_active = region
+                    .loaded_objects
+                    .iter()
+                    .any(|object| object.builder_active);
+                let center = (region.pos.as_vec3() + Vec3::splat(0.5)) * region_world_size;
+                (region_index, !builder_active, center.distance(camera_pos))
+            })
+            .collect();
+        region_order.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)));
+        region_order.truncate(self.max_ticked_regions);
+
+        self.stats.clear();
+        for (region_index, _, _) in region_order {
+            let region = &regions[region_index];
+            for (object_index, object) in region.loaded_objects.iter().enumerate() {
+                let backlog = object.get_backlog();
+                if backlog.is_empty() {
+                    continue;
+                }
+
+                let priority = if object.builder_active {
+                    BUILDER_PRIORITY
+                } else {
+                    let dist = object.transform.w_axis.truncate().distance(camera_pos);
+                    1.0 / (1.0 + dist / region_world_size)
+                };
+
+                self.stats.push(ObjectTickStats {
+                    region_index,
+                    object_index,
+                    priority,
+                    backlog,
+                    ..Default::default()
+                });
+            }
+        }
+        self.stats.sort_by(|a, b| b.priority.total_cmp(&a.priority));
+
+        let mut priority_left: f32 = self.stats.iter().map(|stats| stats.priority).sum();
+        let mut changed = vec![];
+        for stats in self.stats.iter_mut() {
+            let time_left = self.budget.saturating_sub(start.elapsed());
+            if time_left.is_zero() {
+                break;
+            }
+
+            let share = (stats.priority / priority_left).min(1.0);
+            priority_left -= stats.priority;
+            stats.budget = time_left.mul_f32(share);
+
+            let object = &mut regions[stats.region_index].loaded_objects[stats.object_index];
+            let object_start = Instant::now();
+            let (ticks, changed_chunks) = object.tick_for(stats.budget, rules);
+            stats.time_used = object_start.elapsed();
+            stats.ticks = ticks;
+            stats.backlog = object.get_backlog();
+
+            changed.push((stats.region_index, stats.object_index, changed_chunks));
+        }
+
+        changed
+    }
+
+    pub fn get_total_backlog(&self) -> usize {
+        self.stats.iter().map(|stats| stats.backlog.total()).sum()
+    }
+
+    pub fn get_total_ticks(&self) -> usize {
+        self.stats.iter().map(|stats| stats.ticks).sum()
+    }
+}
@@ -0,0 +1,154 @@
+use crate::math::random::get_seeded_value;
+use crate::rules::Rules;
+use crate::world::asteroid::AsteroidGenerator;
+use crate::world::region::Region;
+use crate::world::save::{get_region_path, load_region, save_region};
+use anyhow::Result;
+use glam::{ivec3, vec3, IVec3, Mat4, Vec3};
+use log::info;
+use std::path::PathBuf;
+
+/// Chance that a generated region contains an asteroid.
+const ASTEROID_CHANCE: f32 = 0.3;
+const ASTEROID_SIZE: i32 = 11;
+
+/// Loads the regions around the camera from disk or generates them and persists regions that
+/// are too far away.
+pub struct RegionStreamer {
+    /// Side length of a region in world units.
+    pub region_world_size: f32,
+    /// Regions up to this many regions away from the camera region are loaded.
+    pub load_radius: i32,
+    /// Regions further away than this are unloaded. Bigger than load_radius, so regions at the
+    /// border are not loaded and unloaded every frame.
+    pub unload_radius: i32,
+    /// Loading or generating regions is slow, so only this many are loaded per update.
+    pub max_loads_per_update: usize,
+
+    pub save_dir: PathBuf,
+    pub seed: u64,
+
+    /// Regions that failed to load. They are not tried again, so they do not block the regions
+    /// behind them.
+    pub failed_region_poses: Vec<IVec3>,
+}
+
+impl RegionStreamer {
+    pub fn new(region_world_size: f32, save_dir: PathBuf) -> Self {
+        RegionStreamer {
+            region_world_size,
+            load_radius: 1,
+            unload_radius: 2,
+            max_loads_per_update: 1,
+
+            save_dir,
+            seed: 0,
+
+            failed_region_poses: vec![],
+        }
+    }
+
+    pub fn get_region_pos(&self, world_pos: Vec3) -> IVec3 {
+        (world_pos / self.region_world_size).floor().as_ivec3()
+    }
+
+    pub fn get_region_center(&self, region_pos: IVec3) -> Vec3 {
+        (region_pos.as_vec3() + Vec3::splat(0.5)) * self.region_world_size
+    }
+
+    pub fn update(
+        &mut self,
+        regions: &mut Vec<Region>,
+        camera_pos: Vec3,
+        rules: &Rules,
+        asteroid_generator: &AsteroidGenerator,
+    ) -> Result<()> {
+        let camera_region_pos = self.get_region_pos(camera_pos);
+
+        let mut i = 0;
+        while i < regions.len() {
+            let dist = (regions[i].pos - camera_region_pos).abs().max_element();
+            let builder_active = regions[i]
+                .loaded_objects
+                .iter()
+                .any(|object| object.builder_active);
+
+            if dist > self.unload_radius && !builder_active {
+                save_region(&self.save_dir, &regions[i], rules)?;
+                let region = regions.swap_remove(i);
+                info!("Unloaded region {}", region.pos);
+            } else {
+                i += 1;
+            }
+        }
+
+        let mut missing = vec![];
+        for x in -self.load_radius..=self.load_radius {
+            for y in -self.load_radius..=self.load_radius {
+                for z in -self.load_radius..=self.load_radius {
+                    let pos = camera_region_pos + ivec3(x, y, z);
+                    if !regions.iter().any(|region| region.pos == pos)
+                        && !self.failed_region_poses.contains(&pos)
+                    {
+                        missing.push(pos);
+                    }
+                }
+            }
+        }
+        missing.sort_by_key(|pos| (*pos - camera_region_pos).length_squared());
+
+        for pos in missing.into_iter().take(self.max_loads_per_update) {
+            match self.load_or_generate_region(pos, rules, asteroid_generator) {
+                Ok(region) => regions.push(region),
+                Err(err) => {
+                    self.failed_region_poses.push(pos);
+                    return Err(err.context(format!("Region {pos} is skipped")));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load_or_generate_region(
+        &self,
+        pos: IVec3,
+        rules: &Rules,
+        asteroid_generator: &AsteroidGenerator,
+    ) -> Result<Region> {
+        if get_region_path(&self.save_dir, pos).exists() {
+            info!("Loading region {pos}");
+            return load_region(&self.save_dir, pos, rules);
+        }
+
+        info!("Generating region {pos}");
+        Ok(self.generate_region(pos, asteroid_generator))
+    }
+
+    /// The same seed and position always generate the same region.
+    pub fn generate_region(&self, pos: IVec3, asteroid_generator: &AsteroidGenerator) -> Region {
+        let mut region = Region::new(pos);
+
+        let mut rng = fastrand::Rng::with_seed(get_seeded_value(self.seed, pos, 0));
+        let asteroid_pos = if pos == IVec3::ZERO {
+            // The start asteroid
+            Some(vec3(50.0, 0.0, 0.0))
+        } else if rng.f32() < ASTEROID_CHANCE {
+            let offset = vec3(rng.f32(), rng.f32(), rng.f32()) * self.region_world_size;
+            Some(pos.as_vec3() * self.region_world_size + offset)
+        } else {
+            None
+        };
+
+        if let Some(asteroid_pos) = asteroid_pos {
+            let asteroid = asteroid_generator.generate(
+                Mat4::from_translation(asteroid_pos),
+                ASTEROID_SIZE,
+                &mut rng,
+            );
+            region.loaded_objects.push(asteroid);
+        }
+
+        region
+    }
+}
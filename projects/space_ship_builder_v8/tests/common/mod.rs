@@ -0,0 +1,28 @@
+// Not every test file uses all of the setup.
+#![allow(dead_code)]
+
+use space_ship_builder_v8::rules::manifest::RuleManifest;
+use space_ship_builder_v8::rules::{Rules, RULE_MANIFEST_PATH};
+use space_ship_builder_v8::world::data::voxel_loader::VoxelLoader;
+
+pub const VOX_FILE_PATH: &str = "./assets/space_ship.vox";
+pub const CHUNK_SIZE: i32 = 32;
+pub const MAX_TICKS: usize = 1_000_000;
+
+pub fn load_voxel_loader() -> VoxelLoader {
+    VoxelLoader::new(VOX_FILE_PATH).unwrap()
+}
+
+pub fn load_manifest() -> RuleManifest {
+    RuleManifest::load(RULE_MANIFEST_PATH).unwrap()
+}
+
+/// The rules of the assets.
+pub fn load_rules() -> Rules {
+    load_rules_from(&load_voxel_loader())
+}
+
+/// The rules of the manifest, made from a voxel loader the test changed.
+pub fn load_rules_from(voxel_loader: &VoxelLoader) -> Rules {
+    Rules::new(voxel_loader, &load_manifest()).unwrap()
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use common::{load_rules, CHUNK_SIZE, MAX_TICKS};
+use dot_vox::SceneNode;
+use glam::{ivec3, vec3, IVec3, Mat4, Vec3};
+use space_ship_builder_v8::math::rotation::Rot;
+use space_ship_builder_v8::rules::Rules;
+use space_ship_builder_v8::world::block_object::BlockObject;
+use space_ship_builder_v8::world::data::node::{Material, NodeID, Voxel, NODE_SIZE, VOXEL_EMPTY};
+use space_ship_builder_v8::world::export::mesh::VoxelMesh;
+use space_ship_builder_v8::world::export::vox::{get_vox, VOX_MAX_MODEL_SIZE};
+use space_ship_builder_v8::world::export::VoxelGrid;
+
+/// An object with the same node at the given world node positions.
+fn get_node_object(rules: &Rules, node_poses: &[IVec3]) -> (BlockObject, NodeID) {
+    let node_index = rules
+        .nodes
+        .iter()
+        .position(|node| node.voxels.iter().any(|voxel| *voxel != VOXEL_EMPTY))
+        .unwrap();
+    let node_id = NodeID::new(node_index, Rot::IDENTITY);
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    for node_pos in node_poses {
+        let chunk_pos = (*node_pos).div_euclid(IVec3::ONE * CHUNK_SIZE) * CHUNK_SIZE;
+        if !block_object.has_chunk(chunk_pos) {
+            block_object.add_chunk(chunk_pos);
+        }
+
+        let node_index = block_object.get_node_index_from_node_pos(*node_pos - chunk_pos);
+        let chunk = block_object
+            .chunks
+            .iter_mut()
+            .find(|chunk| chunk.pos == chunk_pos)
+            .unwrap();
+        chunk.node_id_bits[node_index] = node_id.into();
+    }
+
+    (block_object, node_id)
+}
+
+#[test]
+fn node_id_bits_decode_to_node_ids() {
+    for rot in Rot::IDENTITY.get_all_permutations() {
+        let node_id = NodeID::new(42, rot);
+        let bits: u32 = node_id.into();
+        assert_eq!(NodeID::from(bits), node_id);
+    }
+    assert_eq!(NodeID::from(0u32), NodeID::empty());
+}
+
+#[test]
+fn voxel_grid_expands_collapsed_nodes() {
+    let rules = load_rules();
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    assert!(block_object.get_voxel_grid(&rules).is_empty());
+
+    let hull = rules.get_block_name_index("Hull");
+    for x in 0..4 {
+        block_object.place_block(ivec3(x, 0, 0), hull);
+    }
+    block_object.tick(MAX_TICKS, &rules);
+
+    let grid = block_object.get_voxel_grid(&rules);
+    assert!(!grid.is_empty());
+    assert_eq!(grid.size % NODE_SIZE, IVec3::ZERO);
+    assert_eq!(grid.voxels.len(), grid.size.element_product() as usize);
+    assert!(grid.voxels.iter().any(|voxel| *voxel != VOXEL_EMPTY));
+}
+
+#[test]
+fn vox_export_splits_large_objects() {
+    let rules = load_rules();
+    let (block_object, node_id) = get_node_object(&rules, &[ivec3(0, 0, 0), ivec3(70, 1, 0)]);
+    let grid = block_object.get_voxel_grid(&rules);
+    assert_eq!(grid.size, ivec3(71, 2, 1) * NODE_SIZE);
+
+    let data = dot_vox::load_bytes(&block_object.get_vox(&rules).unwrap()).unwrap();
+    assert_eq!(data.models.len(), 2);
+    for (i, color) in data.palette.iter().enumerate() {
+        assert_eq!(Material::from(color), rules.materials[i]);
+    }
+
+    let node_voxels = rules.nodes[node_id.index]
+        .voxels
+        .iter()
+        .filter(|voxel| **voxel != VOXEL_EMPTY)
+        .count();
+    let mut num_voxels = 0;
+    for node in data.scenes.iter() {
+        let SceneNode::Transform { frames, child, .. } = node else {
+            continue;
+        };
+        let SceneNode::Shape { models, .. } = &data.scenes[*child as usize] else {
+            continue;
+        };
+
+        let model = &data.models[models[0].model_id as usize];
+        let size = ivec3(
+            model.size.x as i32,
+            model.size.y as i32,
+            model.size.z as i32,
+        );
+        assert!(size.cmple(IVec3::ONE * VOX_MAX_MODEL_SIZE).all());
+
+        let p = frames[0].position().unwrap();
+        let start = ivec3(p.x, p.y, p.z) - size / 2;
+        for voxel in model.voxels.iter() {
+            let pos = start + ivec3(voxel.x as i32, voxel.y as i32, voxel.z as i32);
+            assert_eq!(grid.get_voxel(pos - grid.min), voxel.i);
+            num_voxels += 1;
+        }
+    }
+    assert_eq!(num_voxels, node_voxels * 2);
+}
+
+#[test]
+fn vox_export_rejects_what_magica_voxel_can_not_open() {
+    let rules = load_rules();
+    let block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    assert!(block_object.get_vox(&rules).is_err());
+    assert!(get_vox(&get_grid(IVec3::ZERO, &[VOXEL_EMPTY; 3]), &rules.materials).is_err());
+
+    assert!(get_vox(&get_grid(IVec3::ZERO, &[1, 254]), &rules.materials).is_ok());
+    assert!(get_vox(&get_grid(IVec3::ZERO, &[1, 255]), &rules.materials).is_err());
+}
+
+fn get_grid(min: IVec3, voxels: &[Voxel]) -> VoxelGrid {
+    VoxelGrid {
+        min,
+        size: ivec3(voxels.len() as i32, 1, 1),
+        voxels: voxels.to_owned(),
+    }
+}
+
+#[test]
+fn greedy_mesh_merges_faces_of_the_same_material() {
+    assert_eq!(VoxelMesh::new(&VoxelGrid::default()).num_quads(), 0);
+
+    let single = VoxelMesh::new(&get_grid(ivec3(5, 6, 7), &[1]));
+    assert_eq!(single.num_quads(), 6);
+    assert_eq!(single.positions.len(), 24);
+    assert_eq!(single.normals.len(), 24);
+    assert_eq!(single.indices.len(), 36);
+    let min = single
+        .positions
+        .iter()
+        .fold(Vec3::MAX, |min, p| min.min(*p));
+    let max = single
+        .positions
+        .iter()
+        .fold(Vec3::MIN, |max, p| max.max(*p));
+    assert_eq!((min, max), (vec3(5.0, 6.0, 7.0), vec3(6.0, 7.0, 8.0)));
+
+    let cube = VoxelGrid {
+        min: IVec3::ZERO,
+        size: IVec3::ONE * 3,
+        voxels: vec![1; 27],
+    };
+    assert_eq!(VoxelMesh::new(&cube).positions.len(), 24);
+
+    // Faces between voxels are hidden, but faces of different materials are not merged.
+    let bar = VoxelMesh::new(&get_grid(IVec3::ZERO, &[1, 1, 2, VOXEL_EMPTY, 2]));
+    assert_eq!(bar.num_quads(), 6 + 10);
+    assert_eq!(bar.positions.len(), 64);
+    assert_eq!(bar.get_used_voxels(), vec![1, 2]);
+}
+
+#[test]
+fn mesh_is_written_as_obj_and_gltf() {
+    let rules = load_rules();
+    let mesh = VoxelMesh::new(&get_grid(IVec3::ZERO, &[1, 2]));
+    assert_eq!(mesh.positions.len(), 40);
+
+    let obj = mesh.get_obj("ship.mtl");
+    let count_lines =
+        |text: &str, start: &str| text.lines().filter(|line| line.starts_with(start)).count();
+    assert_eq!(count_lines(&obj, "mtllib ship.mtl"), 1);
+    assert_eq!(count_lines(&obj, "v "), 40);
+    assert_eq!(count_lines(&obj, "vn "), 40);
+    assert_eq!(count_lines(&obj, "f "), 20);
+    assert_eq!(count_lines(&obj, "usemtl "), 2);
+    assert_eq!(count_lines(&mesh.get_mtl(&rules.materials), "newmtl "), 2);
+
+    let glb = mesh.get_glb(&rules.materials);
+    let read_u32 =
+        |offset: usize| u32::from_le_bytes(glb[offset..(offset + 4)].try_into().unwrap());
+    assert_eq!(&glb[0..4], b"glTF");
+    assert_eq!(read_u32(4), 2);
+    assert_eq!(read_u32(8) as usize, glb.len());
+    assert_eq!(glb.len() % 4, 0);
+
+    let json_length = read_u32(12) as usize;
+    let json = std::str::from_utf8(&glb[20..(20 + json_length)]).unwrap();
+    assert!(json.contains(r#""componentType":5126,"count":40,"type":"VEC3""#));
+    assert_eq!(json.matches(r#""componentType":5125"#).count(), 2);
+
+    // Positions, normals and one index per triangle corner.
+    let bin_length = read_u32(20 + json_length) as usize;
+    assert_eq!(bin_length, 40 * 12 * 2 + 60 * 4);
+    assert_eq!(glb.len(), 20 + json_length + 8 + bin_length);
+}
+
+#[test]
+fn block_object_mesh_covers_its_nodes() {
+    let rules = load_rules();
+    let (block_object, node_id) = get_node_object(&rules, &[ivec3(1, 2, 3)]);
+    let grid = block_object.get_voxel_grid(&rules);
+    assert_eq!(grid.min, ivec3(1, 2, 3) * NODE_SIZE);
+
+    let mesh = block_object.get_mesh(&rules);
+    assert!(mesh.num_quads() > 0);
+    for pos in mesh.positions.iter() {
+        assert!(pos.cmpge(grid.min.as_vec3()).all());
+        assert!(pos.cmple((grid.min + grid.size).as_vec3()).all());
+    }
+    for voxel in mesh.get_used_voxels() {
+        assert!(rules.nodes[node_id.index].voxels.contains(&voxel));
+    }
+}
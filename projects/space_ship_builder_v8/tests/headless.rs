@@ -0,0 +1,1360 @@
+mod common;
+
+use common::{
+    load_manifest, load_rules, load_rules_from, load_voxel_loader, CHUNK_SIZE, MAX_TICKS,
+};
+use dot_vox::{Dict, Frame, SceneNode};
+use glam::{ivec3, vec3, IVec3, Mat3, Mat4, Vec3};
+use space_ship_builder_v8::math::get_neighbors_without_zero;
+use space_ship_builder_v8::math::random::{get_seeded_value, get_weighted_seeded_value};
+use space_ship_builder_v8::math::rotation::Rot;
+use space_ship_builder_v8::rules::cache::{get_rule_cache_key, get_rule_cache_path};
+use space_ship_builder_v8::rules::hull::MultiBlockReq;
+use space_ship_builder_v8::rules::manifest::RuleManifest;
+use space_ship_builder_v8::rules::marching_cubes::{NodeReq, DENSITY_ISO_LEVEL};
+use space_ship_builder_v8::rules::registry::SolverRegistry;
+use space_ship_builder_v8::rules::solver::{SolverCacheIndex, SolverFunctions};
+use space_ship_builder_v8::rules::validation::{RuleIssueKind, RuleReport};
+use space_ship_builder_v8::rules::{Prio, Rules};
+use space_ship_builder_v8::world::asteroid::AsteroidGenerator;
+use space_ship_builder_v8::world::block_object::area::{get_box, get_hollow_box, get_line, Mirror};
+use space_ship_builder_v8::world::block_object::contradiction::{
+    Backtracker, Contradiction, MAX_CONTRADICTIONS,
+};
+use space_ship_builder_v8::world::block_object::replay::ReplayOperation;
+use space_ship_builder_v8::world::block_object::{BlockObject, ChunkIndex};
+use space_ship_builder_v8::world::data::block::{Block, BlockIndex};
+use space_ship_builder_v8::world::data::node::NodeID;
+use space_ship_builder_v8::world::region::Region;
+use space_ship_builder_v8::world::scheduler::TickScheduler;
+use std::f32::consts::FRAC_PI_2;
+use std::time::Duration;
+
+const MAX_BACKTRACKS: usize = 4;
+
+#[test]
+fn hull_collapses_without_renderer() {
+    let rules = load_rules();
+    let hull = rules.get_block_name_index("Hull");
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    for x in 0..3 {
+        for y in 0..3 {
+            for z in 0..3 {
+                block_object.place_block(ivec3(x, y, z), hull);
+            }
+        }
+    }
+
+    let (ticks_left, changed_chunks) = block_object.tick(MAX_TICKS, &rules);
+
+    assert!(ticks_left > 0, "Solver did not finish in {MAX_TICKS} ticks");
+    assert!(!changed_chunks.is_empty());
+    assert!(block_object
+        .chunks
+        .iter()
+        .any(|chunk| chunk.node_id_bits.iter().any(|bits| *bits != 0)));
+}
+
+#[test]
+fn parallel_tick_matches_tick() {
+    let rules = load_rules();
+    let hull = rules.get_block_name_index("Hull");
+    let stone = rules.get_block_name_index("Stone");
+
+    // More blocks than the smallest wave that runs on the thread pool, over two chunks.
+    let mut single = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    let mut parallel = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    for x in 12..20 {
+        for y in 0..4 {
+            for z in 0..4 {
+                let block_name_index = if y == 3 { stone } else { hull };
+                single.place_block(ivec3(x, y, z), block_name_index);
+                parallel.place_block(ivec3(x, y, z), block_name_index);
+            }
+        }
+    }
+    assert!(parallel.get_backlog().to_reset > 64);
+
+    let (ticks_left, _) = single.tick(MAX_TICKS, &rules);
+    assert!(ticks_left > 0);
+
+    // All resets are done before the first propergation, which is solved as a wave too.
+    while parallel.get_backlog().to_reset != 0 {
+        let to_reset = parallel.get_backlog().to_reset;
+        parallel.tick_parallel(to_reset, &rules);
+    }
+    assert!(parallel.get_backlog().to_propergate > 64);
+    let (ticks_left, _) = parallel.tick_parallel(MAX_TICKS, &rules);
+    assert!(ticks_left > 0);
+
+    assert!(single.contradictions.is_empty());
+    for chunk in single.chunks.iter() {
+        let parallel_chunk = parallel.chunks.iter().find(|c| c.pos == chunk.pos).unwrap();
+        assert_eq!(parallel_chunk.node_id_bits, chunk.node_id_bits);
+    }
+}
+
+#[test]
+fn replay_reproduces_collapse() {
+    let rules = load_rules();
+    let hull = rules.get_block_name_index("Hull");
+
+    let mut block_object = BlockObject::new(
+        Mat4::from_translation(vec3(1.0, 2.0, 3.0)),
+        CHUNK_SIZE,
+        rules.block_names.len(),
+    );
+    block_object.seed = 42;
+    block_object.start_recording();
+    for x in 0..4 {
+        block_object.place_block(ivec3(x, 0, 0), hull);
+        block_object.tick(100, &rules);
+    }
+    block_object.place_block(ivec3(0, 1, 0), hull);
+    block_object.tick(MAX_TICKS, &rules);
+    let log = block_object.stop_recording().unwrap();
+
+    let replayed = BlockObject::new_from_replay(&log, &rules).unwrap();
+    assert_eq!(replayed.transform, block_object.transform);
+    assert_eq!(replayed.chunks.len(), block_object.chunks.len());
+    for (chunk, replayed_chunk) in block_object.chunks.iter().zip(replayed.chunks.iter()) {
+        assert_eq!(chunk.pos, replayed_chunk.pos);
+        assert_eq!(chunk.node_id_bits, replayed_chunk.node_id_bits);
+    }
+}
+
+#[test]
+fn replay_reproduces_parallel_ticks() {
+    let rules = load_rules();
+    let hull = rules.get_block_name_index("Hull");
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    block_object.seed = 5;
+    block_object.start_recording();
+    for x in 12..20 {
+        for y in 0..4 {
+            for z in 0..4 {
+                block_object.place_block(ivec3(x, y, z), hull);
+            }
+        }
+    }
+
+    // Waves of different sizes with collapses in between.
+    for ticks in [10, 100, 1_000] {
+        block_object.tick_parallel(ticks, &rules);
+    }
+    block_object.place_block(ivec3(20, 0, 0), hull);
+    let (ticks_left, _) = block_object.tick_parallel(MAX_TICKS, &rules);
+    assert!(ticks_left > 0);
+    let log = block_object.stop_recording().unwrap();
+    assert!(log
+        .operations
+        .iter()
+        .all(|operation| !matches!(operation, ReplayOperation::Tick(_))));
+
+    let replayed = BlockObject::new_from_replay(&log, &rules).unwrap();
+    assert_eq!(replayed.chunks.len(), block_object.chunks.len());
+    for (chunk, replayed_chunk) in block_object.chunks.iter().zip(replayed.chunks.iter()) {
+        assert_eq!(chunk.pos, replayed_chunk.pos);
+        assert_eq!(chunk.node_id_bits, replayed_chunk.node_id_bits);
+    }
+}
+
+#[test]
+fn replay_reproduces_tick_for() {
+    let rules = load_rules();
+    let hull = rules.get_block_name_index("Hull");
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    block_object.seed = 7;
+    block_object.start_recording();
+    for x in 0..3 {
+        block_object.place_block(ivec3(x, 0, 0), hull);
+    }
+    block_object.tick_for(Duration::from_millis(1), &rules);
+    block_object.place_block(ivec3(0, 1, 0), hull);
+    while !block_object.get_backlog().is_empty() {
+        block_object.tick_for(Duration::from_millis(5), &rules);
+    }
+    let log = block_object.stop_recording().unwrap();
+
+    let replayed = BlockObject::new_from_replay(&log, &rules).unwrap();
+    assert_eq!(replayed.chunks.len(), block_object.chunks.len());
+    for (chunk, replayed_chunk) in block_object.chunks.iter().zip(replayed.chunks.iter()) {
+        assert_eq!(chunk.pos, replayed_chunk.pos);
+        assert_eq!(chunk.node_id_bits, replayed_chunk.node_id_bits);
+    }
+}
+
+#[test]
+fn edit_order_does_not_change_the_hull() {
+    let rules = load_rules();
+    let hull = rules.get_block_name_index("Hull");
+
+    // Over two chunks, so the chunks are also created in a different order.
+    let mut poses = vec![];
+    for x in 14..18 {
+        for y in 0..3 {
+            for z in 0..2 {
+                poses.push(ivec3(x, y, z));
+            }
+        }
+    }
+    let mut shuffled = poses.to_owned();
+    fastrand::Rng::with_seed(3).shuffle(&mut shuffled);
+
+    let mut block_objects = vec![];
+    for poses in [
+        poses.to_owned(),
+        poses.into_iter().rev().collect(),
+        shuffled,
+    ] {
+        let mut block_object =
+            BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+        block_object.seed = 42;
+        for pos in poses {
+            block_object.place_block(pos, hull);
+        }
+        let (ticks_left, _) = block_object.tick(MAX_TICKS, &rules);
+        assert!(ticks_left > 0);
+        block_objects.push(block_object);
+    }
+
+    let first = &block_objects[0];
+    for block_object in block_objects[1..].iter() {
+        assert_eq!(block_object.chunks.len(), first.chunks.len());
+        for chunk in first.chunks.iter() {
+            let other_chunk = block_object
+                .chunks
+                .iter()
+                .find(|c| c.pos == chunk.pos)
+                .unwrap();
+            assert_eq!(other_chunk.node_id_bits, chunk.node_id_bits);
+        }
+    }
+}
+
+#[test]
+fn scheduler_prefers_builder_objects() {
+    let rules = load_rules();
+    let hull = rules.get_block_name_index("Hull");
+
+    let mut region = Region::new(IVec3::ZERO);
+    for (pos, builder_active) in [(vec3(100.0, 0.0, 0.0), false), (Vec3::ZERO, true)] {
+        let mut block_object = BlockObject::new(
+            Mat4::from_translation(pos),
+            CHUNK_SIZE,
+            rules.block_names.len(),
+        );
+        block_object.builder_active = builder_active;
+        for x in 0..3 {
+            block_object.place_block(ivec3(x, 0, 0), hull);
+        }
+        region.loaded_objects.push(block_object);
+    }
+    let mut regions = vec![region];
+
+    let mut scheduler = TickScheduler::new(Duration::from_millis(10));
+    scheduler.tick(&mut regions, 512.0, Vec3::ZERO, &rules);
+    assert_eq!(scheduler.stats.len(), 2);
+    assert_eq!(scheduler.stats[0].object_index, 1);
+    assert!(scheduler.stats[0].priority > scheduler.stats[1].priority);
+
+    let mut frames = 0;
+    while scheduler.get_total_backlog() != 0 {
+        scheduler.tick(&mut regions, 512.0, Vec3::ZERO, &rules);
+        frames += 1;
+        assert!(frames < 10_000, "Scheduler did not finish the objects");
+    }
+    assert!(regions[0]
+        .loaded_objects
+        .iter()
+        .all(|object| object.get_backlog().is_empty()));
+}
+
+#[test]
+fn undo_and_redo_edit_groups() {
+    let rules = load_rules();
+    let hull = rules.get_block_name_index("Hull");
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    block_object.place_block(ivec3(0, 0, 0), hull);
+    block_object.tick(MAX_TICKS, &rules);
+
+    block_object.history.begin_group();
+    for x in 1..4 {
+        block_object.place_block(ivec3(x, 0, 0), hull);
+    }
+    block_object.history.end_group();
+    block_object.tick(MAX_TICKS, &rules);
+
+    assert!(block_object.undo());
+    assert!(!block_object.undo());
+    block_object.tick(MAX_TICKS, &rules);
+    for x in 1..4 {
+        assert_ne!(
+            block_object.get_block_name_from_world_block_pos(ivec3(x, 0, 0)),
+            hull
+        );
+    }
+
+    assert!(block_object.redo());
+    assert!(!block_object.redo());
+    for x in 0..4 {
+        assert_eq!(
+            block_object.get_block_name_from_world_block_pos(ivec3(x, 0, 0)),
+            hull
+        );
+    }
+}
+
+#[test]
+fn area_tools_place_as_one_edit() {
+    let rules = load_rules();
+    let hull = rules.get_block_name_index("Hull");
+    let stone = rules.get_block_name_index("Stone");
+
+    assert_eq!(get_box(ivec3(2, 2, 2), ivec3(0, 0, 0)).len(), 27);
+    assert_eq!(get_hollow_box(ivec3(0, 0, 0), ivec3(2, 2, 2)).len(), 26);
+    let line = get_line(ivec3(0, 0, 0), ivec3(5, 2, -3));
+    assert_eq!(line.len(), 6);
+    assert!(line
+        .windows(2)
+        .all(|pair| (pair[1] - pair[0]).abs().max_element() == 1));
+    let mirror = Mirror::new(0, 0);
+    assert_eq!(mirror.apply(&[ivec3(0, 1, 0), ivec3(2, 0, 0)]).len(), 3);
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    block_object.history.begin_group();
+    block_object.place_blocks(&get_hollow_box(ivec3(0, 0, 0), ivec3(3, 3, 3)), hull);
+    block_object.history.end_group();
+
+    let inside = block_object.get_flood_fill(ivec3(1, 1, 1), 100).unwrap();
+    assert_eq!(inside.len(), 8);
+    assert!(block_object.get_flood_fill(ivec3(-1, 0, 0), 100).is_err());
+
+    block_object.history.begin_group();
+    block_object.place_blocks(&inside, stone);
+    block_object.history.end_group();
+    block_object.tick(MAX_TICKS, &rules);
+
+    assert!(block_object.undo());
+    assert_ne!(
+        block_object.get_block_name_from_world_block_pos(ivec3(1, 1, 1)),
+        stone
+    );
+    assert!(block_object.undo());
+    assert_ne!(
+        block_object.get_block_name_from_world_block_pos(ivec3(0, 0, 0)),
+        hull
+    );
+}
+
+#[test]
+fn rule_manifest_declares_blocks() {
+    let voxel_loader = load_voxel_loader();
+    let manifest = load_manifest();
+    let rules = Rules::new(&voxel_loader, &manifest).unwrap();
+    assert_eq!(rules.block_names, ["Empty", "Hull", "Stone"]);
+    assert_eq!(rules.solvers.len(), rules.block_names.len());
+    assert_eq!(rules.buildable_block_name_indices, [1]);
+    assert_eq!(rules.asteroid_block_name_index, Some(2));
+    assert!(AsteroidGenerator::new(&rules).is_ok());
+
+    assert!(RuleManifest::parse("(blocks: [])").is_err());
+    assert!(RuleManifest::parse(
+        r#"(blocks: [(name: "Stone", solver: Stone(marching_cubes_folder: "Stone-Marching-Cubes"))])"#
+    )
+    .is_err());
+    assert!(RuleManifest::parse(
+        r#"(blocks: [(name: "Empty", solver: Empty), (name: "Empty", solver: Stone(marching_cubes_folder: "A"))])"#
+    )
+    .is_err());
+
+    let manifest = RuleManifest::parse(
+        r#"(blocks: [(name: "Nothing", solver: Empty), (name: "Rock", solver: Stone(marching_cubes_folder: "Stone-Marching-Cubes"))])"#,
+    )
+    .unwrap();
+    let rules = Rules::new(&voxel_loader, &manifest).unwrap();
+    assert_eq!(rules.block_names, ["Nothing", "Rock"]);
+    assert!(rules.buildable_block_name_indices.is_empty());
+    assert!(AsteroidGenerator::new(&rules).is_err());
+
+    assert!(RuleManifest::parse(
+        r#"(blocks: [(name: "Empty", solver: Empty), (name: "A", solver: Custom(kind: "A"), asteroid: true), (name: "B", solver: Custom(kind: "B"), asteroid: true)])"#
+    )
+    .is_err());
+}
+
+/// Every block of the name gets the same node.
+struct PlateSolver {
+    block_name_index: u8,
+}
+
+impl SolverFunctions for PlateSolver {
+    fn block_check_reset(
+        &self,
+        block_object: &BlockObject,
+        block_index: BlockIndex,
+        chunk_index: ChunkIndex,
+        _: IVec3,
+    ) -> Vec<SolverCacheIndex> {
+        if block_object.chunks[chunk_index].block_names[block_index] == self.block_name_index {
+            vec![0]
+        } else {
+            vec![]
+        }
+    }
+
+    fn block_check(
+        &self,
+        _: &BlockObject,
+        _: BlockIndex,
+        _: ChunkIndex,
+        _: IVec3,
+        cache: Vec<SolverCacheIndex>,
+    ) -> Vec<SolverCacheIndex> {
+        cache
+    }
+
+    fn get_block(
+        &self,
+        _: &mut BlockObject,
+        _: BlockIndex,
+        _: ChunkIndex,
+        _: IVec3,
+        cache: Vec<SolverCacheIndex>,
+    ) -> (Block, Prio, usize) {
+        if cache.is_empty() {
+            return (Block::from_single_node_id(NodeID::empty()), Prio::Zero, 0);
+        }
+        (self.get_block_from_cache_index(0), Prio::Basic(0), 0)
+    }
+
+    fn get_block_from_cache_index(&self, _: usize) -> Block {
+        Block::from_single_node_id(NodeID::new(0, Rot::IDENTITY))
+    }
+}
+
+#[test]
+fn custom_solvers_can_be_registered() {
+    let voxel_loader = load_voxel_loader();
+    let manifest = RuleManifest::parse(
+        r#"(blocks: [(name: "Empty", solver: Empty), (name: "Plate", solver: Custom(kind: "Plate"))])"#,
+    )
+    .unwrap();
+
+    assert!(Rules::new(&voxel_loader, &manifest).is_err());
+
+    let mut registry = SolverRegistry::new();
+    registry.register("Plate", |_, _, block_name_index, _| {
+        Ok(Box::new(PlateSolver { block_name_index }))
+    });
+    let rules = Rules::new_with_registry(&voxel_loader, &manifest, &registry).unwrap();
+    let plate = rules.get_block_name_index("Plate");
+    assert!(rules.solvers[plate as usize].as_custom().is_some());
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    block_object.place_block(ivec3(0, 0, 0), plate);
+    let (ticks_left, _) = block_object.tick(MAX_TICKS, &rules);
+
+    assert!(ticks_left > 0);
+    assert!(block_object.contradictions.is_empty());
+}
+
+/// Never finds a block, so every block of the name is a contradiction.
+struct NeverSolver;
+
+impl SolverFunctions for NeverSolver {
+    fn block_check_reset(
+        &self,
+        _: &BlockObject,
+        _: BlockIndex,
+        _: ChunkIndex,
+        _: IVec3,
+    ) -> Vec<SolverCacheIndex> {
+        vec![]
+    }
+
+    fn block_check(
+        &self,
+        _: &BlockObject,
+        _: BlockIndex,
+        _: ChunkIndex,
+        _: IVec3,
+        cache: Vec<SolverCacheIndex>,
+    ) -> Vec<SolverCacheIndex> {
+        cache
+    }
+
+    fn get_block(
+        &self,
+        _: &mut BlockObject,
+        _: BlockIndex,
+        _: ChunkIndex,
+        _: IVec3,
+        _: Vec<SolverCacheIndex>,
+    ) -> (Block, Prio, usize) {
+        (Block::from_single_node_id(NodeID::empty()), Prio::Zero, 0)
+    }
+
+    fn get_block_from_cache_index(&self, _: usize) -> Block {
+        Block::from_single_node_id(NodeID::empty())
+    }
+}
+
+#[test]
+fn contradictions_are_reported_and_bounded() {
+    let voxel_loader = load_voxel_loader();
+    let manifest = RuleManifest::parse(
+        r#"(blocks: [
+            (name: "Empty", solver: Empty),
+            (name: "Plate", solver: Custom(kind: "Plate")),
+            (name: "Never", solver: Custom(kind: "Never")),
+        ])"#,
+    )
+    .unwrap();
+
+    let mut registry = SolverRegistry::new();
+    registry.register("Plate", |_, _, block_name_index, _| {
+        Ok(Box::new(PlateSolver { block_name_index }))
+    });
+    registry.register("Never", |_, _, _, _| Ok(Box::new(NeverSolver)));
+    let rules = Rules::new_with_registry(&voxel_loader, &manifest, &registry).unwrap();
+    let plate = rules.get_block_name_index("Plate");
+    let never = rules.get_block_name_index("Never");
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    block_object.backtracker = Backtracker::new(MAX_BACKTRACKS);
+    block_object.place_block(ivec3(1, 0, 0), plate);
+    block_object.place_block(ivec3(0, 0, 0), never);
+    let (ticks_left, _) = block_object.tick(MAX_TICKS, &rules);
+    assert!(ticks_left > 0);
+
+    let get_reqs = |pos: IVec3| -> Vec<_> {
+        get_neighbors_without_zero()
+            .into_iter()
+            .map(|offset| {
+                (
+                    offset,
+                    block_object.get_block_name_from_world_block_pos(pos + offset),
+                )
+            })
+            .collect()
+    };
+    let get_contradiction = |pos: IVec3, block_name_index, backtracked| Contradiction {
+        world_block_pos: pos,
+        block_name_index,
+        reqs: get_reqs(pos),
+        backtracked,
+    };
+
+    // The first contradiction bans the decision of the plate. Without it the never block has no
+    // decision left to undo and the plate has no block left.
+    assert_eq!(
+        block_object.contradictions,
+        [
+            get_contradiction(IVec3::ZERO, never, true),
+            get_contradiction(IVec3::ZERO, never, false),
+            get_contradiction(IVec3::X, plate, false),
+        ]
+    );
+    let banned = block_object.backtracker.get_banned();
+    assert_eq!(banned.len(), 1);
+    assert_eq!((banned[0].1, banned[0].2), (plate, 0));
+
+    // Blocks that never solve keep only the newest contradictions.
+    for x in 0..(MAX_CONTRADICTIONS as i32 * 2) {
+        block_object.place_block(ivec3(x, 5, 0), never);
+    }
+    block_object.tick(MAX_TICKS, &rules);
+    assert_eq!(block_object.contradictions.len(), MAX_CONTRADICTIONS);
+}
+
+#[test]
+fn rule_report_collects_all_problems() {
+    let voxel_loader = load_voxel_loader();
+
+    // The assets load, so only the lints that do not stop loading can show up.
+    let manifest = load_manifest();
+    let report = RuleReport::new(&voxel_loader, &manifest, &SolverRegistry::new());
+    for kind in [
+        RuleIssueKind::InvalidFolder,
+        RuleIssueKind::InvalidName,
+        RuleIssueKind::InvalidModel,
+        RuleIssueKind::MissingOctPosition,
+        RuleIssueKind::UnsupportedNodeFolderSize,
+        RuleIssueKind::SolverError,
+    ] {
+        assert_eq!(report.count(kind), 0, "{report}");
+    }
+
+    let manifest = RuleManifest::parse(
+        r#"(blocks: [
+            (name: "Empty", solver: Empty),
+            (name: "Hull", solver: Hull(
+                basic_folders: [(name: "Missing-Basic")],
+                multi_folders: [(name: "Missing-Multi")],
+            )),
+            (name: "Stone", solver: Stone(marching_cubes_folder: "Stone-Marching-Cubes")),
+            (name: "Plate", solver: Custom(kind: "Plate")),
+        ])"#,
+    )
+    .unwrap();
+    let report = RuleReport::new(&voxel_loader, &manifest, &SolverRegistry::new());
+
+    assert_eq!(report.count(RuleIssueKind::InvalidFolder), 2);
+    assert!(report
+        .issues
+        .iter()
+        .any(|issue| issue.kind == RuleIssueKind::SolverError && issue.block_name == "Plate"));
+    assert!(report
+        .to_string()
+        .ends_with(&format!("{} issues found.", report.issues.len())));
+}
+
+#[test]
+fn cached_rules_equal_built_rules() {
+    let voxel_loader = load_voxel_loader();
+    let manifest = load_manifest();
+    let dir = std::env::temp_dir().join(format!("ssb_rule_cache_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let key = get_rule_cache_key(&voxel_loader, &manifest).unwrap();
+    let built = Rules::new_cached(&voxel_loader, &manifest, &dir).unwrap();
+    assert!(get_rule_cache_path(&dir, key).exists());
+    let cached = Rules::new_cached(&voxel_loader, &manifest, &dir).unwrap();
+    let fresh = Rules::new(&voxel_loader, &manifest).unwrap();
+
+    assert_eq!(
+        cached.get_cache(key).unwrap(),
+        fresh.get_cache(key).unwrap()
+    );
+    assert_eq!(built.get_cache(key).unwrap(), fresh.get_cache(key).unwrap());
+
+    // The cached rules solve like the built ones.
+    let hull = fresh.get_block_name_index("Hull");
+    let mut solved = vec![];
+    for rules in [&fresh, &cached] {
+        let mut block_object =
+            BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+        block_object.seed = 3;
+        block_object.place_blocks(&get_box(ivec3(0, 0, 0), ivec3(3, 2, 1)), hull);
+        block_object.tick(MAX_TICKS, rules);
+        solved.push(block_object.chunks[0].node_id_bits.to_owned());
+    }
+    assert_eq!(solved[0], solved[1]);
+
+    // Another manifest needs other rules.
+    let mut other_manifest = manifest.clone();
+    other_manifest.blocks.pop();
+    assert_ne!(
+        get_rule_cache_key(&voxel_loader, &other_manifest).unwrap(),
+        key
+    );
+
+    let mut other_voxel_loader = load_voxel_loader();
+    other_voxel_loader.frame = 1;
+    assert_ne!(
+        get_rule_cache_key(&other_voxel_loader, &manifest).unwrap(),
+        key
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rotated_nodes_are_deduplicated() {
+    let mut rules = load_rules();
+    let num_nodes = rules.nodes.len();
+
+    for i in 0..num_nodes {
+        let node = rules.nodes[i];
+        let canonical_voxels = node.get_canonical_voxels();
+
+        for rot in Rot::IDENTITY.get_all_permutations() {
+            assert_eq!(node.rotate(rot).get_canonical_voxels(), canonical_voxels);
+
+            // Nodes can be symmetric, so only the index has to match.
+            let id = rules.add_node(node.rotate(rot), Rot::IDENTITY);
+            assert_eq!(id.index, i);
+        }
+    }
+    assert_eq!(rules.nodes.len(), num_nodes);
+}
+
+#[test]
+fn add_node_matches_linear_scan() {
+    let mut rules = load_rules();
+    let rots = Rot::IDENTITY.get_all_permutations();
+
+    for i in 0..rules.nodes.len() {
+        for rot in rots.iter() {
+            let node = rules.nodes[i].rotate(*rot);
+
+            let mut expected = None;
+            for (j, test_node) in rules.nodes.iter().enumerate() {
+                for test_rot in rots.iter() {
+                    if node.is_duplicate_node_id(Rot::IDENTITY, test_node, *test_rot) {
+                        expected = Some(NodeID::new(j, *test_rot));
+                    }
+                }
+            }
+
+            assert_eq!(Some(rules.add_node(node, Rot::IDENTITY)), expected);
+        }
+    }
+}
+
+#[test]
+fn equal_prios_are_picked_by_weight() {
+    let mut heavy_wins = 0;
+    for x in 0..10_000 {
+        let pos = ivec3(x, x / 7, -x);
+
+        // With equal weights the order is the one of the unweighted hash.
+        assert_eq!(
+            get_weighted_seeded_value(5, pos, 1, 1) < get_weighted_seeded_value(5, pos, 2, 1),
+            get_seeded_value(5, pos, 1) < get_seeded_value(5, pos, 2)
+        );
+
+        if get_weighted_seeded_value(5, pos, 2, 3) < get_weighted_seeded_value(5, pos, 1, 1) {
+            heavy_wins += 1;
+        }
+    }
+
+    // A weight of 3 against 1 wins 75% of the time.
+    assert!((7_000..8_000).contains(&heavy_wins), "{heavy_wins}");
+}
+
+#[test]
+fn hull_picks_equal_prios_by_model_weights() {
+    let mut voxel_loader = load_voxel_loader();
+    for scene in voxel_loader.data.scenes.iter_mut() {
+        if let SceneNode::Transform { attributes, .. } = scene {
+            let weighted_name = match attributes.get("_name").map(|name| name.as_str()) {
+                Some("Block-B-0-Base") => "Block-B-0-Base-W2",
+                Some("Block-B-10-SlopeLower") => "Block-B-10-SlopeLower-W3",
+                _ => continue,
+            };
+            attributes.insert("_name".to_owned(), weighted_name.to_owned());
+        }
+    }
+    let mut rules = load_rules_from(&voxel_loader);
+    let hull_index = rules.get_block_name_index("Hull");
+    let hull = rules.solvers[hull_index as usize].as_hull().unwrap();
+
+    // All basic blocks are rotations of the base block.
+    for i in 0..hull.basic_blocks.len() {
+        assert_eq!(hull.basic_blocks.get_weight(i), 2);
+    }
+
+    let num_basic_blocks = hull.basic_blocks.len();
+    let slopes: Vec<_> = hull
+        .multi_blocks
+        .iter()
+        .zip(hull.multi_block_weights.iter())
+        .enumerate()
+        .filter(|(_, ((_, _, prio), _))| *prio == Prio::Multi(10))
+        .map(|(i, ((_, block, _), weight))| (i + num_basic_blocks, *block, *weight))
+        .collect();
+    let heavy: Vec<_> = slopes
+        .iter()
+        .filter(|(_, _, weight)| *weight == 3)
+        .collect();
+    let light: Vec<_> = slopes
+        .iter()
+        .filter(|(_, _, weight)| *weight == 1)
+        .collect();
+    assert!(heavy.len() > 1);
+    assert!(!light.is_empty());
+
+    // Rotations keep the weight of the block they are made from.
+    let heavy_block = heavy[0].1;
+    let num_heavy_rotations = Rot::IDENTITY
+        .get_all_permutations()
+        .into_iter()
+        .map(|rot| heavy_block.rotate(rot, &mut rules))
+        .filter(|rotated_block| *rotated_block != heavy_block)
+        .filter(|rotated_block| heavy.iter().any(|(_, block, _)| block == rotated_block))
+        .count();
+    assert!(num_heavy_rotations > 0);
+
+    let hull = rules.solvers[hull_index as usize].as_hull().unwrap();
+    let (heavy_index, light_index) = (heavy[0].0, light[0].0);
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    block_object.seed = 9;
+
+    let mut heavy_wins = 0;
+    for x in 0..10_000 {
+        let pos = ivec3(x, x / 7, -x);
+        let (_, prio, index) =
+            hull.get_block(&mut block_object, 0, 0, pos, vec![light_index, heavy_index]);
+        assert_eq!(prio, Prio::Multi(10));
+
+        if index == heavy_index {
+            heavy_wins += 1;
+        }
+    }
+
+    // A weight of 3 against 1 wins 75% of the time.
+    assert!((7_000..8_000).contains(&heavy_wins), "{heavy_wins}");
+}
+
+/// Knows if a block of another name is next to its blocks.
+struct BorderSolver {
+    block_name_index: u8,
+    other_block_name_index: u8,
+}
+
+impl SolverFunctions for BorderSolver {
+    fn block_check_reset(
+        &self,
+        block_object: &BlockObject,
+        block_index: BlockIndex,
+        chunk_index: ChunkIndex,
+        world_block_pos: IVec3,
+    ) -> Vec<SolverCacheIndex> {
+        if block_object.chunks[chunk_index].block_names[block_index] != self.block_name_index {
+            return vec![];
+        }
+
+        let touches_other = [IVec3::X, IVec3::NEG_X].iter().any(|offset| {
+            block_object.get_block_name_from_world_block_pos(world_block_pos + *offset)
+                == self.other_block_name_index
+        });
+        vec![touches_other as usize]
+    }
+
+    fn block_check(
+        &self,
+        _: &BlockObject,
+        _: BlockIndex,
+        _: ChunkIndex,
+        _: IVec3,
+        cache: Vec<SolverCacheIndex>,
+    ) -> Vec<SolverCacheIndex> {
+        cache
+    }
+
+    fn get_block(
+        &self,
+        _: &mut BlockObject,
+        _: BlockIndex,
+        _: ChunkIndex,
+        _: IVec3,
+        cache: Vec<SolverCacheIndex>,
+    ) -> (Block, Prio, usize) {
+        if cache.is_empty() {
+            return (Block::from_single_node_id(NodeID::empty()), Prio::Zero, 0);
+        }
+        (
+            self.get_block_from_cache_index(cache[0]),
+            Prio::Basic(0),
+            cache[0],
+        )
+    }
+
+    fn get_block_from_cache_index(&self, _: usize) -> Block {
+        Block::from_single_node_id(NodeID::new(0, Rot::IDENTITY))
+    }
+}
+
+#[test]
+fn blocks_can_require_other_block_names() {
+    let voxel_loader = load_voxel_loader();
+    // Border requires Stone, which is declared after it.
+    let manifest = RuleManifest::parse(
+        r#"(blocks: [
+            (name: "Empty", solver: Empty),
+            (name: "Border", solver: Custom(kind: "Border")),
+            (name: "Stone", solver: Stone(marching_cubes_folder: "Stone-Marching-Cubes", empty_blocks: ["Border"])),
+        ])"#,
+    )
+    .unwrap();
+
+    let mut registry = SolverRegistry::new();
+    registry.register("Border", |rules, _, block_name_index, _| {
+        Ok(Box::new(BorderSolver {
+            block_name_index,
+            other_block_name_index: rules.get_block_name_index("Stone"),
+        }))
+    });
+    let rules = Rules::new_with_registry(&voxel_loader, &manifest, &registry).unwrap();
+    assert_eq!(rules.block_names, ["Empty", "Border", "Stone"]);
+    let border = rules.get_block_name_index("Border");
+    let stone = rules.get_block_name_index("Stone");
+
+    let marching_cubes = &rules.solvers[stone as usize]
+        .as_stone()
+        .unwrap()
+        .marching_cubes;
+    assert!(!marching_cubes.is_filled(border));
+    assert!(marching_cubes.is_filled(stone));
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    block_object.place_block(ivec3(0, 0, 0), border);
+    block_object.tick(MAX_TICKS, &rules);
+    assert_eq!(
+        block_object.get_cache_from_world_block_pos(ivec3(0, 0, 0), border),
+        [0]
+    );
+
+    // Placing and removing the stone resets its border neighbor.
+    block_object.place_block(ivec3(1, 0, 0), stone);
+    block_object.tick(MAX_TICKS, &rules);
+    assert_eq!(
+        block_object.get_cache_from_world_block_pos(ivec3(0, 0, 0), border),
+        [1]
+    );
+
+    block_object.place_block(ivec3(1, 0, 0), rules.get_block_name_index("Empty"));
+    block_object.tick(MAX_TICKS, &rules);
+    assert_eq!(
+        block_object.get_cache_from_world_block_pos(ivec3(0, 0, 0), border),
+        [0]
+    );
+    assert!(block_object.contradictions.is_empty());
+
+    let manifest = RuleManifest::parse(
+        r#"(blocks: [(name: "Empty", solver: Empty), (name: "Stone", solver: Stone(marching_cubes_folder: "Stone-Marching-Cubes", empty_blocks: ["Glass"]))])"#,
+    )
+    .unwrap();
+    assert!(Rules::new(&voxel_loader, &manifest).is_err());
+}
+
+#[test]
+fn req_trees_match_testing_every_block() {
+    let rules = load_rules();
+    let hull_index = rules.get_block_name_index("Hull");
+    let stone_index = rules.get_block_name_index("Stone");
+    let hull = rules.solvers[hull_index as usize].as_hull().unwrap();
+
+    let mut multi_offsets = vec![];
+    for (reqs, _, _) in hull.multi_blocks.iter() {
+        for (offset, _, _) in reqs {
+            if !multi_offsets.contains(offset) {
+                multi_offsets.push(*offset);
+            }
+        }
+    }
+
+    // Every offset is tested at most once on the way to a leaf.
+    for (tree, num_offsets) in [
+        (&hull.basic_blocks.req_tree, 26),
+        (&hull.multi_broad_req_tree, multi_offsets.len()),
+    ] {
+        let stats = tree.get_stats();
+        assert_eq!(stats.num_nodes, tree.nodes.len());
+        assert_eq!(stats.num_leafs, tree.leafs.len());
+        assert!(stats.max_depth <= num_offsets);
+        assert!(tree.leafs[0].is_empty());
+    }
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    let size = 6;
+    for x in 0..size {
+        for y in 0..size {
+            for z in 0..size {
+                let pos = ivec3(x, y, z);
+                let value = get_seeded_value(3, pos, 0);
+                if value % 5 < 3 {
+                    block_object.place_block(pos, hull_index);
+                } else if value % 5 == 3 {
+                    block_object.place_block(pos, stone_index);
+                }
+            }
+        }
+    }
+
+    for x in -1..=size {
+        for y in -1..=size {
+            for z in -1..=size {
+                let pos = ivec3(x, y, z);
+                assert_eq!(
+                    hull.get_multi_blocks_reset_with_req_tree(&block_object, pos),
+                    hull.get_multi_blocks_reset(&block_object, pos)
+                );
+                assert_eq!(
+                    hull.basic_blocks
+                        .get_possible_blocks(&block_object, pos, hull_index),
+                    hull.basic_blocks.get_possible_blocks_without_req_tree(
+                        &block_object,
+                        pos,
+                        hull_index
+                    )
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn hull_multi_blocks_can_require_other_blocks() {
+    let mut voxel_loader = load_voxel_loader();
+    for scene in voxel_loader.data.scenes.iter_mut() {
+        if let SceneNode::Transform { attributes, .. } = scene {
+            if attributes
+                .get("_name")
+                .is_some_and(|name| name == "Req-B-Full")
+            {
+                attributes.insert("_name".to_owned(), "Stone".to_owned());
+            }
+        }
+    }
+    let rules = load_rules_from(&voxel_loader);
+    let hull_index = rules.get_block_name_index("Hull");
+    let stone_index = rules.get_block_name_index("Stone");
+    let hull = rules.solvers[hull_index as usize].as_hull().unwrap();
+
+    let is_stone_req = |(_, req_blocks, req_block_names): &&MultiBlockReq| {
+        req_blocks.is_empty() && *req_block_names == [stone_index]
+    };
+    let (multi_index, (reqs, _, _)) = hull
+        .multi_blocks
+        .iter()
+        .enumerate()
+        .find(|(_, (reqs, _, _))| reqs.iter().any(|req| is_stone_req(&req)))
+        .unwrap();
+    let cache_index = multi_index + hull.basic_blocks.len();
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    block_object.place_block(IVec3::ZERO, hull_index);
+    for (offset, req_blocks, req_block_names) in reqs {
+        if let Some(block_name_index) = req_block_names.first() {
+            block_object.place_block(*offset, *block_name_index);
+        } else if req_blocks
+            .iter()
+            .any(|req_block| *req_block != Block::from_single_node_id(NodeID::empty()))
+        {
+            block_object.place_block(*offset, hull_index);
+        }
+    }
+
+    assert!(hull
+        .get_multi_blocks_reset(&block_object, IVec3::ZERO)
+        .contains(&cache_index));
+    assert!(hull
+        .get_multi_blocks_reset_with_req_tree(&block_object, IVec3::ZERO)
+        .contains(&cache_index));
+
+    // Hull where the stone is required does not fulfill the req.
+    let (stone_offset, _, _) = reqs.iter().find(is_stone_req).unwrap();
+    block_object.place_block(*stone_offset, hull_index);
+    assert!(!hull
+        .get_multi_blocks_reset(&block_object, IVec3::ZERO)
+        .contains(&cache_index));
+    assert!(!hull
+        .get_multi_blocks_reset_with_req_tree(&block_object, IVec3::ZERO)
+        .contains(&cache_index));
+}
+
+#[test]
+fn marching_cubes_cover_all_configs() {
+    let rules = load_rules();
+    let stone = rules.get_block_name_index("Stone");
+    let marching_cubes = &rules.solvers[stone as usize]
+        .as_stone()
+        .unwrap()
+        .marching_cubes;
+
+    assert!(marching_cubes.get_missing_node_reqs().is_empty());
+    assert_eq!(marching_cubes.node_reqs.len(), 256);
+    for i in 0..=u8::MAX {
+        let corners: [bool; 8] = std::array::from_fn(|j| i & (1 << j) != 0);
+        let node_req = NodeReq::from(corners);
+        let node_id = marching_cubes.get_node_id(node_req);
+
+        assert_eq!(marching_cubes.node_reqs[i as usize].0, node_req);
+        assert!(node_id.index < rules.nodes.len());
+        // Synthesized nodes always have voxels.
+        if marching_cubes.synthesized_node_reqs.contains(&node_req) {
+            assert!(rules.nodes[node_id.index]
+                .voxels
+                .iter()
+                .any(|voxel| *voxel != 0));
+        }
+    }
+}
+
+#[test]
+fn smooth_stone_follows_densities() {
+    let rules = load_rules();
+    let stone = rules.get_block_name_index("Stone");
+    let marching_cubes = &rules.solvers[stone as usize]
+        .as_stone()
+        .unwrap()
+        .marching_cubes;
+    assert!(marching_cubes.smooth);
+    assert_eq!(marching_cubes.voxel_masks.len(), 256);
+
+    let mut blocky = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    let mut smooth = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    for x in 0..8 {
+        for y in 0..8 {
+            for z in 0..8 {
+                // A slope, so the surface cuts the blocks at different heights.
+                let pos = ivec3(x, y, z);
+                let density = 0.9 - (z as f32 - x as f32 * 0.3) * 0.2;
+                smooth.set_density(pos, density);
+                if density > DENSITY_ISO_LEVEL {
+                    smooth.place_block(pos, stone);
+                    blocky.place_block(pos, stone);
+                }
+            }
+        }
+    }
+    assert_eq!(
+        smooth.get_density_from_world_block_pos(ivec3(0, 0, 0)),
+        Some(0.9)
+    );
+    assert_eq!(
+        smooth.get_density_from_world_block_pos(ivec3(100, 0, 0)),
+        None
+    );
+    assert_eq!(
+        blocky.get_density_from_world_block_pos(ivec3(0, 0, 0)),
+        None
+    );
+
+    let (ticks_left, _) = smooth.tick(MAX_TICKS, &rules);
+    assert!(ticks_left > 0);
+    assert!(smooth.contradictions.is_empty());
+    blocky.tick(MAX_TICKS, &rules);
+
+    let get_node_id_bits = |block_object: &BlockObject| {
+        let chunk = block_object.chunks.iter().find(|c| c.pos == IVec3::ZERO);
+        chunk.unwrap().node_id_bits.to_owned()
+    };
+    assert_ne!(get_node_id_bits(&smooth), get_node_id_bits(&blocky));
+}
+
+#[test]
+fn replay_reproduces_densities() {
+    let rules = load_rules();
+    let stone = rules.get_block_name_index("Stone");
+
+    let mut block_object = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    block_object.start_recording();
+    for x in 0..4 {
+        for z in 0..4 {
+            let pos = ivec3(x, 0, z);
+            block_object.set_density(pos, 0.9 - z as f32 * 0.1);
+            block_object.place_block(pos, stone);
+        }
+    }
+    block_object.tick(MAX_TICKS, &rules);
+    let log = block_object.stop_recording().unwrap();
+    assert!(log
+        .operations
+        .iter()
+        .any(|operation| matches!(operation, ReplayOperation::Density { .. })));
+
+    let replayed = BlockObject::new_from_replay(&log, &rules).unwrap();
+    assert_eq!(
+        replayed.get_density_from_world_block_pos(ivec3(0, 0, 3)),
+        block_object.get_density_from_world_block_pos(ivec3(0, 0, 3))
+    );
+    for (chunk, replayed_chunk) in block_object.chunks.iter().zip(replayed.chunks.iter()) {
+        assert_eq!(chunk.node_id_bits, replayed_chunk.node_id_bits);
+    }
+}
+
+#[test]
+fn hull_and_stone_touch_without_contradictions() {
+    let rules = load_rules();
+    let hull = rules.get_block_name_index("Hull");
+    let stone = rules.get_block_name_index("Stone");
+    let marching_cubes = &rules.solvers[stone as usize]
+        .as_stone()
+        .unwrap()
+        .marching_cubes;
+    // Stone grows into the hull instead of closing its surface in front of it.
+    assert!(marching_cubes.is_filled(hull));
+
+    let mut touching = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    let mut alone = BlockObject::new(Mat4::IDENTITY, CHUNK_SIZE, rules.block_names.len());
+    for x in 0..6 {
+        for y in 0..3 {
+            for z in 0..3 {
+                let pos = ivec3(x, y, z);
+                if x < 3 {
+                    touching.place_block(pos, hull);
+                } else {
+                    touching.place_block(pos, stone);
+                    alone.place_block(pos, stone);
+                }
+            }
+        }
+    }
+
+    let (ticks_left, _) = touching.tick(MAX_TICKS, &rules);
+    assert!(ticks_left > 0);
+    assert!(touching.contradictions.is_empty());
+    alone.tick(MAX_TICKS, &rules);
+
+    let get_node_id_bits = |block_object: &BlockObject, pos: IVec3| {
+        let chunk = block_object.chunks.iter().find(|c| c.pos == IVec3::ZERO);
+        let node_index = block_object.get_node_index_from_node_pos(pos);
+        chunk.unwrap().node_id_bits[node_index]
+    };
+    for x in 0..6 {
+        assert_ne!(get_node_id_bits(&touching, ivec3(x, 2, 2)), 0);
+    }
+
+    // The edge of the stone face at the hull uses the node of the stone inside.
+    let inside = get_node_id_bits(&alone, ivec3(8, 2, 2));
+    assert_eq!(get_node_id_bits(&touching, ivec3(6, 0, 1)), inside);
+    assert_ne!(get_node_id_bits(&alone, ivec3(6, 0, 1)), inside);
+}
+
+#[test]
+fn voxel_loader_walks_groups_layers_and_frames() {
+    let mut voxel_loader = load_voxel_loader();
+    let (models, folder_rot) = voxel_loader.get_name_folder("Hull-Base-0").unwrap();
+    assert!(!models.is_empty());
+
+    // Move all models of the folder into a group without a name, that is moved and rotated
+    // around z from frame 10 on.
+    let folder_group = voxel_loader
+        .data
+        .scenes
+        .iter()
+        .find_map(|node| match node {
+            SceneNode::Transform {
+                attributes, child, ..
+            } if attributes
+                .get("_name")
+                .is_some_and(|name| name == "Hull-Base-0") =>
+            {
+                Some(*child as usize)
+            }
+            _ => None,
+        })
+        .unwrap();
+    let transform_index = voxel_loader.data.scenes.len();
+    let SceneNode::Group { children, .. } = &mut voxel_loader.data.scenes[folder_group] else {
+        panic!("Hull-Base-0 is not a group");
+    };
+    let group_children = std::mem::replace(children, vec![transform_index as u32]);
+
+    let rot = Rot::from(Mat3::from_rotation_z(FRAC_PI_2));
+    let rot_byte: u8 = rot.into();
+    let frames = vec![
+        Frame {
+            attributes: Dict::from_iter([("_t".to_owned(), "0 0 0".to_owned())]),
+        },
+        Frame {
+            attributes: Dict::from_iter([
+                ("_f".to_owned(), "10".to_owned()),
+                ("_t".to_owned(), "8 0 4".to_owned()),
+                ("_r".to_owned(), rot_byte.to_string()),
+            ]),
+        },
+    ];
+    voxel_loader.data.scenes.push(SceneNode::Transform {
+        attributes: Dict::new(),
+        frames,
+        child: transform_index as u32 + 1,
+        layer_id: 0,
+    });
+    voxel_loader.data.scenes.push(SceneNode::Group {
+        attributes: Dict::new(),
+        children: group_children,
+    });
+
+    for frame in [0, 5] {
+        voxel_loader.frame = frame;
+        assert_eq!(
+            voxel_loader.get_name_folder("Hull-Base-0").unwrap(),
+            (models.to_owned(), folder_rot)
+        );
+    }
+
+    voxel_loader.frame = 12;
+    let (moved_models, _) = voxel_loader.get_name_folder("Hull-Base-0").unwrap();
+    assert_eq!(moved_models.len(), models.len());
+    for ((name, index, model_rot, pos), moved) in models.iter().zip(moved_models.iter()) {
+        assert_eq!(
+            *moved,
+            (
+                name.to_owned(),
+                *index,
+                rot * *model_rot,
+                ivec3(8 - pos.y, pos.x, 4 + pos.z)
+            )
+        );
+    }
+
+    // Hiding the layer of the group hides all models in it.
+    let SceneNode::Transform { layer_id, .. } = &mut voxel_loader.data.scenes[transform_index]
+    else {
+        unreachable!()
+    };
+    *layer_id = 1;
+    voxel_loader.data.layers[1]
+        .attributes
+        .insert("_hidden".to_owned(), "1".to_owned());
+    let (hidden_models, _) = voxel_loader.get_name_folder("Hull-Base-0").unwrap();
+    assert!(hidden_models.is_empty());
+}
+
+#[test]
+fn folder_in_rotated_group_has_world_rot() {
+    let mut voxel_loader = load_voxel_loader();
+    let (models, folder_rot) = voxel_loader.get_name_folder("Hull-Base-0").unwrap();
+
+    // Put the transform of the folder into a group without a name, that is rotated around z.
+    let folder_transform = voxel_loader
+        .data
+        .scenes
+        .iter()
+        .position(|node| match node {
+            SceneNode::Transform { attributes, .. } => attributes
+                .get("_name")
+                .is_some_and(|name| name == "Hull-Base-0"),
+            _ => false,
+        })
+        .unwrap() as u32;
+    let transform_index = voxel_loader.data.scenes.len() as u32;
+    let parent_children = voxel_loader
+        .data
+        .scenes
+        .iter_mut()
+        .find_map(|node| match node {
+            SceneNode::Group { children, .. } if children.contains(&folder_transform) => {
+                Some(children)
+            }
+            _ => None,
+        })
+        .unwrap();
+    for child in parent_children.iter_mut() {
+        if *child == folder_transform {
+            *child = transform_index;
+        }
+    }
+
+    let rot = Rot::from(Mat3::from_rotation_z(FRAC_PI_2));
+    let rot_byte: u8 = rot.into();
+    voxel_loader.data.scenes.push(SceneNode::Transform {
+        attributes: Dict::new(),
+        frames: vec![Frame {
+            attributes: Dict::from_iter([("_r".to_owned(), rot_byte.to_string())]),
+        }],
+        child: transform_index + 1,
+        layer_id: 0,
+    });
+    voxel_loader.data.scenes.push(SceneNode::Group {
+        attributes: Dict::new(),
+        children: vec![folder_transform],
+    });
+
+    // The models stay relative to the folder, only the rot of the folder changes.
+    assert_eq!(
+        voxel_loader.get_name_folder("Hull-Base-0").unwrap(),
+        (models, rot * folder_rot)
+    );
+    let (_, model_folder_rot) = voxel_loader.get_model_folder("Hull-Base-0").unwrap();
+    assert_eq!(model_folder_rot, rot * folder_rot);
+}
@@ -0,0 +1,178 @@
+use glam::{ivec3, BVec3, IVec3, Mat3, Vec3};
+use space_ship_builder_v8::math::all_bvec3s;
+use space_ship_builder_v8::math::rotation::Rot;
+use space_ship_builder_v8::world::data::node::{Node, NODE_SIZE};
+
+// The group only has 48 elements, so every property is checked for all of them.
+
+fn get_mat(rot: Rot) -> Mat3 {
+    rot.into()
+}
+
+fn get_test_vectors() -> Vec<IVec3> {
+    vec![
+        ivec3(1, 0, 0),
+        ivec3(0, 1, 0),
+        ivec3(0, 0, 1),
+        ivec3(1, 2, 3),
+        ivec3(-4, 5, -6),
+    ]
+}
+
+/// Every voxel is different, so any wrong position shows up.
+fn get_test_node() -> Node {
+    Node::new(std::array::from_fn(|i| i as u8 + 1))
+}
+
+#[test]
+fn all_rots_are_the_48_signed_permutations() {
+    let rots = Rot::all();
+    assert_eq!(rots.len(), 48);
+
+    let mut permutations = Rot::IDENTITY.get_all_permutations();
+    permutations.sort();
+    assert_eq!(rots, permutations);
+
+    for rot in rots.iter() {
+        let mat = get_mat(*rot);
+        assert_eq!(Rot::from(mat), *rot);
+        assert_eq!(Rot::try_from(<Rot as Into<u8>>::into(*rot)).unwrap(), *rot);
+    }
+}
+
+#[test]
+fn proper_rots_and_reflections_split_the_group() {
+    let proper = Rot::all_proper();
+    let reflections = Rot::all_reflections();
+    assert_eq!(proper.len(), 24);
+    assert_eq!(reflections.len(), 24);
+    assert!(proper.contains(&Rot::IDENTITY));
+
+    for rot in Rot::all() {
+        assert_eq!(rot.determinant() as f32, get_mat(rot).determinant());
+        assert_eq!(rot.is_proper(), proper.contains(&rot));
+        assert_eq!(rot.is_proper(), !reflections.contains(&rot));
+
+        let (_, scale) = rot.to_quat_scale();
+        assert_eq!(scale, Vec3::ONE * rot.determinant() as f32);
+    }
+
+    // Proper rotations are a subgroup, two reflections make a proper rotation.
+    for a in proper.iter() {
+        for b in proper.iter() {
+            assert!((*a * *b).is_proper());
+        }
+    }
+    for a in reflections.iter() {
+        for b in reflections.iter() {
+            assert!((*a * *b).is_proper());
+        }
+    }
+}
+
+#[test]
+fn composition_matches_matrix_multiplication() {
+    let rots = Rot::all();
+    for a in rots.iter() {
+        assert_eq!(*a * Rot::IDENTITY, *a);
+        assert_eq!(Rot::IDENTITY * *a, *a);
+
+        for b in rots.iter() {
+            let ab = *a * *b;
+            assert_eq!(get_mat(ab), get_mat(*a) * get_mat(*b));
+
+            for c in rots.iter() {
+                assert_eq!(ab * *c, *a * (*b * *c));
+            }
+        }
+    }
+}
+
+#[test]
+fn inverse_undoes_the_rot() {
+    for rot in Rot::all() {
+        let inverse = rot.inverse();
+        assert_eq!(rot * inverse, Rot::IDENTITY);
+        assert_eq!(inverse * rot, Rot::IDENTITY);
+        assert_eq!(inverse.inverse(), rot);
+        assert_eq!(get_mat(inverse), get_mat(rot).transpose());
+        assert_eq!(inverse.determinant(), rot.determinant());
+    }
+}
+
+#[test]
+fn vectors_are_transformed_like_with_the_matrix() {
+    for rot in Rot::all() {
+        let mat = get_mat(rot);
+        for v in get_test_vectors() {
+            assert_eq!((rot * v).as_vec3(), mat * v.as_vec3());
+            assert_eq!(rot.inverse() * (rot * v), v);
+        }
+
+        let (quat, scale) = rot.to_quat_scale();
+        for v in get_test_vectors() {
+            let transformed = quat * (v.as_vec3() * scale);
+            assert_eq!(transformed.round(), (rot * v).as_vec3());
+        }
+
+        // The offset moves the rotated voxel back into the node.
+        assert_eq!(rot.rot_offset(), (IVec3::ONE - rot * IVec3::ONE) / 2);
+    }
+}
+
+#[test]
+fn flip_mirrors_the_axes() {
+    for rot in Rot::all() {
+        for axis in all_bvec3s() {
+            let scale = Vec3::select(axis, -Vec3::ONE, Vec3::ONE);
+            assert_eq!(
+                get_mat(rot.flip(axis)),
+                get_mat(rot) * Mat3::from_diagonal(scale)
+            );
+        }
+        assert_eq!(rot.flip(BVec3::FALSE), rot);
+    }
+}
+
+#[test]
+fn rotated_voxels_follow_the_rot() {
+    let node = get_test_node();
+    assert_eq!(node.rotate(Rot::IDENTITY), node);
+
+    for a in Rot::all() {
+        let rotated = node.rotate(a);
+        let mut voxels = rotated.voxels;
+        voxels.sort();
+        assert_eq!(voxels, node.voxels, "{a:?} loses voxels");
+        assert_eq!(rotated.rotate(a.inverse()), node);
+
+        // Voxels turn around the center of the node.
+        let mat = get_mat(a);
+        let center = NODE_SIZE.as_vec3() * 0.5;
+        for (i, (pos, _)) in node.get_rotated_voxels(a).enumerate() {
+            let old_pos = ivec3(i as i32 % 4, (i as i32 / 4) % 4, i as i32 / 16);
+            assert_eq!(
+                pos.as_vec3() + 0.5 - center,
+                mat * (old_pos.as_vec3() + 0.5 - center)
+            );
+        }
+
+        for b in Rot::all() {
+            assert_eq!(rotated.rotate(b), node.rotate(b * a));
+        }
+    }
+}
+
+#[test]
+fn duplicate_nodes_are_found_for_every_rot() {
+    // The test node has no symmetries, so only the matching rot makes a duplicate.
+    let node = get_test_node();
+    for a in Rot::all() {
+        for b in Rot::all() {
+            let rotated = node.rotate(b);
+            for c in Rot::all() {
+                assert_eq!(node.is_duplicate_node_id(a, &rotated, c), a == c * b);
+            }
+        }
+    }
+}
@@ -0,0 +1,460 @@
+mod common;
+
+use common::{load_manifest, load_rules, load_voxel_loader, CHUNK_SIZE, MAX_TICKS};
+use glam::{ivec3, vec3, IVec3, Mat4};
+use space_ship_builder_v8::rules::Rules;
+use space_ship_builder_v8::world::asteroid::AsteroidGenerator;
+use space_ship_builder_v8::world::block_object::BlockObject;
+use space_ship_builder_v8::world::data::file_watcher::FileWatcher;
+use space_ship_builder_v8::world::region::Region;
+use space_ship_builder_v8::world::save::{
+    get_backup_path, get_region_path, load_regions, rotate_backups, save_regions, ShipSave,
+    ShipSaveV0, SAVE_MAGIC, SAVE_VERSION,
+};
+use space_ship_builder_v8::world::streaming::RegionStreamer;
+use std::fs;
+use std::time::Duration;
+
+fn get_test_ship(rules: &Rules) -> BlockObject {
+    let hull = rules.get_block_name_index("Hull");
+    let stone = rules.get_block_name_index("Stone");
+
+    let mut block_object = BlockObject::new(
+        Mat4::from_translation(vec3(1.0, 2.0, 3.0)),
+        CHUNK_SIZE,
+        rules.block_names.len(),
+    );
+    block_object.seed = 7;
+    for x in 0..4 {
+        block_object.place_block(ivec3(x, 0, 0), hull);
+    }
+    block_object.place_block(ivec3(-3, 20, 5), stone);
+    block_object
+}
+
+fn get_block_names(block_object: &BlockObject, poses: &[IVec3]) -> Vec<u8> {
+    poses
+        .iter()
+        .map(|pos| block_object.get_block_name_from_world_block_pos(*pos))
+        .collect()
+}
+
+const TEST_POSES: [IVec3; 6] = [
+    IVec3::new(0, 0, 0),
+    IVec3::new(3, 0, 0),
+    IVec3::new(4, 0, 0),
+    IVec3::new(-3, 20, 5),
+    IVec3::new(0, 1, 0),
+    IVec3::new(-100, 0, 0),
+];
+
+#[test]
+fn save_round_trips_through_bytes() {
+    let rules = load_rules();
+    let save = get_test_ship(&rules).get_save(&rules, false);
+
+    let data = save.to_bytes();
+    assert_eq!(data[0..4], SAVE_MAGIC);
+    assert_eq!(data[4..8], SAVE_VERSION.to_le_bytes());
+    assert_eq!(ShipSave::from_bytes(&data, &rules).unwrap(), save);
+}
+
+#[test]
+fn save_restores_object() {
+    let rules = load_rules();
+    let mut block_object = get_test_ship(&rules);
+    block_object.tick(MAX_TICKS, &rules);
+
+    let save = block_object.get_save(&rules, true);
+    let loaded = BlockObject::new_from_save(save.clone(), &rules).unwrap();
+
+    assert_eq!(loaded.transform, block_object.transform);
+    assert_eq!(loaded.seed, block_object.seed);
+    assert_eq!(
+        get_block_names(&loaded, &TEST_POSES),
+        get_block_names(&block_object, &TEST_POSES)
+    );
+    for chunk in block_object.chunks.iter() {
+        let loaded_chunk = loaded.chunks.iter().find(|c| c.pos == chunk.pos);
+        if let Some(loaded_chunk) = loaded_chunk {
+            assert_eq!(loaded_chunk.node_id_bits, chunk.node_id_bits);
+
+            // The restored nodes are drawn before the solver collapses them again.
+            #[cfg(feature = "render")]
+            assert!(loaded_chunk
+                .render_nodes
+                .iter()
+                .zip(chunk.render_nodes.iter())
+                .all(|(loaded_node, node)| loaded_node.0 == node.0));
+        } else {
+            assert!(chunk.node_id_bits.iter().all(|bits| *bits == 0));
+        }
+    }
+    assert_eq!(loaded.get_save(&rules, true), save);
+}
+
+#[test]
+fn save_keeps_densities() {
+    let rules = load_rules();
+    let stone = rules.get_block_name_index("Stone");
+    let mut block_object = get_test_ship(&rules);
+    for x in 0..3 {
+        block_object.place_block(ivec3(x, 20, 5), stone);
+        block_object.set_density(ivec3(x, 20, 5), 0.6 + x as f32 * 0.1);
+    }
+    block_object.set_density(ivec3(0, 21, 5), 0.4);
+    block_object.tick(MAX_TICKS, &rules);
+
+    let save = block_object.get_save(&rules, false);
+    let mut loaded = BlockObject::new_from_save(save, &rules).unwrap();
+    for pos in [
+        ivec3(0, 20, 5),
+        ivec3(2, 20, 5),
+        ivec3(0, 21, 5),
+        ivec3(0, 22, 5),
+    ] {
+        assert_eq!(
+            loaded.get_density_from_world_block_pos(pos),
+            block_object.get_density_from_world_block_pos(pos)
+        );
+    }
+
+    // The smooth stone collapses to the same nodes as before saving.
+    loaded.tick(MAX_TICKS, &rules);
+    for chunk in block_object.chunks.iter() {
+        let loaded_chunk = loaded.chunks.iter().find(|c| c.pos == chunk.pos).unwrap();
+        assert_eq!(loaded_chunk.node_id_bits, chunk.node_id_bits);
+    }
+}
+
+#[test]
+fn save_drops_node_ids_of_other_nodes() {
+    let rules = load_rules();
+    let mut block_object = get_test_ship(&rules);
+    block_object.tick(MAX_TICKS, &rules);
+
+    let mut save = block_object.get_save(&rules, true);
+    assert_eq!(save.nodes_key, rules.get_nodes_key());
+    save.nodes_key += 1;
+
+    let loaded = BlockObject::new_from_save(save, &rules).unwrap();
+    assert_eq!(
+        get_block_names(&loaded, &TEST_POSES),
+        get_block_names(&block_object, &TEST_POSES)
+    );
+    for chunk in loaded.chunks.iter() {
+        assert!(chunk.node_id_bits.iter().all(|bits| *bits == 0));
+    }
+}
+
+#[test]
+fn save_is_chunk_sparse_and_run_length_encoded() {
+    let rules = load_rules();
+    let mut block_object = get_test_ship(&rules);
+    block_object.place_block(ivec3(200, 0, 0), rules.get_block_name_index("Hull"));
+    block_object.place_block(ivec3(200, 0, 0), rules.get_block_name_index("Empty"));
+
+    let save = block_object.get_save(&rules, false);
+    assert_eq!(save.chunks.len(), 2);
+    for chunk in save.chunks.iter() {
+        let len: u32 = chunk.block_runs.iter().map(|(len, _)| *len).sum();
+        assert_eq!(len as usize, block_object.block_length);
+        assert!(chunk.block_runs.len() < 16);
+        assert!(chunk.node_id_bits.is_none());
+    }
+}
+
+#[test]
+fn save_uses_names_instead_of_indices() {
+    let rules = load_rules();
+    let mut save = get_test_ship(&rules).get_save(&rules, false);
+
+    // Unknown names are loaded as empty blocks.
+    let hull_index = save.palette.iter().position(|name| name == "Hull").unwrap();
+    save.palette[hull_index] = "Removed".to_owned();
+    let loaded = BlockObject::new_from_save(save, &rules).unwrap();
+
+    assert_eq!(
+        loaded.get_block_name_from_world_block_pos(ivec3(0, 0, 0)),
+        rules.get_block_name_index("Empty")
+    );
+    assert_eq!(
+        loaded.get_block_name_from_world_block_pos(ivec3(-3, 20, 5)),
+        rules.get_block_name_index("Stone")
+    );
+}
+
+#[test]
+fn save_migrates_v0() {
+    let rules = load_rules();
+    let block_object = get_test_ship(&rules);
+
+    let mut blocks = vec![];
+    for pos in TEST_POSES {
+        let block_name_index = block_object.get_block_name_from_world_block_pos(pos);
+        if block_name_index != 0 {
+            blocks.push((pos.into(), block_name_index));
+        }
+    }
+    let v0 = ShipSaveV0 {
+        blocks,
+        nodes_per_chunk: [CHUNK_SIZE; 3],
+    };
+    let data = bitcode::encode(&v0);
+
+    let save = ShipSave::from_bytes(&data, &rules).unwrap();
+    assert_eq!(save.transform, Mat4::IDENTITY.to_cols_array());
+    let loaded = BlockObject::new_from_save(save, &rules).unwrap();
+    assert_eq!(
+        get_block_names(&loaded, &TEST_POSES),
+        get_block_names(&block_object, &TEST_POSES)
+    );
+}
+
+#[test]
+fn save_rejects_invalid_v0() {
+    let rules = load_rules();
+    let invalid_index = ShipSaveV0 {
+        blocks: vec![([0, 0, 0], rules.block_names.len() as u8)],
+        nodes_per_chunk: [CHUNK_SIZE; 3],
+    };
+    assert!(ShipSave::from_bytes(&bitcode::encode(&invalid_index), &rules).is_err());
+
+    for nodes_per_chunk in [
+        [0; 3],
+        [-4; 3],
+        [6; 3],
+        [33; 3],
+        [CHUNK_SIZE, 16, CHUNK_SIZE],
+    ] {
+        let invalid_size = ShipSaveV0 {
+            blocks: vec![],
+            nodes_per_chunk,
+        };
+        let data = bitcode::encode(&invalid_size);
+        assert!(
+            ShipSave::from_bytes(&data, &rules).is_err(),
+            "{nodes_per_chunk:?}"
+        );
+    }
+}
+
+#[test]
+fn save_rejects_unknown_version() {
+    let rules = load_rules();
+    let mut data = get_test_ship(&rules).get_save(&rules, false).to_bytes();
+    data[4..8].copy_from_slice(&(SAVE_VERSION + 1).to_le_bytes());
+
+    assert!(ShipSave::from_bytes(&data, &rules).is_err());
+}
+
+#[test]
+fn regions_round_trip_with_backups() {
+    let rules = load_rules();
+    let dir = std::env::temp_dir().join(format!("ssb_save_test_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut regions = vec![];
+    for x in 0..2 {
+        let mut region = Region::new(ivec3(x, 0, -1));
+        region.loaded_objects.push(get_test_ship(&rules));
+        regions.push(region);
+    }
+
+    save_regions(&dir, &regions, &rules).unwrap();
+    let loaded = load_regions(&dir, &rules).unwrap();
+    assert_eq!(loaded.len(), 2);
+    for (region, loaded_region) in regions.iter().zip(loaded.iter()) {
+        assert_eq!(region.pos, loaded_region.pos);
+        assert_eq!(
+            get_block_names(&loaded_region.loaded_objects[0], &TEST_POSES),
+            get_block_names(&region.loaded_objects[0], &TEST_POSES)
+        );
+    }
+
+    // Regions that are not loaded keep their file.
+    rotate_backups(&dir, 2).unwrap();
+    regions.pop();
+    save_regions(&dir, &regions, &rules).unwrap();
+    assert!(get_region_path(&dir, ivec3(1, 0, -1)).exists());
+    assert!(get_region_path(&get_backup_path(&dir, 0), ivec3(1, 0, -1)).exists());
+    assert_eq!(load_regions(&dir, &rules).unwrap().len(), 2);
+
+    rotate_backups(&dir, 2).unwrap();
+    rotate_backups(&dir, 2).unwrap();
+    assert!(get_backup_path(&dir, 1).exists());
+    assert!(!get_backup_path(&dir, 2).exists());
+    assert!(fs::read_dir(&dir)
+        .unwrap()
+        .all(|entry| entry.unwrap().path().extension().unwrap() != "tmp"));
+
+    for path in [
+        dir.clone(),
+        get_backup_path(&dir, 0),
+        get_backup_path(&dir, 1),
+    ] {
+        fs::remove_dir_all(path).unwrap();
+    }
+}
+
+#[test]
+fn streaming_skips_broken_regions() {
+    let rules = load_rules();
+    let asteroid_generator = AsteroidGenerator::new(&rules).unwrap();
+    let dir = std::env::temp_dir().join(format!("ssb_broken_stream_test_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(get_region_path(&dir, IVec3::ZERO), [1, 2, 3]).unwrap();
+
+    let mut streamer = RegionStreamer::new(512.0, dir.clone());
+    let mut regions = vec![];
+    assert!(streamer
+        .update(
+            &mut regions,
+            vec3(1.0, 1.0, 1.0),
+            &rules,
+            &asteroid_generator,
+        )
+        .is_err());
+    assert_eq!(streamer.failed_region_poses, [IVec3::ZERO]);
+
+    // The broken region is not tried again, so the regions around it still load.
+    for _ in 0..26 {
+        streamer
+            .update(
+                &mut regions,
+                vec3(1.0, 1.0, 1.0),
+                &rules,
+                &asteroid_generator,
+            )
+            .unwrap();
+    }
+    assert_eq!(regions.len(), 26);
+    assert!(regions.iter().all(|region| region.pos != IVec3::ZERO));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn streaming_unloads_and_reloads_regions() {
+    let rules = load_rules();
+    let asteroid_generator = AsteroidGenerator::new(&rules).unwrap();
+    let dir = std::env::temp_dir().join(format!("ssb_stream_test_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut streamer = RegionStreamer::new(512.0, dir.clone());
+    streamer.load_radius = 0;
+    streamer.unload_radius = 0;
+
+    let mut regions = vec![];
+    streamer
+        .update(
+            &mut regions,
+            vec3(1.0, 1.0, 1.0),
+            &rules,
+            &asteroid_generator,
+        )
+        .unwrap();
+    assert_eq!(regions.len(), 1);
+    assert_eq!(regions[0].pos, IVec3::ZERO);
+    assert_eq!(regions[0].loaded_objects.len(), 1);
+    let start_save = regions[0].get_save(&rules);
+
+    streamer
+        .update(
+            &mut regions,
+            vec3(-600.0, 1.0, 1.0),
+            &rules,
+            &asteroid_generator,
+        )
+        .unwrap();
+    assert_eq!(regions.len(), 1);
+    assert_eq!(regions[0].pos, ivec3(-2, 0, 0));
+    assert!(get_region_path(&dir, IVec3::ZERO).exists());
+
+    streamer
+        .update(
+            &mut regions,
+            vec3(1.0, 1.0, 1.0),
+            &rules,
+            &asteroid_generator,
+        )
+        .unwrap();
+    assert_eq!(regions.len(), 1);
+    assert_eq!(regions[0].get_save(&rules), start_save);
+
+    // Generation only depends on the seed and the position.
+    let generated = streamer.generate_region(ivec3(3, -1, 2), &asteroid_generator);
+    let generated_again = streamer.generate_region(ivec3(3, -1, 2), &asteroid_generator);
+    assert_eq!(generated.get_save(&rules), generated_again.get_save(&rules));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn objects_are_solved_again_with_new_rules() {
+    let rules = load_rules();
+    let mut region = Region::new(IVec3::ZERO);
+    region.loaded_objects.push(get_test_ship(&rules));
+    region.loaded_objects[0].tick(MAX_TICKS, &rules);
+    let block_names: Vec<_> = get_block_names(&region.loaded_objects[0], &TEST_POSES)
+        .into_iter()
+        .map(|index| rules.block_names[index as usize].to_owned())
+        .collect();
+
+    // The new rules order the block names differently.
+    let voxel_loader = load_voxel_loader();
+    let mut manifest = load_manifest();
+    manifest.blocks.swap(1, 2);
+    let new_rules = Rules::new(&voxel_loader, &manifest).unwrap();
+    assert_ne!(new_rules.block_names, rules.block_names);
+
+    region.reload_rules(&rules, &new_rules).unwrap();
+    let object = &mut region.loaded_objects[0];
+    let new_block_names: Vec<_> = get_block_names(object, &TEST_POSES)
+        .into_iter()
+        .map(|index| new_rules.block_names[index as usize].to_owned())
+        .collect();
+    assert_eq!(new_block_names, block_names);
+    assert_eq!(object.seed, 7);
+
+    let (ticks_left, _) = object.tick(MAX_TICKS, &new_rules);
+    assert!(ticks_left > 0);
+    assert!(object.contradictions.is_empty());
+}
+
+#[test]
+fn reload_keeps_objects_if_a_block_name_is_removed() {
+    let rules = load_rules();
+    let mut region = Region::new(IVec3::ZERO);
+    region.loaded_objects.push(get_test_ship(&rules));
+    region.loaded_objects[0].tick(MAX_TICKS, &rules);
+    let save = region.get_save(&rules);
+
+    let voxel_loader = load_voxel_loader();
+    let mut manifest = load_manifest();
+    manifest.blocks.retain(|block| block.name != "Stone");
+    let new_rules = Rules::new(&voxel_loader, &manifest).unwrap();
+
+    assert!(region.reload_rules(&rules, &new_rules).is_err());
+    assert_eq!(region.get_save(&rules), save);
+}
+
+#[test]
+fn file_watcher_sees_changes() {
+    let path = std::env::temp_dir().join(format!("ssb_watch_test_{}.vox", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let mut watcher = FileWatcher::new(&[path.to_str().unwrap()], Duration::from_secs(60));
+    assert!(!watcher.has_changed());
+
+    fs::write(&path, [1]).unwrap();
+    assert!(watcher.has_changed());
+    assert!(!watcher.has_changed());
+
+    // Polling checks at most once per intervall.
+    assert!(!watcher.poll());
+    fs::remove_file(&path).unwrap();
+    assert!(!watcher.poll());
+    assert!(watcher.has_changed());
+}